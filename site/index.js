@@ -1,8 +1,22 @@
-import init, { log_msg } from "./hello_wasm.js";
+// Generated by `cargo xtask site`. Edit site.toml instead.
+import init, { init_logger, hydrate, mount_app, set_worker_script, Level } from "./hello_wasm.js";
 
 async function run() {
     await init();
+    set_worker_script("/worker.js");
+    init_logger(Level.Debug);
 
-    log_msg();
+    // Pages rendered ahead of time already have the app's markup.
+    if (document.getElementById("app").hasChildNodes()) {
+        hydrate("#app");
+    } else {
+        mount_app("#app");
+    }
 }
 run();
+
+if ("serviceWorker" in navigator) {
+    navigator.serviceWorker.register("/sw.js").catch((error) => {
+        console.warn("failed to register the service worker:", error);
+    });
+}
@@ -0,0 +1,12 @@
+// Generated by `cargo xtask site`. Edit site.toml instead.
+self.onmessage = async (event) => {
+    self.onmessage = null;
+    const { module, memory, threads, start } = event.data;
+    try {
+        const bindings = await import(threads ? "./hello_wasm_threads.js" : "./hello_wasm.js");
+        await bindings.default({ module_or_path: module, memory });
+        bindings[start]();
+    } catch (error) {
+        self.postMessage({ error: String(error) });
+    }
+};
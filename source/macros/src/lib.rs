@@ -0,0 +1,418 @@
+//! Procedural macros for `hello-wasm`. Use them through the main crate, which re-exports
+//! them.
+
+mod names;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::{braced, Error, Expr, Ident, LitStr, Token};
+
+/// Builds a `hello_wasm::vdom::VNode` from HTML-like markup.
+///
+/// Elements are written as in HTML, except that elements without children must close
+/// themselves (`<p/>`) unless they are void elements such as `<br>`. Text is written as
+/// string literals, and `{ expr }` inserts anything that converts into a `VNode`;
+/// `{ for iter }` inserts every node an iterator yields.
+///
+/// Attribute values are string literals or `{ expr }`, where the expression is anything
+/// that implements `Display`. An attribute with no value is set to the empty string.
+/// `key` sets the node's key, and `on<event>={handler}` adds an event listener.
+///
+/// Tags starting with an upper-case letter are components: `props={expr}` gives their
+/// props, which default to `Default::default()`, and their children go into the
+/// component's child slot.
+///
+/// ```ignore
+/// use hello_wasm::html;
+///
+/// let items = ["one", "two"];
+/// let node = html! {
+///     <ul class="list" data-count={items.len()}>
+///         { for items.iter().map(|item| html! { <li key={item}>{ *item }</li> }) }
+///     </ul>
+/// };
+/// let button = html! { <button onclick={|_| ()} disabled>"Go"</button> };
+/// ```
+///
+/// Names are checked when the macro expands. Unknown tags, attributes and events are
+/// errors, as are mismatched closing tags and children of void elements. The tests in
+/// the main crate's `tests/ui` directory show the messages.
+#[proc_macro]
+pub fn html(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    match syn::parse::<Root>(input) {
+        Ok(root) => root.into_token_stream().into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+struct Root(Node);
+
+enum Node {
+    Element(Element),
+    Component(Component),
+    Text(LitStr),
+    Expr(Expr),
+    /// `{ for iter }`
+    Iter(Expr),
+}
+
+struct Element {
+    tag: String,
+    attrs: Vec<Attr>,
+    key: Option<AttrValue>,
+    listeners: Vec<(String, Expr)>,
+    children: Vec<Node>,
+}
+
+struct Component {
+    path: syn::Path,
+    props: Option<Expr>,
+    key: Option<AttrValue>,
+    children: Vec<Node>,
+}
+
+struct Attr {
+    name: String,
+    value: Option<AttrValue>,
+}
+
+enum AttrValue {
+    Lit(LitStr),
+    Expr(Expr),
+}
+
+/// A tag or attribute name, which may contain dashes.
+struct Name {
+    text: String,
+    span: Span,
+}
+
+impl Parse for Root {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let node: Node = input.parse()?;
+        if let Node::Iter(expr) = &node {
+            return Err(Error::new_spanned(
+                expr,
+                "`for` needs a parent element to put the nodes in",
+            ));
+        }
+        if !input.is_empty() {
+            return Err(input.error("html! takes a single root node; wrap siblings in an element"));
+        }
+        Ok(Root(node))
+    }
+}
+
+impl Parse for Node {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![<]) {
+            parse_tag(input)
+        } else if input.peek(LitStr) {
+            Ok(Node::Text(input.parse()?))
+        } else if input.peek(syn::token::Brace) {
+            let content;
+            braced!(content in input);
+            if content.peek(Token![for]) {
+                content.parse::<Token![for]>()?;
+                Ok(Node::Iter(content.parse()?))
+            } else {
+                Ok(Node::Expr(content.parse()?))
+            }
+        } else {
+            Err(input.error("expected an element, a string literal or `{ expression }`"))
+        }
+    }
+}
+
+impl Parse for Name {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let first = Ident::parse_any(input)?;
+        let span = first.span();
+        let mut text = first.to_string();
+        while input.peek(Token![-]) {
+            input.parse::<Token![-]>()?;
+            let part = input.step(|cursor| match cursor.token_tree() {
+                Some((proc_macro2::TokenTree::Ident(ident), rest)) => Ok((ident.to_string(), rest)),
+                Some((proc_macro2::TokenTree::Literal(lit), rest)) => Ok((lit.to_string(), rest)),
+                _ => Err(cursor.error("expected a name after `-`")),
+            })?;
+            text.push('-');
+            text.push_str(&part);
+        }
+        Ok(Name { text, span })
+    }
+}
+
+fn is_component(input: ParseStream) -> bool {
+    let fork = input.fork();
+    match fork.parse::<syn::Path>() {
+        Ok(path) => {
+            path.segments.len() > 1
+                || path.segments[0]
+                    .ident
+                    .to_string()
+                    .starts_with(|c: char| c.is_ascii_uppercase())
+        }
+        Err(_) => false,
+    }
+}
+
+fn parse_tag(input: ParseStream) -> syn::Result<Node> {
+    input.parse::<Token![<]>()?;
+    if input.peek(Token![/]) {
+        return Err(input.error("unexpected closing tag"));
+    }
+    if is_component(input) {
+        return parse_component(input).map(Node::Component);
+    }
+
+    let name: Name = input.parse()?;
+    if !names::is_tag(&name.text) {
+        return Err(Error::new(
+            name.span,
+            format!("unknown element `<{}>`", name.text),
+        ));
+    }
+    let mut element = Element {
+        tag: name.text,
+        attrs: Vec::new(),
+        key: None,
+        listeners: Vec::new(),
+        children: Vec::new(),
+    };
+    let mut seen = Vec::new();
+    while !input.peek(Token![>]) && !input.peek(Token![/]) {
+        let attr: Name = input.parse()?;
+        if seen.contains(&attr.text) {
+            return Err(Error::new(
+                attr.span,
+                format!("duplicate attribute `{}`", attr.text),
+            ));
+        }
+        seen.push(attr.text.clone());
+        let value = parse_attr_value(input)?;
+
+        if attr.text == "key" {
+            element.key = Some(value.ok_or_else(|| Error::new(attr.span, "`key` needs a value"))?);
+        } else if let Some(event) = attr.text.strip_prefix("on") {
+            if !names::EVENTS.contains(&event) {
+                return Err(Error::new(
+                    attr.span,
+                    format!("unknown event `{event}` in `{}`", attr.text),
+                ));
+            }
+            let Some(AttrValue::Expr(handler)) = value else {
+                return Err(Error::new(
+                    attr.span,
+                    format!(
+                        "`{}` takes a handler: `{}={{|event| ...}}`",
+                        attr.text, attr.text
+                    ),
+                ));
+            };
+            element.listeners.push((event.to_owned(), handler));
+        } else if names::is_attribute(&attr.text) {
+            element.attrs.push(Attr {
+                name: attr.text,
+                value,
+            });
+        } else {
+            return Err(Error::new(
+                attr.span,
+                format!("unknown attribute `{}` on `<{}>`", attr.text, element.tag),
+            ));
+        }
+    }
+
+    if input.peek(Token![/]) {
+        input.parse::<Token![/]>()?;
+        input.parse::<Token![>]>()?;
+        return Ok(Node::Element(element));
+    }
+    input.parse::<Token![>]>()?;
+    if names::VOID_TAGS.contains(&element.tag.as_str()) {
+        return Ok(Node::Element(element));
+    }
+
+    element.children = parse_children(input, &element.tag)?;
+    let close: Name = input.parse()?;
+    if names::VOID_TAGS.contains(&close.text.as_str()) {
+        return Err(Error::new(
+            close.span,
+            format!(
+                "`<{}>` is a void element and cannot have children",
+                close.text
+            ),
+        ));
+    }
+    if close.text != element.tag {
+        return Err(Error::new(
+            close.span,
+            format!("expected `</{}>`, found `</{}>`", element.tag, close.text),
+        ));
+    }
+    input.parse::<Token![>]>()?;
+    Ok(Node::Element(element))
+}
+
+/// Parses children up to and including the `</` of the parent's closing tag.
+fn parse_children(input: ParseStream, parent: &str) -> syn::Result<Vec<Node>> {
+    let mut children = Vec::new();
+    loop {
+        if input.is_empty() {
+            return Err(input.error(format!("`<{parent}>` is never closed")));
+        }
+        if input.peek(Token![<]) && input.peek2(Token![/]) {
+            input.parse::<Token![<]>()?;
+            input.parse::<Token![/]>()?;
+            return Ok(children);
+        }
+        children.push(input.parse()?);
+    }
+}
+
+fn parse_attr_value(input: ParseStream) -> syn::Result<Option<AttrValue>> {
+    if !input.peek(Token![=]) {
+        return Ok(None);
+    }
+    input.parse::<Token![=]>()?;
+    if input.peek(LitStr) {
+        Ok(Some(AttrValue::Lit(input.parse()?)))
+    } else if input.peek(syn::token::Brace) {
+        let content;
+        braced!(content in input);
+        Ok(Some(AttrValue::Expr(content.parse()?)))
+    } else {
+        Err(input.error("expected a string literal or `{ expression }` as the value"))
+    }
+}
+
+fn parse_component(input: ParseStream) -> syn::Result<Component> {
+    let path: syn::Path = input.parse()?;
+    let mut component = Component {
+        path,
+        props: None,
+        key: None,
+        children: Vec::new(),
+    };
+    while !input.peek(Token![>]) && !input.peek(Token![/]) {
+        let attr: Name = input.parse()?;
+        let value = parse_attr_value(input)?;
+        match (attr.text.as_str(), value) {
+            ("key", Some(value)) if component.key.is_none() => component.key = Some(value),
+            ("props", Some(AttrValue::Expr(props))) if component.props.is_none() => {
+                component.props = Some(props)
+            }
+            _ => {
+                return Err(Error::new(
+                    attr.span,
+                    "components take `props={...}` and `key`, each at most once",
+                ))
+            }
+        }
+    }
+
+    if input.peek(Token![/]) {
+        input.parse::<Token![/]>()?;
+        input.parse::<Token![>]>()?;
+        return Ok(component);
+    }
+    input.parse::<Token![>]>()?;
+    let name = component.path.to_token_stream().to_string();
+    component.children = parse_children(input, &name)?;
+    let close: syn::Path = input.parse()?;
+    if close.to_token_stream().to_string() != name {
+        return Err(Error::new_spanned(
+            &close,
+            format!(
+                "expected `</{name}>`, found `</{}>`",
+                close.to_token_stream()
+            ),
+        ));
+    }
+    input.parse::<Token![>]>()?;
+    Ok(component)
+}
+
+impl ToTokens for Root {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let node = &self.0;
+        tokens.extend(quote!(::hello_wasm::vdom::VNode::from(#node)));
+    }
+}
+
+impl ToTokens for Node {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(match self {
+            Node::Element(element) => element.to_token_stream(),
+            Node::Component(component) => component.to_token_stream(),
+            Node::Text(text) => quote!(#text),
+            Node::Expr(expr) => quote!(#expr),
+            Node::Iter(_) => unreachable!("iterators are expanded by their parent"),
+        });
+    }
+}
+
+impl ToTokens for AttrValue {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(match self {
+            AttrValue::Lit(lit) => quote!(#lit),
+            AttrValue::Expr(expr) => quote!(::std::string::ToString::to_string(&(#expr))),
+        });
+    }
+}
+
+/// Calls that append `children` to the element or component being built.
+fn children(children: &[Node]) -> TokenStream {
+    children
+        .iter()
+        .map(|child| match child {
+            Node::Iter(iter) => quote! {
+                .children(
+                    ::core::iter::IntoIterator::into_iter(#iter)
+                        .map(::core::convert::Into::<::hello_wasm::vdom::VNode>::into)
+                )
+            },
+            child => quote!(.child(#child)),
+        })
+        .collect()
+}
+
+impl ToTokens for Element {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let tag = &self.tag;
+        let key = self.key.as_ref().map(|key| quote!(.key(#key)));
+        let attrs = self.attrs.iter().map(|attr| {
+            let name = &attr.name;
+            match &attr.value {
+                Some(value) => quote!(.attr(#name, #value)),
+                None => quote!(.attr(#name, "")),
+            }
+        });
+        let listeners = self
+            .listeners
+            .iter()
+            .map(|(event, handler)| quote!(.on(#event, #handler)));
+        let children = children(&self.children);
+        tokens.extend(quote! {
+            ::hello_wasm::vdom::h(#tag) #key #(#attrs)* #(#listeners)* #children
+        });
+    }
+}
+
+impl ToTokens for Component {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let path = &self.path;
+        let props = match &self.props {
+            Some(props) => quote!(#props),
+            None => quote!(::core::default::Default::default()),
+        };
+        let key = self.key.as_ref().map(|key| quote!(.key(#key)));
+        let children = children(&self.children);
+        tokens.extend(quote! {
+            ::hello_wasm::component::comp::<#path>(#props) #key #children
+        });
+    }
+}
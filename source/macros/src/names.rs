@@ -0,0 +1,70 @@
+//! The names `html!` accepts. Anything else is rejected at compile time, which catches
+//! typos that the browser would otherwise ignore without a word. Only HTML is here: the
+//! DOM patcher creates every element in the HTML namespace, where SVG elements draw
+//! nothing.
+
+#[rustfmt::skip]
+pub const TAGS: &[&str] = &[
+    "a", "abbr", "address", "area", "article", "aside", "audio", "b", "base", "bdi", "bdo",
+    "blockquote", "body", "br", "button", "canvas", "caption", "cite", "code", "col",
+    "colgroup", "data", "datalist", "dd", "del", "details", "dfn", "dialog", "div", "dl",
+    "dt", "em", "embed", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2",
+    "h3", "h4", "h5", "h6", "head", "header", "hgroup", "hr", "html", "i", "iframe", "img",
+    "input", "ins", "kbd", "label", "legend", "li", "link", "main", "map", "mark", "menu",
+    "meta", "meter", "nav", "noscript", "object", "ol", "optgroup", "option", "output", "p",
+    "picture", "pre", "progress", "q", "rp", "rt", "ruby", "s", "samp", "script", "search",
+    "section", "select", "slot", "small", "source", "span", "strong", "style", "sub",
+    "summary", "sup", "table", "tbody", "td", "template", "textarea", "tfoot", "th",
+    "thead", "time", "title", "tr", "track", "u", "ul", "var", "video", "wbr",
+];
+
+/// Elements that never have children.
+#[rustfmt::skip]
+pub const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr",
+];
+
+#[rustfmt::skip]
+pub const ATTRIBUTES: &[&str] = &[
+    // Global attributes.
+    "accesskey", "autocapitalize", "autofocus", "class", "contenteditable", "dir",
+    "draggable", "enterkeyhint", "hidden", "id", "inert", "inputmode", "is", "itemid",
+    "itemprop", "itemref", "itemscope", "itemtype", "lang", "nonce", "part", "popover",
+    "role", "slot", "spellcheck", "style", "tabindex", "title", "translate",
+    // Element attributes.
+    "accept", "accept-charset", "action", "allow", "alt", "async", "autocomplete",
+    "autoplay", "charset", "checked", "cite", "cols", "colspan", "content", "controls",
+    "coords", "crossorigin", "datetime", "decoding", "default", "defer", "dirname",
+    "disabled", "download", "enctype", "for", "form", "formaction", "headers", "height",
+    "high", "href", "hreflang", "http-equiv", "integrity", "kind", "label", "list",
+    "loading", "loop", "low", "max", "maxlength", "media", "method", "min", "minlength",
+    "multiple", "muted", "name", "novalidate", "open", "optimum", "pattern", "placeholder",
+    "playsinline", "poster", "preload", "readonly", "referrerpolicy", "rel", "required",
+    "reversed", "rows", "rowspan", "sandbox", "scope", "selected", "shape", "size", "sizes",
+    "span", "src", "srcdoc", "srclang", "srcset", "start", "step", "target", "type",
+    "usemap", "value", "width", "wrap",
+];
+
+/// Events accepted as `on<event>` attributes.
+#[rustfmt::skip]
+pub const EVENTS: &[&str] = &[
+    "abort", "animationend", "beforeinput", "blur", "cancel", "change", "click", "close",
+    "contextmenu", "copy", "cut", "dblclick", "drag", "dragend", "dragenter", "dragleave",
+    "dragover", "dragstart", "drop", "ended", "error", "focus", "focusin", "focusout",
+    "input", "invalid", "keydown", "keyup", "load", "mousedown", "mouseenter", "mouseleave",
+    "mousemove", "mouseout", "mouseover", "mouseup", "paste", "pause", "play",
+    "pointercancel", "pointerdown", "pointerenter", "pointerleave", "pointermove",
+    "pointerout", "pointerover", "pointerup", "reset", "resize", "scroll", "select",
+    "submit", "toggle", "touchcancel", "touchend", "touchmove", "touchstart",
+    "transitionend", "wheel",
+];
+
+/// Whether `name` is a known element, or a custom element, which must contain a dash.
+pub fn is_tag(name: &str) -> bool {
+    TAGS.contains(&name) || name.contains('-')
+}
+
+pub fn is_attribute(name: &str) -> bool {
+    ATTRIBUTES.contains(&name) || name.starts_with("data-") || name.starts_with("aria-")
+}
@@ -0,0 +1,159 @@
+//! The service worker that keeps the site working offline. It is built into a wasm
+//! module of its own by `cargo xtask release`, which also writes the `sw.js` that loads
+//! it and hands it the [`Config`] for the release: the files to precache, and from
+//! `site.toml`, how to answer other requests.
+//!
+//! Installing a new version caches its files under a cache of its own, and activating
+//! it deletes the caches of other versions. Like other service workers, a new version
+//! waits until no page uses the old one, unless a page posts `"skip-waiting"` to it.
+//!
+//! Which strategy a request gets is decided by [`Config::strategy`], which, like the
+//! rest of [`routing`](Config), is plain Rust that works natively.
+
+mod routing;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use web_sys::{Cache, FetchEvent, Request, RequestMode, Response, ServiceWorkerGlobalScope};
+
+pub use routing::{matches, Config, Route, Routing, Strategy, CACHE_PREFIX};
+
+thread_local! {
+    static CONFIG: RefCell<Option<Rc<Config>>> = const { RefCell::new(None) };
+}
+
+/// Sets the configuration, given as JSON. `sw.js` calls this once the module loads.
+#[wasm_bindgen]
+pub fn configure(config: &str) -> Result<(), JsValue> {
+    let config: Config = serde_json::from_str(config)
+        .map_err(|error| JsError::new(&format!("invalid service worker config: {error}")))?;
+    CONFIG.with(|current| *current.borrow_mut() = Some(Rc::new(config)));
+    Ok(())
+}
+
+fn config() -> Rc<Config> {
+    CONFIG
+        .with(|config| config.borrow().clone())
+        .expect_throw("`configure` was not called")
+}
+
+fn global() -> ServiceWorkerGlobalScope {
+    js_sys::global().unchecked_into()
+}
+
+async fn open_cache(name: &str) -> Result<Cache, JsValue> {
+    let caches = global().caches()?;
+    Ok(JsFuture::from(caches.open(name)).await?.unchecked_into())
+}
+
+/// Caches the release's files. Installing fails if any of them can't be fetched, so a
+/// version is only used once it is complete.
+#[wasm_bindgen]
+pub async fn install() -> Result<(), JsValue> {
+    let config = config();
+    let cache = open_cache(&config.cache_name()).await?;
+    let paths: js_sys::Array = config
+        .precache
+        .iter()
+        .map(|path| JsValue::from_str(path))
+        .collect();
+    JsFuture::from(cache.add_all_with_str_sequence(&paths)).await?;
+    Ok(())
+}
+
+/// Deletes other versions' caches and takes over the pages that are open.
+#[wasm_bindgen]
+pub async fn activate() -> Result<(), JsValue> {
+    let config = config();
+    let caches = global().caches()?;
+    let names: Vec<String> = js_sys::Array::from(&JsFuture::from(caches.keys()).await?)
+        .iter()
+        .filter_map(|name| name.as_string())
+        .collect();
+    for name in config.stale_caches(&names) {
+        JsFuture::from(caches.delete(name)).await?;
+    }
+    JsFuture::from(global().clients().claim()).await?;
+    Ok(())
+}
+
+/// Answers a request with the strategy its route calls for. `sw.js` only passes on
+/// same-origin `GET` requests.
+#[wasm_bindgen]
+pub async fn handle_fetch(event: FetchEvent) -> Result<Response, JsValue> {
+    let config = config();
+    let request = event.request();
+    let path = web_sys::Url::new(&request.url())?.pathname();
+    let navigation = request.mode() == RequestMode::Navigate;
+    let cache = open_cache(&config.cache_name()).await?;
+
+    let response = match config.strategy(&path, navigation) {
+        Strategy::CacheFirst => match cached(&cache, &request).await? {
+            Some(response) => Ok(response),
+            None => fetch_and_cache(&cache, &request).await,
+        },
+        Strategy::NetworkFirst => match fetch_and_cache(&cache, &request).await {
+            Ok(response) => Ok(response),
+            Err(error) => cached(&cache, &request).await?.ok_or(error),
+        },
+        Strategy::StaleWhileRevalidate => {
+            // Started before looking in the cache, so it's under way either way.
+            let refreshed = fetch_and_cache(&cache, &request);
+            match cached(&cache, &request).await? {
+                Some(response) => {
+                    // Keeps the worker alive until the cache is refreshed.
+                    let refreshed =
+                        future_to_promise(async move { refreshed.await.map(Into::into) });
+                    event.wait_until(&refreshed)?;
+                    Ok(response)
+                }
+                None => refreshed.await,
+            }
+        }
+        Strategy::NetworkOnly => fetch(&request).await,
+    };
+
+    match (response, &config.routing.offline) {
+        (Err(_), Some(offline)) if navigation => {
+            let page = JsFuture::from(cache.match_with_str(offline)).await?;
+            page.dyn_into()
+                .map_err(|_| JsError::new(&format!("{offline} is not cached")).into())
+        }
+        (response, _) => response,
+    }
+}
+
+async fn cached(cache: &Cache, request: &Request) -> Result<Option<Response>, JsValue> {
+    let response = JsFuture::from(cache.match_with_request(request)).await?;
+    Ok(response.dyn_into().ok())
+}
+
+fn fetch(request: &Request) -> impl Future<Output = Result<Response, JsValue>> {
+    let response = JsFuture::from(global().fetch_with_request(request));
+    async move { Ok(response.await?.unchecked_into()) }
+}
+
+/// Fetches `request` and caches the response if it succeeded. Only a network failure
+/// is an error, so error statuses reach the page as they are.
+fn fetch_and_cache(
+    cache: &Cache,
+    request: &Request,
+) -> impl Future<Output = Result<Response, JsValue>> {
+    let cache = cache.clone();
+    // `Request::clone` is the JS method, which copies the request rather than the handle.
+    let request = Clone::clone(request);
+    let response = fetch(&request);
+    async move {
+        let response = response.await?;
+        // Partial responses to range requests can't be cached.
+        if response.status() == 200 {
+            let _ = JsFuture::from(cache.put_with_request(&request, &response.clone()?)).await;
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+
+/// Cache names start with this, followed by the version they belong to.
+pub const CACHE_PREFIX: &str = "hello-wasm-";
+
+/// How to answer a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// From the cache, or from the network when it isn't cached, caching the response.
+    CacheFirst,
+    /// From the network, caching the response, or from the cache when offline.
+    NetworkFirst,
+    /// From the cache straight away while the cached copy is refreshed from the
+    /// network, or from the network when nothing is cached.
+    StaleWhileRevalidate,
+    /// From the network, without caching.
+    NetworkOnly,
+}
+
+/// Requests whose URL path matches `path` are answered with `strategy`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    /// A pattern where `*` matches anything up to the next `/` and `**` matches
+    /// anything at all, such as `/api/**` or `/images/*.png`.
+    pub path: String,
+    pub strategy: Strategy,
+}
+
+/// What the worker does, generated for each release.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Identifies the release. Each version has a cache of its own, and activating one
+    /// deletes the others' caches.
+    pub version: String,
+    /// The URL paths fetched into the cache when the worker installs.
+    #[serde(default)]
+    pub precache: Vec<String>,
+    #[serde(flatten)]
+    pub routing: Routing,
+}
+
+/// The choices about requests, which `site.toml` makes in its `[service_worker]`
+/// section.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Routing {
+    /// Tried in order before anything else, the first match deciding.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// For navigations to pages that no route matches.
+    #[serde(default = "default_navigation")]
+    pub navigation: Strategy,
+    /// For other requests that no route matches and that aren't precached.
+    #[serde(default = "default_fallback")]
+    pub fallback: Strategy,
+    /// A precached page to show for navigations that fail, such as when offline and the
+    /// page isn't cached. The app's router can render any route from its home page.
+    #[serde(default)]
+    pub offline: Option<String>,
+}
+
+impl Default for Routing {
+    fn default() -> Routing {
+        Routing {
+            routes: Vec::new(),
+            navigation: default_navigation(),
+            fallback: default_fallback(),
+            offline: None,
+        }
+    }
+}
+
+fn default_navigation() -> Strategy {
+    Strategy::NetworkFirst
+}
+
+fn default_fallback() -> Strategy {
+    Strategy::NetworkOnly
+}
+
+impl Config {
+    pub fn cache_name(&self) -> String {
+        format!("{CACHE_PREFIX}{}", self.version)
+    }
+
+    /// The strategy for a request to `path`, a URL path without the query. Routes come
+    /// first, then `navigation` for navigations, then cache-first for precached paths,
+    /// which only change along with the version, and finally `fallback`.
+    ///
+    /// ```
+    /// use hello_wasm_service_worker::{Config, Strategy};
+    ///
+    /// let config: Config = serde_json::from_str(
+    ///     r#"{
+    ///         "version": "1",
+    ///         "precache": ["/", "/app.js"],
+    ///         "routes": [{ "path": "/api/**", "strategy": "network-first" }]
+    ///     }"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.strategy("/api/users/1", false), Strategy::NetworkFirst);
+    /// assert_eq!(config.strategy("/app.js", false), Strategy::CacheFirst);
+    /// assert_eq!(config.strategy("/about", true), Strategy::NetworkFirst);
+    /// assert_eq!(config.strategy("/favicon.ico", false), Strategy::NetworkOnly);
+    /// ```
+    pub fn strategy(&self, path: &str, navigation: bool) -> Strategy {
+        let routing = &self.routing;
+        if let Some(route) = routing
+            .routes
+            .iter()
+            .find(|route| matches(&route.path, path))
+        {
+            route.strategy
+        } else if navigation {
+            routing.navigation
+        } else if self.precache.iter().any(|precached| precached == path) {
+            Strategy::CacheFirst
+        } else {
+            routing.fallback
+        }
+    }
+
+    /// The caches among `names` that earlier or later versions made, leaving alone
+    /// those that something else on the same origin made.
+    pub fn stale_caches<'a>(&self, names: &'a [String]) -> Vec<&'a str> {
+        let current = self.cache_name();
+        (names.iter())
+            .filter(|name| name.starts_with(CACHE_PREFIX) && **name != current)
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Whether `path` matches `pattern`, where `*` stands for anything without a `/` and
+/// `**` for anything.
+///
+/// ```
+/// use hello_wasm_service_worker::matches;
+///
+/// assert!(matches("/images/*.png", "/images/cat.png"));
+/// assert!(!matches("/images/*.png", "/images/cats/tabby.png"));
+/// assert!(matches("/api/**", "/api/users/1"));
+/// assert!(!matches("/api/**", "/apis"));
+/// ```
+pub fn matches(pattern: &str, path: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn matches_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|skip| matches_bytes(rest, &path[skip..])),
+        [b'*', rest @ ..] => {
+            let segment = (path.iter().position(|&byte| byte == b'/')).unwrap_or(path.len());
+            (0..=segment).any(|skip| matches_bytes(rest, &path[skip..]))
+        }
+        [byte, rest @ ..] => path.first() == Some(byte) && matches_bytes(rest, &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(routes: &[(&str, Strategy)]) -> Config {
+        Config {
+            version: "v1".to_owned(),
+            precache: vec!["/".to_owned(), "/app.js".to_owned()],
+            routing: Routing {
+                routes: (routes.iter())
+                    .map(|&(path, strategy)| Route {
+                        path: path.to_owned(),
+                        strategy,
+                    })
+                    .collect(),
+                ..Routing::default()
+            },
+        }
+    }
+
+    #[test]
+    fn routes_come_first_in_order() {
+        let config = config(&[
+            ("/api/live/**", Strategy::NetworkOnly),
+            ("/api/**", Strategy::StaleWhileRevalidate),
+            ("/app.js", Strategy::NetworkFirst),
+        ]);
+        assert_eq!(
+            config.strategy("/api/live/feed", false),
+            Strategy::NetworkOnly
+        );
+        assert_eq!(
+            config.strategy("/api/users", false),
+            Strategy::StaleWhileRevalidate
+        );
+        // Even navigations and precached paths.
+        assert_eq!(
+            config.strategy("/api/users", true),
+            Strategy::StaleWhileRevalidate
+        );
+        assert_eq!(config.strategy("/app.js", false), Strategy::NetworkFirst);
+    }
+
+    #[test]
+    fn falls_back_by_kind_of_request() {
+        let mut config = config(&[]);
+        assert_eq!(config.strategy("/", true), Strategy::NetworkFirst);
+        assert_eq!(config.strategy("/", false), Strategy::CacheFirst);
+        assert_eq!(config.strategy("/app.js", false), Strategy::CacheFirst);
+        assert_eq!(config.strategy("/other.js", false), Strategy::NetworkOnly);
+
+        config.routing.navigation = Strategy::CacheFirst;
+        config.routing.fallback = Strategy::StaleWhileRevalidate;
+        assert_eq!(config.strategy("/about", true), Strategy::CacheFirst);
+        assert_eq!(
+            config.strategy("/other.js", false),
+            Strategy::StaleWhileRevalidate
+        );
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("/", "/"));
+        assert!(!matches("/", "/a"));
+        assert!(matches("/*", "/"));
+        assert!(matches("/*.js", "/app.js"));
+        assert!(!matches("/*.js", "/lib/app.js"));
+        assert!(matches("/**.js", "/lib/app.js"));
+        assert!(matches("/a/*/c", "/a/b/c"));
+        assert!(!matches("/a/*/c", "/a/b/x/c"));
+        assert!(matches("/a/**/c", "/a/b/x/c"));
+        assert!(matches("/api/**", "/api/"));
+        assert!(!matches("/api/**", "/api"));
+    }
+
+    #[test]
+    fn reads_routing_from_site_toml_form() {
+        let routing: Routing = serde_json::from_str(
+            r#"{ "routes": [{ "path": "/img/*", "strategy": "cache-first" }], "offline": "/" }"#,
+        )
+        .unwrap();
+        assert_eq!(routing.routes[0].strategy, Strategy::CacheFirst);
+        assert_eq!(routing.navigation, Strategy::NetworkFirst);
+        assert_eq!(routing.fallback, Strategy::NetworkOnly);
+        assert_eq!(routing.offline.as_deref(), Some("/"));
+
+        let unknown = r#"{ "routes": [{ "path": "/", "strategy": "cache-only" }] }"#;
+        assert!(serde_json::from_str::<Routing>(unknown).is_err());
+        assert!(serde_json::from_str::<Routing>(r#"{ "route": [] }"#).is_err());
+    }
+
+    #[test]
+    fn finds_caches_of_other_versions() {
+        let config = config(&[]);
+        let names = [
+            "hello-wasm-v0",
+            "hello-wasm-v1",
+            "other-cache",
+            "hello-wasm-v2",
+        ]
+        .map(str::to_owned);
+        assert_eq!(config.cache_name(), "hello-wasm-v1");
+        assert_eq!(
+            config.stale_caches(&names),
+            ["hello-wasm-v0", "hello-wasm-v2"]
+        );
+    }
+}
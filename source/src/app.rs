@@ -0,0 +1,195 @@
+//! The application mounted by `site/index.js`, or rendered ahead of time with
+//! [`render_app`].
+
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+
+use crate::component::{comp, Component, Context};
+use crate::dom;
+use crate::html;
+use crate::router::{self, History, Route, Router};
+use crate::vdom::{render_to_string, Root, VNode};
+
+thread_local! {
+    static APP: RefCell<Option<Root>> = const { RefCell::new(None) };
+}
+
+/// Renders the application into the first element matching `selector`, replacing any
+/// application mounted before.
+#[wasm_bindgen]
+pub fn mount_app(selector: &str) -> Result<(), JsError> {
+    let parent = query(selector)?;
+    APP.with(|app| {
+        let mut app = app.borrow_mut();
+        app.take();
+        *app = Some(Root::mount(&parent, app_node(router::current_url())));
+    });
+    Ok(())
+}
+
+/// Starts the application on markup that [`render_app`] rendered into the element
+/// matching `root`, keeping the existing DOM.
+#[wasm_bindgen]
+pub fn hydrate(root: &str) -> Result<(), JsError> {
+    let parent = query(root)?;
+    APP.with(|app| {
+        let mut app = app.borrow_mut();
+        app.take();
+        *app = Some(Root::hydrate(&parent, app_node(router::current_url())));
+    });
+    Ok(())
+}
+
+/// Renders the application as it looks at `url` to HTML, for serving or saving ahead
+/// of time.
+///
+/// ```
+/// let html = hello_wasm::render_app("/users/7");
+/// assert!(html.starts_with("<main><h1>Hello WASM!</h1>"));
+/// assert!(html.contains("<p>Profile of user 7</p>"));
+/// ```
+pub fn render_app(url: &str) -> String {
+    render_to_string(&app_node(url.to_owned()))
+}
+
+fn app_node(url: String) -> VNode {
+    comp::<App>(AppProps { url }).into()
+}
+
+fn query(selector: &str) -> Result<web_sys::Element, JsError> {
+    dom::query(selector).ok_or_else(|| JsError::new(&format!("no element matches {selector:?}")))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Page {
+    Home,
+    About,
+    Users,
+    UserList,
+    User,
+    NotFound,
+}
+
+fn routes() -> Router<Page> {
+    Router::new()
+        .route(Route::new("/", Page::Home))
+        .route(Route::new("/about", Page::About))
+        .route(
+            Route::new("/users", Page::Users)
+                .child(Route::new("", Page::UserList))
+                .child(Route::new(":id", Page::User)),
+        )
+        .route(Route::new("*", Page::NotFound))
+}
+
+#[derive(Default, PartialEq)]
+pub struct AppProps {
+    /// The URL to render first. In the browser the router takes over once mounted.
+    pub url: String,
+}
+
+/// The root component: navigation and whichever page the URL points at.
+pub struct App {
+    clicks: u32,
+    url: String,
+    router: Router<Page>,
+    history: Option<History>,
+}
+
+impl Component for App {
+    type Props = AppProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        App {
+            clicks: 0,
+            url: ctx.props().url.clone(),
+            router: routes(),
+            history: None,
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> VNode {
+        let route = self.router.recognize(&self.url);
+        let page = match route.as_ref().map(|route| *route.value()) {
+            Some(Page::Home) => self.home(ctx),
+            Some(Page::About) => html! { <p>"A Rust and WebAssembly playground."</p> },
+            Some(Page::UserList) => html! {
+                <ul>
+                    { for (1..=3).map(|id| html! {
+                        <li key={id}><a href={format!("/users/{id}")}>{ format!("User {id}") }</a></li>
+                    }) }
+                </ul>
+            },
+            Some(Page::User) => {
+                let id = route
+                    .as_ref()
+                    .and_then(|route| route.param("id"))
+                    .unwrap_or_default();
+                html! { <p>{ format!("Profile of user {id}") }</p> }
+            }
+            Some(Page::Users | Page::NotFound) | None => html! { <p>"Page not found."</p> },
+        };
+
+        html! {
+            <main>
+                <h1>"Hello WASM!"</h1>
+                <nav>
+                    <a href="/">"Home"</a>" | "<a href="/about">"About"</a>" | "<a href="/users">"Users"</a>
+                </nav>
+                { page }
+            </main>
+        }
+    }
+
+    fn mounted(&mut self, ctx: &Context<Self>) {
+        let link = ctx.link().clone();
+        self.history = Some(router::start(move |url| {
+            link.update(move |app: &mut App| {
+                app.url = url;
+                true
+            })
+        }));
+        log::debug!("app mounted");
+    }
+}
+
+impl App {
+    fn home(&self, ctx: &Context<Self>) -> VNode {
+        let increment = ctx.link().callback(|app: &mut App, _| {
+            app.clicks += 1;
+            true
+        });
+        html! {
+            <Card props={CardProps { title: "Counter".to_owned() }}>
+                <p>{ format!("Clicked {} times", self.clicks) }</p>
+                <button onclick={increment}>"Click me"</button>
+            </Card>
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct CardProps {
+    pub title: String,
+}
+
+/// A titled box around whatever children it is given.
+pub struct Card;
+
+impl Component for Card {
+    type Props = CardProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Card
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> VNode {
+        html! {
+            <section class="card">
+                <h2>{ ctx.props().title.as_str() }</h2>
+                { for ctx.children().iter().cloned() }
+            </section>
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Reusable pieces of UI with their own state. A component renders a [`VNode`] tree
+//! from its props and state, and re-renders through its [`Link`] when the state
+//! changes. Components appear in other trees as [`VComponent`] nodes made with
+//! [`comp`].
+
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::vdom::{diff, DomNode, VComponent, VNode};
+
+pub trait Component: Sized + 'static {
+    type Props: PartialEq + 'static;
+
+    /// Creates the component's state. This also runs when rendering on the server, so
+    /// anything that needs the browser belongs in [`mounted`](Component::mounted).
+    fn create(ctx: &Context<Self>) -> Self;
+
+    fn view(&self, ctx: &Context<Self>) -> VNode;
+
+    /// Called when the parent renders the component with different props or children.
+    /// The new props are already in `ctx`. Returns whether to render again.
+    fn changed(&mut self, _ctx: &Context<Self>, _old_props: &Self::Props) -> bool {
+        true
+    }
+
+    /// Called once the component's DOM is in the document.
+    fn mounted(&mut self, _ctx: &Context<Self>) {}
+
+    /// Called after every render except the first.
+    fn updated(&mut self, _ctx: &Context<Self>) {}
+
+    /// Called when the component is removed.
+    fn unmounted(&mut self, _ctx: &Context<Self>) {}
+}
+
+/// What a component knows about where it is mounted.
+pub struct Context<C: Component> {
+    props: Rc<C::Props>,
+    children: Vec<VNode>,
+    link: Link<C>,
+}
+
+impl<C: Component> Context<C> {
+    pub fn props(&self) -> &C::Props {
+        &self.props
+    }
+
+    /// The nodes the parent passed in, for the component to place in its view.
+    pub fn children(&self) -> &[VNode] {
+        &self.children
+    }
+
+    pub fn link(&self) -> &Link<C> {
+        &self.link
+    }
+}
+
+type Update<C> = Box<dyn FnOnce(&mut C) -> bool>;
+
+/// A handle for changing a component's state from event handlers. It does nothing once
+/// the component is unmounted.
+pub struct Link<C: Component> {
+    shared: Weak<Shared<C>>,
+}
+
+impl<C: Component> Clone for Link<C> {
+    fn clone(&self) -> Self {
+        Link {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C: Component> Link<C> {
+    /// Changes the component's state, rendering again if `f` returns `true`. Updates
+    /// made while the component is busy, such as from inside a hook, run once it is
+    /// done.
+    pub fn update(&self, f: impl FnOnce(&mut C) -> bool + 'static) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.queue.borrow_mut().push(Box::new(f));
+            shared.run_queue();
+        }
+    }
+
+    /// Makes an event handler that updates the component, for use with
+    /// [`VElement::on`](crate::vdom::VElement::on).
+    pub fn callback(
+        &self,
+        f: impl Fn(&mut C, web_sys::Event) -> bool + 'static,
+    ) -> impl Fn(web_sys::Event) + 'static {
+        let link = self.clone();
+        let f = Rc::new(f);
+        move |event| {
+            let f = f.clone();
+            link.update(move |component| f(component, event));
+        }
+    }
+}
+
+struct State<C: Component> {
+    component: C,
+    ctx: Context<C>,
+    vnode: VNode,
+    tree: DomNode,
+}
+
+impl<C: Component> Drop for State<C> {
+    fn drop(&mut self) {
+        self.component.unmounted(&self.ctx);
+    }
+}
+
+struct Shared<C: Component> {
+    state: RefCell<State<C>>,
+    queue: RefCell<Vec<Update<C>>>,
+}
+
+impl<C: Component> Shared<C> {
+    /// Applies queued updates unless the state is already borrowed further up the
+    /// stack, in which case whoever holds it runs the queue when done.
+    fn run_queue(&self) {
+        loop {
+            let Ok(mut state) = self.state.try_borrow_mut() else {
+                return;
+            };
+            let updates = std::mem::take(&mut *self.queue.borrow_mut());
+            if updates.is_empty() {
+                return;
+            }
+            let mut render = false;
+            for update in updates {
+                render |= update(&mut state.component);
+            }
+            if render {
+                state.render();
+            }
+        }
+    }
+}
+
+impl<C: Component> State<C> {
+    fn render(&mut self) {
+        let vnode = self.component.view(&self.ctx);
+        for patch in diff(&self.vnode, &vnode) {
+            self.tree.apply(patch);
+        }
+        self.vnode = vnode;
+        self.component.updated(&self.ctx);
+    }
+}
+
+/// The type-erased side of a mounted component, used by the DOM patcher.
+pub(crate) trait Instance {
+    fn node(&self) -> web_sys::Node;
+    fn mounted(&self);
+    fn set_props(&self, node: &VComponent);
+}
+
+impl<C: Component> Instance for Shared<C> {
+    fn node(&self) -> web_sys::Node {
+        self.state.borrow().tree.dom()
+    }
+
+    fn mounted(&self) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.tree.notify_mounted();
+            let State { component, ctx, .. } = &mut *state;
+            component.mounted(ctx);
+        }
+        self.run_queue();
+    }
+
+    fn set_props(&self, node: &VComponent) {
+        {
+            let mut state = self.state.borrow_mut();
+            let props = node
+                .props
+                .clone()
+                .downcast::<C::Props>()
+                .unwrap_or_else(|_| unreachable!("props do not match the component"));
+            let old_props = std::mem::replace(&mut state.ctx.props, props);
+            state.ctx.children = node.children.clone();
+            let State { component, ctx, .. } = &mut *state;
+            if component.changed(ctx, &old_props) {
+                state.render();
+            }
+        }
+        self.run_queue();
+    }
+}
+
+fn context<C: Component>(node: &VComponent, shared: Weak<Shared<C>>) -> Context<C> {
+    let props = node
+        .props
+        .clone()
+        .downcast::<C::Props>()
+        .unwrap_or_else(|_| unreachable!("props do not match the component"));
+    Context {
+        props,
+        children: node.children.clone(),
+        link: Link { shared },
+    }
+}
+
+/// Creates the component and its DOM, or adopts `existing` DOM rendered on the server.
+fn instantiate<C: Component>(
+    node: &VComponent,
+    existing: Option<web_sys::Node>,
+) -> Rc<dyn Instance> {
+    shared::<C>(node, |vnode| match existing {
+        Some(existing) => DomNode::hydrate(vnode, existing),
+        None => DomNode::create(vnode),
+    })
+}
+
+/// Creates the component, with `tree` making the DOM for its first view.
+fn shared<C: Component>(node: &VComponent, tree: impl FnOnce(&VNode) -> DomNode) -> Rc<Shared<C>> {
+    Rc::new_cyclic(|shared: &Weak<Shared<C>>| {
+        let ctx = context(node, shared.clone());
+        let component = C::create(&ctx);
+        let vnode = component.view(&ctx);
+        let tree = tree(&vnode);
+        Shared {
+            state: RefCell::new(State {
+                component,
+                ctx,
+                vnode,
+                tree,
+            }),
+            queue: RefCell::new(Vec::new()),
+        }
+    })
+}
+
+/// Renders the component's view without mounting it, for server-side rendering. Its
+/// link does nothing and no hooks run.
+fn render<C: Component>(node: &VComponent) -> VNode {
+    let ctx = context::<C>(node, Weak::new());
+    C::create(&ctx).view(&ctx)
+}
+
+/// A node that mounts component `C` with the given props.
+pub fn comp<C: Component>(props: C::Props) -> VComponent {
+    VComponent {
+        type_id: TypeId::of::<C>(),
+        name: std::any::type_name::<C>(),
+        key: None,
+        props: Rc::new(props),
+        props_eq: |a, b| a.downcast_ref::<C::Props>() == b.downcast_ref::<C::Props>(),
+        children: Vec::new(),
+        instantiate: instantiate::<C>,
+        render: render::<C>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    #[derive(PartialEq)]
+    struct Props {
+        log: Log,
+        /// What [`Component::changed`] returns.
+        render: bool,
+    }
+
+    /// Logs its renders and hooks. Its view never changes, so it needs no DOM.
+    struct Counter {
+        count: u32,
+        log: Log,
+    }
+
+    impl Counter {
+        fn log(&self, entry: String) {
+            self.log.borrow_mut().push(entry);
+        }
+    }
+
+    impl Component for Counter {
+        type Props = Props;
+
+        fn create(ctx: &Context<Self>) -> Self {
+            Counter {
+                count: 0,
+                log: ctx.props().log.clone(),
+            }
+        }
+
+        fn view(&self, _ctx: &Context<Self>) -> VNode {
+            self.log(format!("view {}", self.count));
+            VNode::Text("counter".to_owned())
+        }
+
+        fn changed(&mut self, ctx: &Context<Self>, _old_props: &Props) -> bool {
+            self.log("changed".to_owned());
+            ctx.props().render
+        }
+
+        fn mounted(&mut self, _ctx: &Context<Self>) {
+            self.log("mounted".to_owned());
+        }
+
+        fn updated(&mut self, ctx: &Context<Self>) {
+            self.log(format!("updated {}", self.count));
+            // The state is borrowed for the render, so this has to wait for it to end.
+            if self.count == 1 {
+                ctx.link().update(|counter| {
+                    counter.count += 1;
+                    true
+                });
+            }
+        }
+
+        fn unmounted(&mut self, _ctx: &Context<Self>) {
+            self.log("unmounted".to_owned());
+        }
+    }
+
+    fn props(log: &Log, render: bool) -> Props {
+        Props {
+            log: log.clone(),
+            render,
+        }
+    }
+
+    fn mount(log: &Log) -> (Rc<Shared<Counter>>, Link<Counter>) {
+        let shared = shared::<Counter>(&comp::<Counter>(props(log, true)), |_| DomNode::detached());
+        let link = shared.state.borrow().ctx.link().clone();
+        (shared, link)
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.borrow_mut())
+    }
+
+    #[test]
+    fn renders_again_only_when_asked() {
+        let log = Log::default();
+        let (shared, link) = mount(&log);
+        assert_eq!(take(&log), ["view 0"]);
+
+        link.update(|counter| {
+            counter.count = 5;
+            false
+        });
+        assert_eq!(shared.state.borrow().component.count, 5);
+        assert!(take(&log).is_empty());
+
+        link.update(|counter| {
+            counter.count += 1;
+            true
+        });
+        assert_eq!(take(&log), ["view 6", "updated 6"]);
+    }
+
+    #[test]
+    fn runs_updates_made_while_rendering_afterwards() {
+        let log = Log::default();
+        let (shared, link) = mount(&log);
+        take(&log);
+
+        link.update(|counter| {
+            counter.count = 1;
+            true
+        });
+        assert_eq!(take(&log), ["view 1", "updated 1", "view 2", "updated 2"]);
+        assert!(shared.queue.borrow().is_empty());
+    }
+
+    #[test]
+    fn runs_hooks_as_the_component_lives() {
+        let log = Log::default();
+        let (shared, link) = mount(&log);
+        shared.mounted();
+        assert_eq!(take(&log), ["view 0", "mounted"]);
+
+        shared.set_props(&comp::<Counter>(props(&log, false)));
+        assert_eq!(take(&log), ["changed"]);
+        shared.set_props(&comp::<Counter>(props(&log, true)));
+        assert_eq!(take(&log), ["changed", "view 0", "updated 0"]);
+
+        drop(shared);
+        assert_eq!(take(&log), ["unmounted"]);
+        // The link outlives the component without doing anything.
+        link.update(|_| unreachable!("the component is gone"));
+    }
+}
@@ -0,0 +1,222 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+pub fn document() -> web_sys::Document {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .expect_throw("no document; the DOM API only works on the main thread")
+}
+
+/// Returns the first element matching a CSS selector.
+pub fn query(selector: &str) -> Option<web_sys::Element> {
+    document().query_selector(selector).ok().flatten()
+}
+
+/// The message of a thrown JS value: an `Error`'s `message`, or else its debug form.
+pub(crate) fn js_message(error: &JsValue) -> String {
+    error
+        .dyn_ref::<js_sys::Error>()
+        .map(|error| String::from(error.message()))
+        .unwrap_or_else(|| format!("{error:?}"))
+}
+
+/// Sets a property of an object made for a message. Plain property writes on a fresh
+/// object can't fail.
+pub(crate) fn set_property(object: &js_sys::Object, name: &str, value: &JsValue) {
+    let _ = js_sys::Reflect::set(object, &name.into(), value);
+}
+
+/// Starts building an element with the given tag name.
+pub fn el(tag: &str) -> ElementBuilder {
+    let element = document()
+        .create_element(tag)
+        .expect_throw("invalid tag name");
+    ElementBuilder {
+        node: Node {
+            node: element.clone().into(),
+            listeners: Vec::new(),
+            children: Vec::new(),
+        },
+        element,
+    }
+}
+
+/// Creates a text node.
+pub fn text(text: &str) -> Node {
+    Node {
+        node: document().create_text_node(text).into(),
+        listeners: Vec::new(),
+        children: Vec::new(),
+    }
+}
+
+/// An event listener, removed from its target and freed when dropped.
+pub struct Listener {
+    target: web_sys::EventTarget,
+    event: String,
+    closure: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+impl Listener {
+    pub fn new(
+        target: &web_sys::EventTarget,
+        event: &str,
+        handler: impl FnMut(web_sys::Event) + 'static,
+    ) -> Listener {
+        let closure = Closure::<dyn FnMut(web_sys::Event)>::new(handler);
+        target
+            .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+            .expect_throw("failed to add event listener");
+        Listener {
+            target: target.clone(),
+            event: event.to_owned(),
+            closure,
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            &self.event,
+            self.closure.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+/// A DOM node together with the listeners attached to it and its descendants. Dropping
+/// it frees the listeners but leaves the node where it is; use [`Node::mount`] to tie
+/// the node's presence in the document to a handle.
+pub struct Node {
+    node: web_sys::Node,
+    listeners: Vec<Listener>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    pub fn dom(&self) -> &web_sys::Node {
+        &self.node
+    }
+
+    /// Appends the node to `parent`. It stays there until the returned [`Mount`] is
+    /// dropped.
+    pub fn mount(self, parent: &web_sys::Node) -> Mount {
+        parent
+            .append_child(&self.node)
+            .expect_throw("failed to append node");
+        Mount { node: Some(self) }
+    }
+
+    /// Removes the node from the document and frees its listeners.
+    pub fn remove(self) {
+        if let Some(parent) = self.node.parent_node() {
+            let _ = parent.remove_child(&self.node);
+        }
+    }
+}
+
+impl From<ElementBuilder> for Node {
+    fn from(builder: ElementBuilder) -> Node {
+        builder.build()
+    }
+}
+
+impl From<&str> for Node {
+    fn from(value: &str) -> Node {
+        text(value)
+    }
+}
+
+impl From<String> for Node {
+    fn from(value: String) -> Node {
+        text(&value)
+    }
+}
+
+/// A node attached to the document. Dropping it removes the node and frees its
+/// listeners.
+pub struct Mount {
+    node: Option<Node>,
+}
+
+impl Mount {
+    pub fn node(&self) -> &Node {
+        self.node.as_ref().unwrap()
+    }
+
+    /// Leaves the node mounted for the rest of the page's life.
+    pub fn forget(mut self) {
+        std::mem::forget(self.node.take());
+    }
+}
+
+impl Drop for Mount {
+    fn drop(&mut self) {
+        if let Some(node) = self.node.take() {
+            node.remove();
+        }
+    }
+}
+
+/// Builds an element one attribute, child or listener at a time.
+pub struct ElementBuilder {
+    element: web_sys::Element,
+    node: Node,
+}
+
+impl ElementBuilder {
+    /// Adds a class to the element's class list.
+    pub fn class(self, name: &str) -> ElementBuilder {
+        self.element
+            .class_list()
+            .add_1(name)
+            .expect_throw("invalid class name");
+        self
+    }
+
+    pub fn attr(self, name: &str, value: &str) -> ElementBuilder {
+        self.element
+            .set_attribute(name, value)
+            .expect_throw("invalid attribute name");
+        self
+    }
+
+    /// Appends a text node.
+    pub fn text(self, value: &str) -> ElementBuilder {
+        self.child(text(value))
+    }
+
+    pub fn child(mut self, child: impl Into<Node>) -> ElementBuilder {
+        let child = child.into();
+        self.element
+            .append_child(&child.node)
+            .expect_throw("failed to append child");
+        self.node.children.push(child);
+        self
+    }
+
+    /// Calls `handler` for every `event` dispatched to the element. The handler lives
+    /// as long as the [`Node`] this builder produces.
+    pub fn on(
+        mut self,
+        event: &str,
+        handler: impl FnMut(web_sys::Event) + 'static,
+    ) -> ElementBuilder {
+        let listener = Listener::new(&self.element, event, handler);
+        self.node.listeners.push(listener);
+        self
+    }
+
+    pub fn element(&self) -> &web_sys::Element {
+        &self.element
+    }
+
+    pub fn build(self) -> Node {
+        self.node
+    }
+
+    /// Builds the element and appends it to `parent`.
+    pub fn mount(self, parent: &web_sys::Node) -> Mount {
+        self.build().mount(parent)
+    }
+}
@@ -0,0 +1,704 @@
+//! An async HTTP client. Requests are built with [`Request`] and sent through a
+//! [`Backend`]: [`FetchBackend`] uses the browser's `fetch`, and [`NativeBackend`]
+//! speaks plain HTTP/1.1 over TCP so the same code runs natively, against a server on
+//! the loopback interface for example.
+
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::dom::js_message;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The request could not be made as described, such as a malformed URL.
+    InvalidRequest(String),
+    /// The request body could not be serialised.
+    Encode(String),
+    /// The server could not be reached or the connection failed.
+    Network(String),
+    /// No complete response arrived within the request's timeout.
+    Timeout,
+    /// The server answered with a status outside 200-299.
+    Status { status: u16, body: String },
+    /// The response body was not what was expected.
+    Decode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidRequest(message) => write!(f, "invalid request: {message}"),
+            Error::Encode(message) => write!(f, "failed to encode the request body: {message}"),
+            Error::Network(message) => write!(f, "network error: {message}"),
+            Error::Timeout => write!(f, "the request timed out"),
+            Error::Status { status, .. } => write!(f, "the server responded with status {status}"),
+            Error::Decode(message) => write!(f, "failed to decode the response: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// The longest wait between retries, however many there have been.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A request under construction. Nothing is sent until [`send`](Request::send).
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// How long one attempt may take, including reading the body.
+    pub timeout: Option<Duration>,
+    /// How many times to try again after a network error, a timeout, a 5xx status or
+    /// 429 Too Many Requests.
+    pub retries: u32,
+    /// The wait before the first retry, doubled before each one after up to
+    /// [`MAX_BACKOFF`].
+    pub backoff: Duration,
+    /// An error from building the request, reported when it is sent.
+    error: Option<Error>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: impl Into<String>) -> Request {
+        Request {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+            retries: 0,
+            backoff: Duration::from_millis(200),
+            error: None,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Request {
+        Request::new(Method::Get, url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Request {
+        Request::new(Method::Post, url)
+    }
+
+    pub fn put(url: impl Into<String>) -> Request {
+        Request::new(Method::Put, url)
+    }
+
+    pub fn delete(url: impl Into<String>) -> Request {
+        Request::new(Method::Delete, url)
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Request {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Request {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Sends `value` as JSON.
+    pub fn json(mut self, value: &impl Serialize) -> Request {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.body = Some(body);
+                self.header("Content-Type", "application/json")
+            }
+            Err(error) => {
+                self.error = Some(Error::Encode(error.to_string()));
+                self
+            }
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Request {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Tries again up to `retries` times, waiting `backoff`, then twice as long, and so
+    /// on up to [`MAX_BACKOFF`]. Only use this for requests that are safe to repeat.
+    pub fn retry(mut self, retries: u32, backoff: Duration) -> Request {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sends the request with the backend for the current target. Any status counts as
+    /// success here; see [`send_json`](Request::send_json).
+    pub async fn send(self) -> Result<Response, Error> {
+        self.send_with(&DefaultBackend::default()).await
+    }
+
+    pub async fn send_with(self, backend: &impl Backend) -> Result<Response, Error> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let result = backend.send(&self).await;
+            let transient = match &result {
+                Ok(response) => response.status >= 500 || response.status == 429,
+                Err(error) => matches!(error, Error::Network(_) | Error::Timeout),
+            };
+            if !transient || attempt == self.retries {
+                return result;
+            }
+            attempt += 1;
+            log::debug!(
+                "retrying {} {} in {delay:?} ({attempt}/{})",
+                self.method.as_str(),
+                self.url,
+                self.retries
+            );
+            backend.sleep(delay).await;
+            delay = delay.saturating_mul(2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Sends the request, asking for JSON, and decodes a successful response as `T`.
+    pub async fn send_json<T: DeserializeOwned>(self) -> Result<T, Error> {
+        self.send_json_with(&DefaultBackend::default()).await
+    }
+
+    pub async fn send_json_with<T: DeserializeOwned>(
+        self,
+        backend: &impl Backend,
+    ) -> Result<T, Error> {
+        self.header("Accept", "application/json")
+            .send_with(backend)
+            .await?
+            .error_for_status()?
+            .json()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Turns a status outside 200-299 into [`Error::Status`].
+    pub fn error_for_status(self) -> Result<Response, Error> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(Error::Status {
+                status: self.status,
+                body: String::from_utf8_lossy(&self.body).into_owned(),
+            })
+        }
+    }
+
+    pub fn text(&self) -> Result<String, Error> {
+        String::from_utf8(self.body.clone()).map_err(|error| Error::Decode(error.to_string()))
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_slice(&self.body).map_err(|error| Error::Decode(error.to_string()))
+    }
+}
+
+/// Carries out single attempts of a request. Retries are handled by [`Request`].
+pub trait Backend {
+    fn send(&self, request: &Request) -> impl Future<Output = Result<Response, Error>>;
+
+    /// Waits before a retry.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub type DefaultBackend = FetchBackend;
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultBackend = NativeBackend;
+
+#[wasm_bindgen]
+extern "C" {
+    // The global functions rather than `window`'s, so requests also work in workers.
+    #[wasm_bindgen(js_name = fetch)]
+    pub(crate) fn global_fetch(request: &web_sys::Request) -> js_sys::Promise;
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> i32;
+    #[wasm_bindgen(js_name = clearTimeout)]
+    fn clear_timeout(handle: i32);
+}
+
+/// Sends requests with `fetch`, aborting them through an `AbortController` when they
+/// time out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchBackend;
+
+impl Backend for FetchBackend {
+    async fn send(&self, request: &Request) -> Result<Response, Error> {
+        let js_error = |error: JsValue| Error::Network(js_message(&error));
+
+        let init = web_sys::RequestInit::new();
+        init.set_method(request.method.as_str());
+        let headers = web_sys::Headers::new().map_err(js_error)?;
+        for (name, value) in &request.headers {
+            headers
+                .append(name, value)
+                .map_err(|_| Error::InvalidRequest(format!("invalid header {name:?}")))?;
+        }
+        init.set_headers(&headers);
+        if let Some(body) = &request.body {
+            init.set_body(&js_sys::Uint8Array::from(body.as_slice()));
+        }
+        let controller = web_sys::AbortController::new().map_err(js_error)?;
+        init.set_signal(Some(&controller.signal()));
+        let fetch_request = web_sys::Request::new_with_str_and_init(&request.url, &init)
+            .map_err(|error| Error::InvalidRequest(format!("{error:?}")))?;
+
+        // Held until the body has been read, so the timeout covers all of it.
+        let _timer = request.timeout.map(|timeout| {
+            let closure = Closure::once(move || controller.abort());
+            let handle = set_timeout(
+                closure.as_ref().unchecked_ref(),
+                timeout.as_millis().try_into().unwrap_or(i32::MAX),
+            );
+            Timer {
+                handle,
+                _closure: closure,
+            }
+        });
+        let timed_out = |error: JsValue| {
+            let aborted = error
+                .dyn_ref::<web_sys::DomException>()
+                .is_some_and(|error| error.name() == "AbortError");
+            if aborted {
+                Error::Timeout
+            } else {
+                js_error(error)
+            }
+        };
+
+        let response: web_sys::Response = JsFuture::from(global_fetch(&fetch_request))
+            .await
+            .map_err(timed_out)?
+            .unchecked_into();
+        let mut headers = Vec::new();
+        if let Ok(Some(entries)) = js_sys::try_iter(&response.headers()) {
+            for entry in entries.flatten() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                headers.push((
+                    entry.get(0).as_string().unwrap_or_default(),
+                    entry.get(1).as_string().unwrap_or_default(),
+                ));
+            }
+        }
+        let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+            .await
+            .map_err(timed_out)?;
+        Ok(Response {
+            status: response.status(),
+            headers,
+            body: js_sys::Uint8Array::new(&buffer).to_vec(),
+        })
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let millis: i32 = duration.as_millis().try_into().unwrap_or(i32::MAX);
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            set_timeout(&resolve, millis);
+        });
+        let _ = JsFuture::from(promise).await;
+    }
+}
+
+/// A pending `setTimeout`, cancelled when dropped.
+struct Timer {
+    handle: i32,
+    _closure: Closure<dyn FnMut()>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        clear_timeout(self.handle);
+    }
+}
+
+/// Sends requests over HTTP/1.1 with blocking sockets, one connection per request.
+/// Only `http://` URLs are supported. Meant for tests and tools rather than serving
+/// traffic: the futures it returns block until they are done.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeBackend;
+
+impl Backend for NativeBackend {
+    async fn send(&self, request: &Request) -> Result<Response, Error> {
+        native_send(request)
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Splits an `http://` URL into its authority, its path and the address to connect to.
+pub(crate) fn split_url(url: &str) -> Result<(&str, &str, String), Error> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| Error::InvalidRequest(format!("{url} is not an http:// URL")))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_owned()
+    } else {
+        format!("{authority}:80")
+    };
+    Ok((authority, path, address))
+}
+
+fn native_send(request: &Request) -> Result<Response, Error> {
+    let (authority, path, address) = split_url(&request.url)?;
+
+    let deadline = request.timeout.map(|timeout| Instant::now() + timeout);
+    let remaining = || match deadline {
+        Some(deadline) => deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .map(Some)
+            .ok_or(Error::Timeout),
+        None => Ok(None),
+    };
+    let network = |error: io::Error| match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout,
+        _ => Error::Network(error.to_string()),
+    };
+
+    let socket_address = address
+        .to_socket_addrs()
+        .map_err(network)?
+        .next()
+        .ok_or_else(|| Error::Network(format!("{address} did not resolve")))?;
+    let mut stream = match remaining()? {
+        Some(timeout) => TcpStream::connect_timeout(&socket_address, timeout),
+        None => TcpStream::connect(socket_address),
+    }
+    .map_err(network)?;
+
+    let body = request.body.as_deref().unwrap_or_default();
+    let mut head = format!(
+        "{} {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        request.method.as_str(),
+        body.len()
+    );
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.set_write_timeout(remaining()?).map_err(network)?;
+    stream.write_all(head.as_bytes()).map_err(network)?;
+    stream.write_all(body).map_err(network)?;
+
+    let mut raw = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        stream.set_read_timeout(remaining()?).map_err(network)?;
+        match stream.read(&mut buffer).map_err(network)? {
+            0 => break,
+            n => raw.extend_from_slice(&buffer[..n]),
+        }
+    }
+    parse_response(&raw, request.method == Method::Head)
+}
+
+pub(crate) fn parse_response(raw: &[u8], head_only: bool) -> Result<Response, Error> {
+    let malformed = |what: &str| Error::Network(format!("malformed response: {what}"));
+    let end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| malformed("no end of headers"))?;
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| malformed("headers"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| malformed("status line"))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect();
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+
+    let body = &raw[end + 4..];
+    if head_only {
+        return Ok(response);
+    }
+    response.body = if response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        decode_chunked(body).ok_or_else(|| malformed("chunked body"))?
+    } else if let Some(length) = response.header("Content-Length") {
+        let length: usize = length.parse().map_err(|_| malformed("Content-Length"))?;
+        body.get(..length)
+            .ok_or_else(|| malformed("body shorter than Content-Length"))?
+            .to_vec()
+    } else {
+        body.to_vec()
+    };
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    /// Serves one connection on the loopback interface, answering with `response`, and
+    /// returns its URL along with a handle yielding the request it read.
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            // Read the head and whatever body Content-Length announces.
+            while !request.windows(4).any(|window| window == b"\r\n\r\n")
+                || request.len() < head_and_body_len(&request)
+            {
+                let n = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    fn head_and_body_len(request: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(request);
+        let Some((head, _)) = text.split_once("\r\n\r\n") else {
+            return usize::MAX;
+        };
+        let length = (head.lines())
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |length| length.parse().unwrap());
+        head.len() + 4 + length
+    }
+
+    #[test]
+    fn sends_requests_over_tcp() {
+        let (url, server) =
+            serve_once("HTTP/1.1 201 Created\r\nX-Id: 7\r\nContent-Length: 2\r\n\r\nokextra");
+        let response = block_on(
+            Request::post(format!("{url}/items"))
+                .header("X-Test", "yes")
+                .body("hello")
+                .send_with(&NativeBackend),
+        )
+        .unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.header("x-id"), Some("7"));
+        assert_eq!(response.body, b"ok");
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /items HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nContent-Length: 5\r\n"));
+        assert!(request.contains("\r\nX-Test: yes\r\n"));
+        assert!(request.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn decodes_json_and_reports_statuses() {
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\n[1,\r\n4;x=y\r\n 2]\n\r\n0\r\n\r\n",
+        );
+        let numbers: Vec<u32> = block_on(Request::get(url).send_json_with(&NativeBackend)).unwrap();
+        assert_eq!(numbers, [1, 2]);
+        assert!(server
+            .join()
+            .unwrap()
+            .contains("\r\nAccept: application/json\r\n"));
+
+        let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope");
+        let error = block_on(Request::get(url).send_json_with::<u32>(&NativeBackend)).unwrap_err();
+        assert_eq!(
+            error,
+            Error::Status {
+                status: 404,
+                body: "nope".to_owned()
+            }
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn times_out() {
+        // Accepted by the listener's backlog, but never answered.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let request = Request::get(url).timeout(Duration::from_millis(50));
+        assert_eq!(
+            block_on(request.send_with(&NativeBackend)),
+            Err(Error::Timeout)
+        );
+    }
+
+    #[test]
+    fn rejects_other_urls_and_malformed_responses() {
+        let error = block_on(Request::get("https://example.com").send_with(&NativeBackend));
+        assert!(matches!(error, Err(Error::InvalidRequest(_))));
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n", false).is_err());
+        assert!(parse_response(b"nonsense\r\n\r\n", false).is_err());
+        assert!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nshort", false).is_err()
+        );
+        let head = parse_response(
+            b"HTTP/1.1 204 No Content\r\nContent-Length: 9\r\n\r\n",
+            true,
+        );
+        assert_eq!(head.unwrap().status, 204);
+    }
+
+    /// Answers with the given statuses in turn and records the waits between them.
+    struct Scripted {
+        statuses: RefCell<Vec<u16>>,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl Scripted {
+        fn new(statuses: &[u16]) -> Scripted {
+            Scripted {
+                statuses: RefCell::new(statuses.iter().rev().copied().collect()),
+                sleeps: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl Backend for Scripted {
+        async fn send(&self, _request: &Request) -> Result<Response, Error> {
+            match self.statuses.borrow_mut().pop() {
+                Some(0) | None => Err(Error::Network("refused".to_owned())),
+                Some(status) => Ok(Response {
+                    status,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                }),
+            }
+        }
+
+        async fn sleep(&self, duration: Duration) {
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    #[test]
+    fn retries_transient_failures_with_backoff() {
+        let backend = Scripted::new(&[503, 0, 429, 200]);
+        let request = Request::get("http://test/").retry(5, Duration::from_millis(100));
+        assert_eq!(block_on(request.send_with(&backend)).unwrap().status, 200);
+        assert_eq!(
+            *backend.sleeps.borrow(),
+            [100, 200, 400].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn does_not_retry_other_statuses() {
+        let backend = Scripted::new(&[404, 200]);
+        let request = Request::get("http://test/").retry(3, Duration::from_millis(100));
+        assert_eq!(block_on(request.send_with(&backend)).unwrap().status, 404);
+        assert!(backend.sleeps.borrow().is_empty());
+    }
+
+    #[test]
+    fn caps_the_backoff() {
+        let backend = Scripted::new(&[]);
+        let request = Request::get("http://test/").retry(40, Duration::from_secs(20));
+        assert!(matches!(
+            block_on(request.send_with(&backend)),
+            Err(Error::Network(_))
+        ));
+        let sleeps = backend.sleeps.borrow();
+        assert_eq!(sleeps.len(), 40);
+        assert_eq!(sleeps[0], Duration::from_secs(20));
+        assert!(sleeps[1..].iter().all(|&sleep| sleep == MAX_BACKOFF));
+
+        // Large enough that doubling it would overflow.
+        let backend = Scripted::new(&[]);
+        let request = Request::get("http://test/").retry(2, Duration::MAX);
+        let _ = block_on(request.send_with(&backend));
+        assert_eq!(*backend.sleeps.borrow(), [Duration::MAX, MAX_BACKOFF]);
+    }
+}
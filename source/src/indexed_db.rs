@@ -0,0 +1,1356 @@
+//! Typed object stores in IndexedDB, for data too big or too structured for
+//! [`storage`](crate::storage).
+//!
+//! A [`Schema`] lists the steps that take a database from each version to the next;
+//! opening a database runs the ones it hasn't had yet. Values are stored as their JSON
+//! form, so stores and indexes find keys by path in it. [`MemoryBackend`] stands in for
+//! the browser natively:
+//!
+//! ```
+//! use futures::executor::block_on;
+//! use futures::StreamExt;
+//! use hello_wasm::indexed_db::{
+//!     Database, Direction, IndexSchema, KeyRange, MemoryBackend, Schema, StoreSchema, Upgrade,
+//! };
+//! use serde_json::{json, Value};
+//!
+//! block_on(async {
+//!     let schema = Schema::new("notes")
+//!         .version(1, Upgrade::new().create_store(StoreSchema::new("notes").key_path("id")))
+//!         .version(
+//!             2,
+//!             Upgrade::new()
+//!                 .create_index("notes", IndexSchema::new("by_tag", "tags").multi_entry())
+//!                 .update("notes", |mut note| {
+//!                     note["tags"] = json!([]);
+//!                     Ok(note)
+//!                 }),
+//!         );
+//!     let database = Database::open_with(MemoryBackend::new(), schema).await.unwrap();
+//!
+//!     let notes = database.store::<Value>("notes");
+//!     notes.put(&json!({ "id": 1, "text": "Buy milk", "tags": ["shopping"] })).await.unwrap();
+//!     notes.put(&json!({ "id": 2, "text": "Call Sam", "tags": [] })).await.unwrap();
+//!     let shopping = notes.index("by_tag").get_all("shopping").await.unwrap();
+//!     assert_eq!(shopping[0]["text"], "Buy milk");
+//!
+//!     let mut newest_first = notes.cursor(KeyRange::all(), Direction::Prev);
+//!     let (id, note) = newest_first.next().await.unwrap().unwrap();
+//!     assert_eq!((id, note["text"].as_str()), (2.into(), Some("Call Sam")));
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::Listener;
+
+mod key;
+mod memory;
+
+pub use key::{Key, KeyRange};
+pub use memory::{MemoryBackend, MemoryConnection, MemoryTransaction};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// IndexedDB cannot be used, such as in a private window of some browsers, or it
+    /// failed in a way it doesn't say more about.
+    Unavailable(String),
+    /// The database is at a newer version than the schema it was opened with.
+    Version(String),
+    /// There is no object store or index with the name given.
+    NotFound(String),
+    /// A write would break a constraint, such as adding a key that is already there or
+    /// repeating a value in a unique index. It aborts the transaction.
+    Constraint(String),
+    /// A key or key range is invalid, or a value has no key where its store expects one.
+    InvalidKey(String),
+    /// A write was attempted in a read-only transaction.
+    ReadOnly,
+    QuotaExceeded,
+    /// The transaction has already committed or been aborted.
+    Inactive,
+    Aborted(String),
+    /// An [`Upgrade::update`] failed, which leaves the database at its old version.
+    Upgrade(String),
+    /// The value could not be serialised.
+    Encode(String),
+    /// The stored value is not what was expected.
+    Decode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unavailable(message) => write!(f, "IndexedDB is unavailable: {message}"),
+            Error::Version(message) => write!(f, "the database version is too new: {message}"),
+            Error::NotFound(message) => write!(f, "not found: {message}"),
+            Error::Constraint(message) => write!(f, "a constraint failed: {message}"),
+            Error::InvalidKey(message) => write!(f, "invalid key: {message}"),
+            Error::ReadOnly => write!(f, "the transaction is read-only"),
+            Error::QuotaExceeded => write!(f, "the storage quota is exceeded"),
+            Error::Inactive => write!(f, "the transaction has already finished"),
+            Error::Aborted(message) => write!(f, "the transaction was aborted: {message}"),
+            Error::Upgrade(message) => write!(f, "failed to upgrade the database: {message}"),
+            Error::Encode(message) => write!(f, "failed to encode the value: {message}"),
+            Error::Decode(message) => write!(f, "failed to decode the stored value: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A database's name and the upgrades that build it up, version by version.
+#[derive(Clone)]
+pub struct Schema {
+    name: String,
+    /// Upgrades in version order, each with the version it upgrades to.
+    upgrades: Vec<(u32, Upgrade)>,
+}
+
+impl Schema {
+    pub fn new(name: impl Into<String>) -> Schema {
+        Schema {
+            name: name.into(),
+            upgrades: Vec::new(),
+        }
+    }
+
+    /// Adds the steps that take the database from the previous version to `version`.
+    /// Versions start at 1 and need not be consecutive.
+    pub fn version(mut self, version: u32, upgrade: Upgrade) -> Schema {
+        assert!(version > 0, "IndexedDB versions start at 1");
+        let position = self
+            .upgrades
+            .partition_point(|(existing, _)| *existing < version);
+        match self.upgrades.get_mut(position) {
+            Some((existing, steps)) if *existing == version => steps.steps.extend(upgrade.steps),
+            _ => self.upgrades.insert(position, (version, upgrade)),
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The latest version, or 1 if no versions were added.
+    pub fn current_version(&self) -> u32 {
+        self.upgrades.last().map_or(1, |(version, _)| *version)
+    }
+
+    /// The steps for a database at version `old`, in the order to run them.
+    pub fn steps_after(&self, old: u32) -> impl Iterator<Item = &Step> {
+        (self.upgrades.iter())
+            .filter(move |(version, _)| *version > old)
+            .flat_map(|(_, upgrade)| &upgrade.steps)
+    }
+}
+
+/// The steps that upgrade a database by one version, run in the order they were added.
+#[derive(Clone, Default)]
+pub struct Upgrade {
+    steps: Vec<Step>,
+}
+
+impl Upgrade {
+    pub fn new() -> Upgrade {
+        Upgrade::default()
+    }
+
+    pub fn create_store(mut self, store: StoreSchema) -> Upgrade {
+        self.steps.push(Step::CreateStore(store));
+        self
+    }
+
+    pub fn delete_store(mut self, name: impl Into<String>) -> Upgrade {
+        self.steps.push(Step::DeleteStore(name.into()));
+        self
+    }
+
+    /// Adds an index to an existing store, indexing the records already in it.
+    pub fn create_index(mut self, store: impl Into<String>, index: IndexSchema) -> Upgrade {
+        self.steps.push(Step::CreateIndex {
+            store: store.into(),
+            index,
+        });
+        self
+    }
+
+    pub fn delete_index(mut self, store: impl Into<String>, index: impl Into<String>) -> Upgrade {
+        self.steps.push(Step::DeleteIndex {
+            store: store.into(),
+            index: index.into(),
+        });
+        self
+    }
+
+    /// Rewrites every record in `store`. An error fails the upgrade, so the database
+    /// stays as it was.
+    pub fn update(
+        mut self,
+        store: impl Into<String>,
+        update: impl Fn(Value) -> Result<Value, String> + 'static,
+    ) -> Upgrade {
+        self.steps.push(Step::Update {
+            store: store.into(),
+            update: Rc::new(update),
+        });
+        self
+    }
+}
+
+/// One change to a database's structure or contents during an upgrade.
+#[derive(Clone)]
+pub enum Step {
+    CreateStore(StoreSchema),
+    DeleteStore(String),
+    CreateIndex {
+        store: String,
+        index: IndexSchema,
+    },
+    DeleteIndex {
+        store: String,
+        index: String,
+    },
+    Update {
+        store: String,
+        update: Rc<dyn Fn(Value) -> Result<Value, String>>,
+    },
+}
+
+/// An object store to create.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreSchema {
+    pub name: String,
+    /// Where in each value its key is, or `None` if keys are given separately.
+    pub key_path: Option<String>,
+    /// Whether keys missing from values, or not given, are generated by counting up
+    /// from 1.
+    pub auto_increment: bool,
+    pub indexes: Vec<IndexSchema>,
+}
+
+impl StoreSchema {
+    pub fn new(name: impl Into<String>) -> StoreSchema {
+        StoreSchema {
+            name: name.into(),
+            key_path: None,
+            auto_increment: false,
+            indexes: Vec::new(),
+        }
+    }
+
+    pub fn key_path(mut self, path: impl Into<String>) -> StoreSchema {
+        self.key_path = Some(path.into());
+        self
+    }
+
+    pub fn auto_increment(mut self) -> StoreSchema {
+        self.auto_increment = true;
+        self
+    }
+
+    pub fn index(mut self, index: IndexSchema) -> StoreSchema {
+        self.indexes.push(index);
+        self
+    }
+}
+
+/// An index on a store, keyed by the value at a path in each record. Records without a
+/// valid key there are left out of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexSchema {
+    pub name: String,
+    pub key_path: String,
+    /// Whether two records may not have the same key in the index.
+    pub unique: bool,
+    /// Whether a record whose key path holds an array is indexed under each element,
+    /// rather than under the array.
+    pub multi_entry: bool,
+}
+
+impl IndexSchema {
+    pub fn new(name: impl Into<String>, key_path: impl Into<String>) -> IndexSchema {
+        IndexSchema {
+            name: name.into(),
+            key_path: key_path.into(),
+            unique: false,
+            multi_entry: false,
+        }
+    }
+
+    pub fn unique(mut self) -> IndexSchema {
+        self.unique = true;
+        self
+    }
+
+    pub fn multi_entry(mut self) -> IndexSchema {
+        self.multi_entry = true;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// The order a [`Cursor`] goes through keys in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Next,
+    Prev,
+}
+
+/// An open database.
+pub struct Database<B: Backend = WebBackend> {
+    connection: Rc<B::Connection>,
+}
+
+impl Database {
+    pub async fn open(schema: Schema) -> Result<Database, Error> {
+        Database::open_with(WebBackend, schema).await
+    }
+
+    /// Deletes a database, waiting for other tabs to close it first.
+    pub async fn delete(name: &str) -> Result<(), Error> {
+        WebBackend.delete(name).await
+    }
+}
+
+impl<B: Backend> Database<B> {
+    /// Opens the database, creating it or upgrading it to the schema's latest version.
+    pub async fn open_with(backend: B, schema: Schema) -> Result<Database<B>, Error> {
+        let connection = backend.open(&schema).await?;
+        Ok(Database {
+            connection: Rc::new(connection),
+        })
+    }
+
+    /// A store whose operations each run in a transaction of their own.
+    pub fn store<T: Serialize + DeserializeOwned>(&self, name: &str) -> Store<T, B> {
+        Store {
+            name: name.to_owned(),
+            scope: Scope::Database(self.connection.clone()),
+            types: PhantomData,
+        }
+    }
+
+    /// Starts a transaction over `stores`, for operations that must succeed or fail
+    /// together.
+    pub fn transaction(&self, stores: &[&str], mode: Mode) -> Result<Transaction<B>, Error> {
+        let transaction = self.connection.transaction(stores, mode)?;
+        Ok(Transaction {
+            transaction: Rc::new(transaction),
+        })
+    }
+}
+
+type TransactionOf<B> = <<B as Backend>::Connection as Connection>::Transaction;
+
+/// Operations on several stores that are applied together or not at all. A failed
+/// write aborts it.
+///
+/// The browser commits a transaction once it has no requests left, so awaiting anything
+/// but this transaction's own operations while it is open can leave it committed, with
+/// later operations failing with [`Error::Inactive`]. Dropping it without committing or
+/// aborting it commits it.
+pub struct Transaction<B: Backend = WebBackend> {
+    transaction: Rc<TransactionOf<B>>,
+}
+
+impl<B: Backend> Transaction<B> {
+    /// One of the transaction's stores. Operations on it are part of the transaction.
+    pub fn store<T: Serialize + DeserializeOwned>(&self, name: &str) -> Store<T, B> {
+        Store {
+            name: name.to_owned(),
+            scope: Scope::Transaction(self.transaction.clone()),
+            types: PhantomData,
+        }
+    }
+
+    /// Commits the transaction, waiting until its changes are written.
+    pub async fn commit(self) -> Result<(), Error> {
+        self.transaction.commit().await
+    }
+
+    /// Discards the transaction's changes.
+    pub fn abort(self) {
+        self.transaction.abort();
+    }
+}
+
+/// Records of type `T`, keyed by [`Key`]s.
+pub struct Store<T, B: Backend = WebBackend> {
+    name: String,
+    scope: Scope<B>,
+    types: PhantomData<fn(T) -> T>,
+}
+
+/// What a [`Store`]'s operations run in.
+enum Scope<B: Backend> {
+    /// A transaction of their own each.
+    Database(Rc<B::Connection>),
+    Transaction(Rc<TransactionOf<B>>),
+}
+
+impl<B: Backend> Clone for Scope<B> {
+    fn clone(&self) -> Scope<B> {
+        match self {
+            Scope::Database(connection) => Scope::Database(connection.clone()),
+            Scope::Transaction(transaction) => Scope::Transaction(transaction.clone()),
+        }
+    }
+}
+
+impl<T, B: Backend> Clone for Store<T, B> {
+    fn clone(&self) -> Store<T, B> {
+        Store {
+            name: self.name.clone(),
+            scope: self.scope.clone(),
+            types: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, B: Backend> Store<T, B> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<T>, Error> {
+        get(self, None, KeyRange::only(key)).await
+    }
+
+    pub async fn get_all(&self, range: impl Into<KeyRange>) -> Result<Vec<T>, Error> {
+        get_all(self, None, range.into()).await
+    }
+
+    pub async fn count(&self, range: impl Into<KeyRange>) -> Result<u32, Error> {
+        count(self, None, range.into()).await
+    }
+
+    /// Saves `value`, replacing any record with the same key, and returns its key. The
+    /// key comes from the value, or is generated if the store counts keys up.
+    pub async fn put(&self, value: &T) -> Result<Key, Error> {
+        let value = encode(value)?;
+        write(self, Operation::Put { value, key: None }).await
+    }
+
+    /// Saves `value` under `key`, for stores without a key path.
+    pub async fn put_with_key(&self, key: impl Into<Key>, value: &T) -> Result<Key, Error> {
+        let value = encode(value)?;
+        let key = Some(key.into());
+        write(self, Operation::Put { value, key }).await
+    }
+
+    /// Saves `value` like [`put`](Store::put), but fails with [`Error::Constraint`] if a
+    /// record with its key exists.
+    pub async fn add(&self, value: &T) -> Result<Key, Error> {
+        let value = encode(value)?;
+        write(self, Operation::Add { value, key: None }).await
+    }
+
+    pub async fn add_with_key(&self, key: impl Into<Key>, value: &T) -> Result<Key, Error> {
+        let value = encode(value)?;
+        let key = Some(key.into());
+        write(self, Operation::Add { value, key }).await
+    }
+
+    pub async fn delete(&self, range: impl Into<KeyRange>) -> Result<(), Error> {
+        self.run(Operation::Delete(range.into())).await?;
+        Ok(())
+    }
+
+    pub async fn clear(&self) -> Result<(), Error> {
+        self.run(Operation::Clear).await?;
+        Ok(())
+    }
+
+    /// Goes through the records with keys in `range`, with their keys.
+    pub fn cursor(&self, range: impl Into<KeyRange>, direction: Direction) -> Cursor<T, B> {
+        cursor(self, None, range.into(), direction)
+    }
+
+    pub fn index(&self, name: &str) -> Index<T, B> {
+        Index {
+            store: self.clone(),
+            name: name.to_owned(),
+        }
+    }
+
+    async fn run(&self, operation: Operation) -> Result<Outcome, Error> {
+        match &self.scope {
+            Scope::Transaction(transaction) => transaction.execute(&self.name, operation).await,
+            Scope::Database(connection) => {
+                let mode = if operation.writes() {
+                    Mode::ReadWrite
+                } else {
+                    Mode::ReadOnly
+                };
+                let transaction = connection.transaction(&[&self.name], mode)?;
+                let outcome = transaction.execute(&self.name, operation).await?;
+                transaction.commit().await?;
+                Ok(outcome)
+            }
+        }
+    }
+}
+
+/// A store's records, looked up by an index rather than by their keys.
+pub struct Index<T, B: Backend = WebBackend> {
+    store: Store<T, B>,
+    name: String,
+}
+
+impl<T: Serialize + DeserializeOwned, B: Backend> Index<T, B> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The first record with `key` in the index.
+    pub async fn get(&self, key: impl Into<Key>) -> Result<Option<T>, Error> {
+        get(&self.store, Some(&self.name), KeyRange::only(key)).await
+    }
+
+    pub async fn get_all(&self, range: impl Into<KeyRange>) -> Result<Vec<T>, Error> {
+        get_all(&self.store, Some(&self.name), range.into()).await
+    }
+
+    pub async fn count(&self, range: impl Into<KeyRange>) -> Result<u32, Error> {
+        count(&self.store, Some(&self.name), range.into()).await
+    }
+
+    /// Goes through the records with keys in `range` in the index, in the index's
+    /// order, with their primary keys.
+    pub fn cursor(&self, range: impl Into<KeyRange>, direction: Direction) -> Cursor<T, B> {
+        cursor(&self.store, Some(&self.name), range.into(), direction)
+    }
+}
+
+async fn get<T, B>(
+    store: &Store<T, B>,
+    index: Option<&str>,
+    range: KeyRange,
+) -> Result<Option<T>, Error>
+where
+    T: Serialize + DeserializeOwned,
+    B: Backend,
+{
+    let index = index.map(str::to_owned);
+    match store.run(Operation::Get { index, range }).await? {
+        Outcome::Value(value) => value.map(decode).transpose(),
+        outcome => unexpected("a get", &outcome),
+    }
+}
+
+async fn get_all<T, B>(
+    store: &Store<T, B>,
+    index: Option<&str>,
+    range: KeyRange,
+) -> Result<Vec<T>, Error>
+where
+    T: Serialize + DeserializeOwned,
+    B: Backend,
+{
+    let index = index.map(str::to_owned);
+    let limit = None;
+    match store
+        .run(Operation::GetAll {
+            index,
+            range,
+            limit,
+        })
+        .await?
+    {
+        Outcome::Values(values) => values.into_iter().map(decode).collect(),
+        outcome => unexpected("a get_all", &outcome),
+    }
+}
+
+async fn count<T, B>(
+    store: &Store<T, B>,
+    index: Option<&str>,
+    range: KeyRange,
+) -> Result<u32, Error>
+where
+    T: Serialize + DeserializeOwned,
+    B: Backend,
+{
+    let index = index.map(str::to_owned);
+    match store.run(Operation::Count { index, range }).await? {
+        Outcome::Count(count) => Ok(count),
+        outcome => unexpected("a count", &outcome),
+    }
+}
+
+async fn write<T, B>(store: &Store<T, B>, operation: Operation) -> Result<Key, Error>
+where
+    T: Serialize + DeserializeOwned,
+    B: Backend,
+{
+    match store.run(operation).await? {
+        Outcome::Key(key) => Ok(key),
+        outcome => unexpected("a write", &outcome),
+    }
+}
+
+fn cursor<T, B: Backend>(
+    store: &Store<T, B>,
+    index: Option<&str>,
+    range: KeyRange,
+    direction: Direction,
+) -> Cursor<T, B> {
+    let (sender, entries) = mpsc::unbounded();
+    let transaction = match &store.scope {
+        Scope::Transaction(transaction) => Ok(transaction.clone()),
+        Scope::Database(connection) => {
+            (connection.transaction(&[&store.name], Mode::ReadOnly)).map(Rc::new)
+        }
+    };
+    match &transaction {
+        Ok(transaction) => transaction.cursor(&store.name, index, &range, direction, sender),
+        Err(error) => {
+            let _ = sender.unbounded_send(Err(error.clone()));
+        }
+    }
+    Cursor {
+        entries,
+        _transaction: transaction.ok(),
+        types: PhantomData,
+    }
+}
+
+fn unexpected<T>(operation: &str, outcome: &Outcome) -> T {
+    unreachable!("the backend answered {operation} with {outcome:?}")
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Value, Error> {
+    serde_json::to_value(value).map_err(|error| Error::Encode(error.to_string()))
+}
+
+fn decode<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    serde_json::from_value(value).map_err(|error| Error::Decode(error.to_string()))
+}
+
+/// The records in a range of a store or index, with their primary keys. Dropping it
+/// stops it early.
+///
+/// Records are read ahead as fast as the backend gives them, because the browser
+/// commits a transaction that waits on its cursor.
+pub struct Cursor<T, B: Backend = WebBackend> {
+    entries: UnboundedReceiver<Result<Entry, Error>>,
+    /// Keeps the cursor's transaction open while it is read.
+    _transaction: Option<Rc<TransactionOf<B>>>,
+    types: PhantomData<fn(T) -> T>,
+}
+
+impl<T: DeserializeOwned, B: Backend> Stream for Cursor<T, B> {
+    type Item = Result<(Key, T), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.entries.poll_next_unpin(cx).map(|entry| {
+            entry.map(|entry| entry.and_then(|entry| Ok((entry.primary_key, decode(entry.value)?))))
+        })
+    }
+}
+
+/// A request a transaction runs against one of its stores, or against one of the
+/// store's indexes where there is an `index`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// The first record in the range.
+    Get {
+        index: Option<String>,
+        range: KeyRange,
+    },
+    GetAll {
+        index: Option<String>,
+        range: KeyRange,
+        /// How many records to get at most, with 0 meaning no limit, as in `getAll`.
+        limit: Option<u32>,
+    },
+    Count {
+        index: Option<String>,
+        range: KeyRange,
+    },
+    Put {
+        value: Value,
+        key: Option<Key>,
+    },
+    Add {
+        value: Value,
+        key: Option<Key>,
+    },
+    Delete(KeyRange),
+    Clear,
+}
+
+impl Operation {
+    pub fn writes(&self) -> bool {
+        matches!(
+            self,
+            Operation::Put { .. } | Operation::Add { .. } | Operation::Delete(_) | Operation::Clear
+        )
+    }
+}
+
+/// The result of an [`Operation`]: a `Value` for a get, `Values` for a get-all, a `Key`
+/// for a put or add, a `Count` for a count, and `Done` for a delete or clear.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Value(Option<Value>),
+    Values(Vec<Value>),
+    Key(Key),
+    Count(u32),
+    Done,
+}
+
+/// A record a cursor is at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// The key in the index, or the primary key if the cursor is over the store.
+    pub key: Key,
+    pub primary_key: Key,
+    pub value: Value,
+}
+
+/// Where databases are kept.
+pub trait Backend {
+    type Connection: Connection;
+
+    /// Opens a database, first running the schema's steps for the versions it hasn't
+    /// had, all in one go: if a step fails, none of them take effect.
+    fn open(&self, schema: &Schema) -> impl Future<Output = Result<Self::Connection, Error>>;
+
+    fn delete(&self, name: &str) -> impl Future<Output = Result<(), Error>>;
+}
+
+/// An open database.
+pub trait Connection {
+    type Transaction: BackendTransaction;
+
+    /// Starts a transaction, failing with [`Error::NotFound`] if a store doesn't exist.
+    fn transaction(&self, stores: &[&str], mode: Mode) -> Result<Self::Transaction, Error>;
+}
+
+/// A transaction as a [`Backend`] runs it. It commits when dropped, unless it was
+/// aborted or a write failed.
+pub trait BackendTransaction {
+    /// Runs an operation, answering with the [`Outcome`] that matches it.
+    fn execute(
+        &self,
+        store: &str,
+        operation: Operation,
+    ) -> impl Future<Output = Result<Outcome, Error>>;
+
+    /// Sends the entries in `range` to `entries` in order, then closes it, or stops at
+    /// the first error. Stops early if `entries` is closed.
+    fn cursor(
+        &self,
+        store: &str,
+        index: Option<&str>,
+        range: &KeyRange,
+        direction: Direction,
+        entries: UnboundedSender<Result<Entry, Error>>,
+    );
+
+    /// Commits the transaction, waiting until it has been.
+    fn commit(&self) -> impl Future<Output = Result<(), Error>>;
+
+    fn abort(&self);
+}
+
+/// The browser's IndexedDB.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebBackend;
+
+impl Backend for WebBackend {
+    type Connection = WebConnection;
+
+    async fn open(&self, schema: &Schema) -> Result<WebConnection, Error> {
+        let request = factory()?
+            .open_with_u32(schema.name(), schema.current_version())
+            .map_err(|error| from_dom(&error))?;
+        // Set when an update fails, which is reported instead of the abort it causes.
+        let failure = Rc::new(RefCell::new(None));
+        // The listeners of updates' cursors, which must outlive the upgrade's handler.
+        let cursors = Rc::new(RefCell::new(Vec::new()));
+        let _upgrade = Listener::new(&request, "upgradeneeded", {
+            let request = request.clone();
+            let schema = schema.clone();
+            let failure = failure.clone();
+            let cursors = cursors.clone();
+            move |event| {
+                let event: web_sys::IdbVersionChangeEvent = event.unchecked_into();
+                let upgrading = Upgrading {
+                    database: request
+                        .result()
+                        .expect_throw("no database during an upgrade")
+                        .unchecked_into(),
+                    transaction: request
+                        .transaction()
+                        .expect_throw("no transaction during an upgrade"),
+                    steps: schema
+                        .steps_after(event.old_version() as u32)
+                        .cloned()
+                        .collect(),
+                    failure: failure.clone(),
+                    cursors: cursors.clone(),
+                };
+                upgrading.run(0);
+            }
+        });
+        let name = schema.name().to_owned();
+        let _blocked = Listener::new(&request, "blocked", move |_| {
+            log::warn!("opening {name} is waiting for other tabs to close it");
+        });
+
+        let result = finish(&request).await;
+        // The cursors' listeners hold on to the upgrade, and so to this list.
+        cursors.take();
+        if let Some(error) = failure.take() {
+            return Err(error);
+        }
+        let database: web_sys::IdbDatabase = result?.unchecked_into();
+        // Closes this connection when another tab upgrades the database, rather than
+        // keeping that tab waiting.
+        let _version_change = Listener::new(&database, "versionchange", {
+            let database = database.clone();
+            move |_| {
+                log::warn!("closing {} for an upgrade elsewhere", database.name());
+                database.close();
+            }
+        });
+        Ok(WebConnection {
+            database,
+            _version_change,
+        })
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), Error> {
+        let request = factory()?
+            .delete_database(name)
+            .map_err(|error| from_dom(&error))?;
+        let name = name.to_owned();
+        let _blocked = Listener::new(&request, "blocked", move |_| {
+            log::warn!("deleting {name} is waiting for other tabs to close it");
+        });
+        finish(&request).await?;
+        Ok(())
+    }
+}
+
+/// The global `indexedDB` rather than `window`'s, so databases also work in workers.
+fn factory() -> Result<web_sys::IdbFactory, Error> {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+        .ok()
+        .and_then(|factory| factory.dyn_into().ok())
+        .ok_or_else(|| Error::Unavailable("there is no indexedDB".to_owned()))
+}
+
+/// An upgrade in progress in the browser.
+#[derive(Clone)]
+struct Upgrading {
+    database: web_sys::IdbDatabase,
+    transaction: web_sys::IdbTransaction,
+    steps: Rc<[Step]>,
+    /// Set when a step fails, which is reported instead of the abort it causes.
+    failure: Rc<RefCell<Option<Error>>>,
+    /// The listeners of updates' cursors, which must outlive the upgrade's handler.
+    cursors: Rc<RefCell<Vec<Listener>>>,
+}
+
+impl Upgrading {
+    /// Applies the steps from `first` on. An update's cursor only walks the records
+    /// once the handler that opened it returns, so the steps after an update run when
+    /// it reaches the end, to see the records as it left them.
+    fn run(&self, first: usize) {
+        for (index, step) in self.steps.iter().enumerate().skip(first) {
+            let applied = match step {
+                Step::Update { store, update } => {
+                    let upgrading = self.clone();
+                    match self.walk(store, update.clone(), move || upgrading.run(index + 1)) {
+                        Ok(cursor) => {
+                            self.cursors.borrow_mut().push(cursor);
+                            return;
+                        }
+                        Err(error) => Err(error),
+                    }
+                }
+                step => apply(&self.database, &self.transaction, step),
+            };
+            if let Err(error) = applied {
+                self.fail(error);
+                return;
+            }
+        }
+    }
+
+    fn fail(&self, error: Error) {
+        *self.failure.borrow_mut() = Some(error);
+        let _ = self.transaction.abort();
+    }
+
+    /// Updates every record of `store`, calling `done` after the last.
+    fn walk(
+        &self,
+        store: &str,
+        update: Rc<dyn Fn(Value) -> Result<Value, String>>,
+        done: impl FnOnce() + 'static,
+    ) -> Result<Listener, Error> {
+        let request = (self.transaction.object_store(store))
+            .and_then(|store| store.open_cursor())
+            .map_err(|error| from_dom(&error))?;
+        let upgrading = self.clone();
+        let mut done = Some(done);
+        Ok(Listener::new(&request.clone(), "success", move |_| {
+            let cursor = (request.result().ok())
+                .and_then(|result| result.dyn_into::<web_sys::IdbCursorWithValue>().ok());
+            let Some(cursor) = cursor else {
+                // There are no more records.
+                if let Some(done) = done.take() {
+                    done();
+                }
+                return;
+            };
+            let updated = (cursor.value().map_err(|error| from_dom(&error)))
+                .and_then(|value| from_js(&value))
+                .and_then(|value| update(value).map_err(Error::Upgrade))
+                .and_then(|value| to_js(&value))
+                .and_then(|value| cursor.update(&value).map_err(|error| from_dom(&error)));
+            match updated {
+                Ok(_) => {
+                    let _ = cursor.continue_();
+                }
+                Err(error) => upgrading.fail(error),
+            }
+        }))
+    }
+}
+
+/// Applies an upgrade step other than an update, which [`Upgrading::walk`] goes through.
+fn apply(
+    database: &web_sys::IdbDatabase,
+    transaction: &web_sys::IdbTransaction,
+    step: &Step,
+) -> Result<(), Error> {
+    let store = |name: &str| {
+        transaction
+            .object_store(name)
+            .map_err(|error| from_dom(&error))
+    };
+    match step {
+        Step::CreateStore(schema) => {
+            let parameters = web_sys::IdbObjectStoreParameters::new();
+            parameters.set_auto_increment(schema.auto_increment);
+            if let Some(path) = &schema.key_path {
+                parameters.set_key_path(&JsValue::from_str(path));
+            }
+            let store = database
+                .create_object_store_with_optional_parameters(&schema.name, &parameters)
+                .map_err(|error| from_dom(&error))?;
+            for index in &schema.indexes {
+                create_index(&store, index)?;
+            }
+        }
+        Step::DeleteStore(name) => {
+            database
+                .delete_object_store(name)
+                .map_err(|error| from_dom(&error))?;
+        }
+        Step::CreateIndex { store: name, index } => create_index(&store(name)?, index)?,
+        Step::DeleteIndex { store: name, index } => {
+            store(name)?
+                .delete_index(index)
+                .map_err(|error| from_dom(&error))?;
+        }
+        Step::Update { .. } => unreachable!("updates are walked with a cursor"),
+    }
+    Ok(())
+}
+
+fn create_index(store: &web_sys::IdbObjectStore, index: &IndexSchema) -> Result<(), Error> {
+    let parameters = web_sys::IdbIndexParameters::new();
+    parameters.set_unique(index.unique);
+    parameters.set_multi_entry(index.multi_entry);
+    store
+        .create_index_with_str_and_optional_parameters(&index.name, &index.key_path, &parameters)
+        .map_err(|error| from_dom(&error))?;
+    Ok(())
+}
+
+/// Waits for a request to succeed or fail, returning its result.
+async fn finish(request: &web_sys::IdbRequest) -> Result<JsValue, Error> {
+    let (done, mut finished) = mpsc::unbounded();
+    let _success = Listener::new(request, "success", {
+        let done = done.clone();
+        move |_| {
+            let _ = done.unbounded_send(true);
+        }
+    });
+    let _error = Listener::new(request, "error", move |_| {
+        let _ = done.unbounded_send(false);
+    });
+    match finished.next().await {
+        Some(true) => request.result().map_err(|error| from_dom(&error)),
+        _ => Err(request_error(request)),
+    }
+}
+
+fn request_error(request: &web_sys::IdbRequest) -> Error {
+    match request.error() {
+        Ok(Some(error)) => from_dom(&error),
+        Ok(None) => Error::Aborted("the request failed without an error".to_owned()),
+        Err(error) => from_dom(&error),
+    }
+}
+
+/// A database opened in the browser, which is closed when this is dropped.
+pub struct WebConnection {
+    database: web_sys::IdbDatabase,
+    _version_change: Listener,
+}
+
+impl Connection for WebConnection {
+    type Transaction = WebTransaction;
+
+    fn transaction(&self, stores: &[&str], mode: Mode) -> Result<WebTransaction, Error> {
+        let names: js_sys::Array = stores.iter().map(|name| JsValue::from_str(name)).collect();
+        let mode = match mode {
+            Mode::ReadOnly => web_sys::IdbTransactionMode::Readonly,
+            Mode::ReadWrite => web_sys::IdbTransactionMode::Readwrite,
+        };
+        let transaction = self
+            .database
+            .transaction_with_str_sequence_and_mode(&names, mode)
+            .map_err(|error| from_dom(&error))?;
+
+        let (done, finished) = oneshot::channel();
+        let done = Rc::new(RefCell::new(Some(done)));
+        let complete = Listener::new(&transaction, "complete", {
+            let done = done.clone();
+            move |_| {
+                if let Some(done) = done.borrow_mut().take() {
+                    let _ = done.send(Ok(()));
+                }
+            }
+        });
+        let abort = Listener::new(&transaction, "abort", {
+            let transaction = transaction.clone();
+            move |_| {
+                let error = match transaction.error() {
+                    Some(error) => from_dom(&error),
+                    None => Error::Aborted("the transaction was aborted".to_owned()),
+                };
+                if let Some(done) = done.borrow_mut().take() {
+                    let _ = done.send(Err(error));
+                }
+            }
+        });
+        Ok(WebTransaction {
+            transaction,
+            finished: RefCell::new(Some(finished)),
+            listeners: RefCell::new(vec![complete, abort]),
+        })
+    }
+}
+
+impl Drop for WebConnection {
+    fn drop(&mut self) {
+        self.database.close();
+    }
+}
+
+/// A transaction in the browser.
+pub struct WebTransaction {
+    transaction: web_sys::IdbTransaction,
+    /// Whether it committed, taken by the first call to `commit`.
+    finished: RefCell<Option<oneshot::Receiver<Result<(), Error>>>>,
+    /// Its own listeners and its cursors'.
+    listeners: RefCell<Vec<Listener>>,
+}
+
+impl WebTransaction {
+    /// Makes the request for `operation`, returning it with the operation, which says
+    /// what to make of its result.
+    fn request(
+        &self,
+        store: &str,
+        operation: Operation,
+    ) -> Result<(web_sys::IdbRequest, Operation), Error> {
+        let store = (self.transaction.object_store(store)).map_err(|error| from_dom(&error))?;
+        let request = match &operation {
+            // `get` needs a key or a bounded range, but `getAll` takes any range.
+            Operation::Get { index, range } => {
+                let range = range_to_js(range)?;
+                match index {
+                    None => store.get_all_with_key_and_limit(&range, 1),
+                    Some(index) => (store.index(index))
+                        .and_then(|index| index.get_all_with_key_and_limit(&range, 1)),
+                }
+            }
+            Operation::GetAll {
+                index,
+                range,
+                limit,
+            } => {
+                let range = range_to_js(range)?;
+                let limit = limit.unwrap_or(0);
+                match index {
+                    None => store.get_all_with_key_and_limit(&range, limit),
+                    Some(index) => (store.index(index))
+                        .and_then(|index| index.get_all_with_key_and_limit(&range, limit)),
+                }
+            }
+            Operation::Count { index, range } => {
+                let range = range_to_js(range)?;
+                match index {
+                    None => store.count_with_key(&range),
+                    Some(index) => store
+                        .index(index)
+                        .and_then(|index| index.count_with_key(&range)),
+                }
+            }
+            Operation::Put { value, key } | Operation::Add { value, key } => {
+                let value = to_js(value)?;
+                let add = matches!(operation, Operation::Add { .. });
+                match (key, add) {
+                    (None, false) => store.put(&value),
+                    (None, true) => store.add(&value),
+                    (Some(key), false) => store.put_with_key(&value, &key_to_js(key)),
+                    (Some(key), true) => store.add_with_key(&value, &key_to_js(key)),
+                }
+            }
+            Operation::Delete(range) if range.is_all() => store.clear(),
+            Operation::Delete(range) => store.delete(&range_to_js(range)?),
+            Operation::Clear => store.clear(),
+        };
+        let request = request.map_err(|error| from_dom(&error))?;
+        Ok((request, operation))
+    }
+}
+
+impl BackendTransaction for WebTransaction {
+    fn execute(
+        &self,
+        store: &str,
+        operation: Operation,
+    ) -> impl Future<Output = Result<Outcome, Error>> {
+        // Made now rather than when first polled, so requests run in the order their
+        // futures are made.
+        let request = self.request(store, operation);
+        async move {
+            let (request, operation) = request?;
+            let result = finish(&request).await?;
+            match operation {
+                Operation::Get { .. } | Operation::GetAll { .. } => {
+                    let values = (js_sys::Array::from(&result).iter())
+                        .map(|value| from_js(&value))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    match operation {
+                        Operation::Get { .. } => Ok(Outcome::Value(values.into_iter().next())),
+                        _ => Ok(Outcome::Values(values)),
+                    }
+                }
+                Operation::Count { .. } => {
+                    Ok(Outcome::Count(result.as_f64().unwrap_or_default() as u32))
+                }
+                Operation::Put { .. } | Operation::Add { .. } => {
+                    Ok(Outcome::Key(key_from_js(&result)?))
+                }
+                Operation::Delete(_) | Operation::Clear => Ok(Outcome::Done),
+            }
+        }
+    }
+
+    fn cursor(
+        &self,
+        store: &str,
+        index: Option<&str>,
+        range: &KeyRange,
+        direction: Direction,
+        entries: UnboundedSender<Result<Entry, Error>>,
+    ) {
+        let direction = match direction {
+            Direction::Next => web_sys::IdbCursorDirection::Next,
+            Direction::Prev => web_sys::IdbCursorDirection::Prev,
+        };
+        let request = range_to_js(range).and_then(|range| {
+            let store = self.transaction.object_store(store);
+            match index {
+                None => store.and_then(|store| {
+                    store.open_cursor_with_range_and_direction(&range, direction)
+                }),
+                Some(index) => store
+                    .and_then(|store| store.index(index))
+                    .and_then(|index| {
+                        index.open_cursor_with_range_and_direction(&range, direction)
+                    }),
+            }
+            .map_err(|error| from_dom(&error))
+        });
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => {
+                let _ = entries.unbounded_send(Err(error));
+                return;
+            }
+        };
+
+        let success = Listener::new(&request, "success", {
+            let request = request.clone();
+            let entries = entries.clone();
+            move |_| {
+                let cursor = (request.result().ok())
+                    .and_then(|result| result.dyn_into::<web_sys::IdbCursorWithValue>().ok());
+                let Some(cursor) = cursor else {
+                    // There are no more records.
+                    entries.close_channel();
+                    return;
+                };
+                let entry = (|| {
+                    let key = cursor.key().map_err(|error| from_dom(&error))?;
+                    let primary_key = cursor.primary_key().map_err(|error| from_dom(&error))?;
+                    let value = cursor.value().map_err(|error| from_dom(&error))?;
+                    Ok(Entry {
+                        key: key_from_js(&key)?,
+                        primary_key: key_from_js(&primary_key)?,
+                        value: from_js(&value)?,
+                    })
+                })();
+                let failed = entry.is_err();
+                if entries.unbounded_send(entry).is_ok() && !failed {
+                    let _ = cursor.continue_();
+                } else {
+                    entries.close_channel();
+                }
+            }
+        });
+        let error = Listener::new(&request, "error", {
+            let request = request.clone();
+            move |_| {
+                let _ = entries.unbounded_send(Err(request_error(&request)));
+                entries.close_channel();
+            }
+        });
+        self.listeners.borrow_mut().extend([success, error]);
+    }
+
+    fn commit(&self) -> impl Future<Output = Result<(), Error>> {
+        // Older browsers have no `commit`, and commit once there is nothing left to do
+        // anyway. It throws if the transaction has finished, which `finished` will tell.
+        let commit = js_sys::Reflect::get(&self.transaction, &JsValue::from_str("commit"))
+            .and_then(|commit| commit.dyn_into::<js_sys::Function>());
+        if let Ok(commit) = commit {
+            let _ = commit.call0(&self.transaction);
+        }
+        let finished = self.finished.borrow_mut().take();
+        async move {
+            match finished {
+                Some(finished) => finished.await.unwrap_or(Err(Error::Inactive)),
+                None => Err(Error::Inactive),
+            }
+        }
+    }
+
+    fn abort(&self) {
+        let _ = self.transaction.abort();
+    }
+}
+
+fn from_dom(error: &JsValue) -> Error {
+    let Some(error) = error.dyn_ref::<web_sys::DomException>() else {
+        return Error::Unavailable(format!("{error:?}"));
+    };
+    let message = error.message();
+    match error.name().as_str() {
+        "ConstraintError" => Error::Constraint(message),
+        "DataError" => Error::InvalidKey(message),
+        "NotFoundError" => Error::NotFound(message),
+        "ReadOnlyError" => Error::ReadOnly,
+        "QuotaExceededError" => Error::QuotaExceeded,
+        "TransactionInactiveError" | "InvalidStateError" => Error::Inactive,
+        "VersionError" => Error::Version(message),
+        "AbortError" => Error::Aborted(message),
+        name => Error::Unavailable(format!("{name}: {message}")),
+    }
+}
+
+fn to_js(value: &Value) -> Result<JsValue, Error> {
+    js_sys::JSON::parse(&value.to_string()).map_err(|error| Error::Encode(format!("{error:?}")))
+}
+
+fn from_js(value: &JsValue) -> Result<Value, Error> {
+    let json = (js_sys::JSON::stringify(value).ok())
+        .and_then(|json| json.as_string())
+        .ok_or_else(|| Error::Decode(format!("{value:?} is not JSON")))?;
+    serde_json::from_str(&json).map_err(|error| Error::Decode(error.to_string()))
+}
+
+fn key_to_js(key: &Key) -> JsValue {
+    match key {
+        Key::Number(number) => JsValue::from_f64(*number),
+        Key::String(string) => JsValue::from_str(string),
+        Key::Array(items) => items
+            .iter()
+            .map(key_to_js)
+            .collect::<js_sys::Array>()
+            .into(),
+    }
+}
+
+fn key_from_js(key: &JsValue) -> Result<Key, Error> {
+    if let Some(number) = key.as_f64() {
+        Ok(Key::Number(number))
+    } else if let Some(string) = key.as_string() {
+        Ok(Key::String(string))
+    } else if js_sys::Array::is_array(key) {
+        (js_sys::Array::from(key).iter())
+            .map(|item| key_from_js(&item))
+            .collect::<Result<_, _>>()
+            .map(Key::Array)
+    } else {
+        Err(Error::Decode(format!(
+            "{key:?} is a kind of key that isn't supported"
+        )))
+    }
+}
+
+/// The `IDBKeyRange` for a range, or `undefined` for all keys.
+fn range_to_js(range: &KeyRange) -> Result<JsValue, Error> {
+    let bound = |bound: &Bound<Key>| match bound {
+        Bound::Included(key) => Some((key_to_js(key), false)),
+        Bound::Excluded(key) => Some((key_to_js(key), true)),
+        Bound::Unbounded => None,
+    };
+    let range = match (bound(&range.lower), bound(&range.upper)) {
+        (None, None) => return Ok(JsValue::UNDEFINED),
+        (Some((lower, open)), None) => web_sys::IdbKeyRange::lower_bound_with_open(&lower, open),
+        (None, Some((upper, open))) => web_sys::IdbKeyRange::upper_bound_with_open(&upper, open),
+        (Some((lower, lower_open)), Some((upper, upper_open))) => {
+            web_sys::IdbKeyRange::bound_with_lower_open_and_upper_open(
+                &lower, &upper, lower_open, upper_open,
+            )
+        }
+    };
+    range.map(Into::into).map_err(|error| from_dom(&error))
+}
@@ -0,0 +1,220 @@
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+use serde_json::Value;
+
+/// The key of a record, or of an entry in an index. IndexedDB also allows dates and
+/// binary keys, which aren't supported here.
+///
+/// Keys sort the way IndexedDB sorts them: numbers before strings before arrays, with
+/// strings compared by UTF-16 code unit and arrays element by element.
+#[derive(Clone, Debug)]
+pub enum Key {
+    Number(f64),
+    String(String),
+    Array(Vec<Key>),
+}
+
+impl Key {
+    /// The key a JSON value stands for, if it is a valid one.
+    pub(crate) fn from_value(value: &Value) -> Option<Key> {
+        match value {
+            Value::Number(number) => number.as_f64().map(Key::Number),
+            Value::String(string) => Some(Key::String(string.clone())),
+            Value::Array(items) => items
+                .iter()
+                .map(Key::from_value)
+                .collect::<Option<_>>()
+                .map(Key::Array),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        match self {
+            Key::Number(number) => {
+                serde_json::Number::from_f64(*number).map_or(Value::Null, Value::Number)
+            }
+            Key::String(string) => Value::String(string.clone()),
+            Key::Array(items) => Value::Array(items.iter().map(Key::to_value).collect()),
+        }
+    }
+
+    /// Whether IndexedDB accepts the key, which it doesn't if there is a NaN in it.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            Key::Number(number) => !number.is_nan(),
+            Key::String(_) => true,
+            Key::Array(items) => items.iter().all(Key::is_valid),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Number(_) => 0,
+            Key::String(_) => 1,
+            Key::Array(_) => 2,
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            // `total_cmp` would put -0 before 0, which IndexedDB treats as the same key.
+            (Key::Number(a), Key::Number(b)) => a.partial_cmp(b).unwrap_or_else(|| a.total_cmp(b)),
+            (Key::String(a), Key::String(b)) => a.encode_utf16().cmp(b.encode_utf16()),
+            (Key::Array(a), Key::Array(b)) => a.cmp(b),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+impl From<f64> for Key {
+    fn from(number: f64) -> Key {
+        Key::Number(number)
+    }
+}
+
+impl From<i32> for Key {
+    fn from(number: i32) -> Key {
+        Key::Number(number.into())
+    }
+}
+
+impl From<u32> for Key {
+    fn from(number: u32) -> Key {
+        Key::Number(number.into())
+    }
+}
+
+impl From<&str> for Key {
+    fn from(string: &str) -> Key {
+        Key::String(string.to_owned())
+    }
+}
+
+impl From<String> for Key {
+    fn from(string: String) -> Key {
+        Key::String(string)
+    }
+}
+
+impl<K: Into<Key>> From<Vec<K>> for Key {
+    fn from(items: Vec<K>) -> Key {
+        Key::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// The keys between two bounds. Anything that converts into a [`Key`] converts into
+/// the range holding only that key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyRange {
+    pub lower: Bound<Key>,
+    pub upper: Bound<Key>,
+}
+
+impl KeyRange {
+    pub fn all() -> KeyRange {
+        KeyRange {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    pub fn only(key: impl Into<Key>) -> KeyRange {
+        let key = key.into();
+        KeyRange {
+            lower: Bound::Included(key.clone()),
+            upper: Bound::Included(key),
+        }
+    }
+
+    /// The keys in a Rust range, such as `KeyRange::new(1..10)` or
+    /// `KeyRange::new("a"..="m")`.
+    pub fn new<K: Into<Key> + Clone>(range: impl RangeBounds<K>) -> KeyRange {
+        KeyRange {
+            lower: range.start_bound().cloned().map(Into::into),
+            upper: range.end_bound().cloned().map(Into::into),
+        }
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        let above = match &self.lower {
+            Bound::Included(lower) => key >= lower,
+            Bound::Excluded(lower) => key > lower,
+            Bound::Unbounded => true,
+        };
+        let below = match &self.upper {
+            Bound::Included(upper) => key <= upper,
+            Bound::Excluded(upper) => key < upper,
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+
+    pub fn is_all(&self) -> bool {
+        matches!(
+            (&self.lower, &self.upper),
+            (Bound::Unbounded, Bound::Unbounded)
+        )
+    }
+
+    /// Whether IndexedDB accepts the range: its keys must be valid, and it must not be
+    /// empty by having its lower bound above its upper one.
+    pub(crate) fn is_valid(&self) -> bool {
+        let (lower, lower_open) = match &self.lower {
+            Bound::Included(key) => (key, false),
+            Bound::Excluded(key) => (key, true),
+            Bound::Unbounded => return bound_key(&self.upper).is_none_or(Key::is_valid),
+        };
+        let Some(upper) = bound_key(&self.upper) else {
+            return lower.is_valid();
+        };
+        let open = lower_open || matches!(self.upper, Bound::Excluded(_));
+        lower.is_valid() && upper.is_valid() && (lower < upper || (lower == upper && !open))
+    }
+}
+
+impl Default for KeyRange {
+    fn default() -> KeyRange {
+        KeyRange::all()
+    }
+}
+
+impl<K: Into<Key>> From<K> for KeyRange {
+    fn from(key: K) -> KeyRange {
+        KeyRange::only(key)
+    }
+}
+
+fn bound_key(bound: &Bound<Key>) -> Option<&Key> {
+    match bound {
+        Bound::Included(key) | Bound::Excluded(key) => Some(key),
+        Bound::Unbounded => None,
+    }
+}
+
+/// The value at a key path, a dotted path like `author.id`, in `value`. The empty path
+/// is the value itself.
+pub(crate) fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.')
+        .try_fold(value, |value, name| value.get(name))
+}
@@ -0,0 +1,774 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::{self, Future};
+use std::rc::Rc;
+
+use futures::channel::mpsc::UnboundedSender;
+use serde_json::Value;
+
+use super::key::value_at;
+use super::{
+    Backend, BackendTransaction, Connection, Direction, Entry, Error, IndexSchema, Key, KeyRange,
+    Mode, Operation, Outcome, Schema, Step, StoreSchema,
+};
+
+/// Databases kept in memory, for running natively. Clones share the same databases.
+///
+/// Transactions work on copies of their stores that replace the originals when they
+/// commit, without the locking the browser does between overlapping transactions, and
+/// indexes are found by going through every record.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    databases: Rc<RefCell<BTreeMap<String, Rc<RefCell<Database>>>>>,
+}
+
+#[derive(Default)]
+struct Database {
+    version: u32,
+    stores: BTreeMap<String, Store>,
+}
+
+#[derive(Clone)]
+struct Store {
+    schema: StoreSchema,
+    records: BTreeMap<Key, Value>,
+    /// The key the store generates next, if it counts keys up.
+    next_key: f64,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// The version of a database, or `None` if it doesn't exist.
+    pub fn version(&self, name: &str) -> Option<u32> {
+        let databases = self.databases.borrow();
+        let version = databases.get(name)?.borrow().version;
+        (version > 0).then_some(version)
+    }
+}
+
+impl Backend for MemoryBackend {
+    type Connection = MemoryConnection;
+
+    fn open(&self, schema: &Schema) -> impl Future<Output = Result<MemoryConnection, Error>> {
+        let database = (self.databases.borrow_mut())
+            .entry(schema.name().to_owned())
+            .or_default()
+            .clone();
+        future::ready(upgrade(&database, schema).map(|()| MemoryConnection { database }))
+    }
+
+    fn delete(&self, name: &str) -> impl Future<Output = Result<(), Error>> {
+        self.databases.borrow_mut().remove(name);
+        future::ready(Ok(()))
+    }
+}
+
+/// Runs the schema's steps that the database hasn't had, on copies of its stores that
+/// replace the originals once every step has succeeded.
+fn upgrade(database: &RefCell<Database>, schema: &Schema) -> Result<(), Error> {
+    let mut database = database.borrow_mut();
+    let version = schema.current_version();
+    if version < database.version {
+        return Err(Error::Version(format!(
+            "the database is at version {}, but the schema only goes up to {version}",
+            database.version
+        )));
+    }
+    if version == database.version {
+        return Ok(());
+    }
+    let mut stores = database.stores.clone();
+    for step in schema.steps_after(database.version) {
+        apply(&mut stores, step)?;
+    }
+    database.stores = stores;
+    database.version = version;
+    Ok(())
+}
+
+fn apply(stores: &mut BTreeMap<String, Store>, step: &Step) -> Result<(), Error> {
+    match step {
+        Step::CreateStore(schema) => {
+            if stores.contains_key(&schema.name) {
+                return Err(Error::Constraint(format!(
+                    "there is already a store named {}",
+                    schema.name
+                )));
+            }
+            let store = Store {
+                schema: schema.clone(),
+                records: BTreeMap::new(),
+                next_key: 1.0,
+            };
+            stores.insert(schema.name.clone(), store);
+        }
+        Step::DeleteStore(name) => {
+            stores.remove(name).ok_or_else(|| no_store(name))?;
+        }
+        Step::CreateIndex { store, index } => {
+            let store = stores.get_mut(store).ok_or_else(|| no_store(store))?;
+            if store.index(&index.name).is_ok() {
+                return Err(Error::Constraint(format!(
+                    "there is already an index named {}",
+                    index.name
+                )));
+            }
+            store.schema.indexes.push(index.clone());
+            for (key, value) in &store.records {
+                store.check_unique(key, value)?;
+            }
+        }
+        Step::DeleteIndex { store, index } => {
+            let store = stores.get_mut(store).ok_or_else(|| no_store(store))?;
+            store.index(index)?;
+            store
+                .schema
+                .indexes
+                .retain(|existing| existing.name != *index);
+        }
+        Step::Update { store, update } => {
+            let store = stores.get_mut(store).ok_or_else(|| no_store(store))?;
+            for (key, value) in store.records.clone() {
+                let value = update(value).map_err(Error::Upgrade)?;
+                // Like `IDBCursor.update`, which can't move a record to another key.
+                let key = match &store.schema.key_path {
+                    Some(path)
+                        if value_at(&value, path).and_then(Key::from_value)
+                            != Some(key.clone()) =>
+                    {
+                        return Err(Error::InvalidKey(format!(
+                            "an update changed the key at {path} of the record {key:?}"
+                        )))
+                    }
+                    Some(_) => None,
+                    None => Some(key),
+                };
+                store.put(value, key, true)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn no_store(name: &str) -> Error {
+    Error::NotFound(format!("there is no store named {name}"))
+}
+
+impl Store {
+    fn index(&self, name: &str) -> Result<&IndexSchema, Error> {
+        (self.schema.indexes.iter())
+            .find(|index| index.name == name)
+            .ok_or_else(|| Error::NotFound(format!("there is no index named {name}")))
+    }
+
+    /// Saves a record, returning its key, which is taken from the value or generated
+    /// when not given.
+    fn put(&mut self, mut value: Value, key: Option<Key>, overwrite: bool) -> Result<Key, Error> {
+        let key = match (self.schema.key_path.clone(), key) {
+            (Some(_), Some(_)) => {
+                return Err(Error::InvalidKey(
+                    "the store takes keys from its values, so none can be given".to_owned(),
+                ))
+            }
+            (Some(path), None) => match value_at(&value, &path) {
+                Some(key) => Key::from_value(key).ok_or_else(|| {
+                    Error::InvalidKey(format!("the value at {path} is not a valid key"))
+                })?,
+                None if self.schema.auto_increment => {
+                    let key = self.generate_key();
+                    insert_at(&mut value, &path, key.to_value())?;
+                    key
+                }
+                None => {
+                    return Err(Error::InvalidKey(format!(
+                        "the value has nothing at {path}"
+                    )))
+                }
+            },
+            (None, Some(key)) => key,
+            (None, None) if self.schema.auto_increment => self.generate_key(),
+            (None, None) => {
+                return Err(Error::InvalidKey(
+                    "the store needs a key for each value".to_owned(),
+                ))
+            }
+        };
+        if !key.is_valid() {
+            return Err(Error::InvalidKey(format!("{key:?} is not a valid key")));
+        }
+        if !overwrite && self.records.contains_key(&key) {
+            return Err(Error::Constraint(format!(
+                "there is already a record with the key {key:?}"
+            )));
+        }
+        self.check_unique(&key, &value)?;
+        if let (true, Key::Number(number)) = (self.schema.auto_increment, &key) {
+            if *number >= self.next_key {
+                self.next_key = number.floor() + 1.0;
+            }
+        }
+        self.records.insert(key.clone(), value);
+        Ok(key)
+    }
+
+    fn generate_key(&mut self) -> Key {
+        let key = Key::Number(self.next_key);
+        self.next_key += 1.0;
+        key
+    }
+
+    /// Fails if `value`, saved under `key`, would share a key in a unique index with
+    /// another record.
+    fn check_unique(&self, key: &Key, value: &Value) -> Result<(), Error> {
+        for index in self.schema.indexes.iter().filter(|index| index.unique) {
+            let keys = index_keys(index, value);
+            let taken = (self.records.iter())
+                .filter(|(other, _)| *other != key)
+                .flat_map(|(_, other)| index_keys(index, other))
+                .find(|other| keys.contains(other));
+            if let Some(taken) = taken {
+                return Err(Error::Constraint(format!(
+                    "another record has {taken:?} in the unique index {}",
+                    index.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries in `range` of the store, or of one of its indexes, in order.
+    fn entries(&self, index: Option<&str>, range: &KeyRange) -> Result<Vec<Entry>, Error> {
+        if !range.is_valid() {
+            return Err(Error::InvalidKey(format!("{range:?} is not a valid range")));
+        }
+        let Some(index) = index else {
+            return Ok((self.records.iter())
+                .filter(|(key, _)| range.contains(key))
+                .map(|(key, value)| Entry {
+                    key: key.clone(),
+                    primary_key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect());
+        };
+        let index = self.index(index)?;
+        let mut entries: Vec<Entry> = (self.records.iter())
+            .flat_map(|(primary_key, value)| {
+                (index_keys(index, value).into_iter())
+                    .filter(|key| range.contains(key))
+                    .map(|key| Entry {
+                        key,
+                        primary_key: primary_key.clone(),
+                        value: value.clone(),
+                    })
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.key, &a.primary_key).cmp(&(&b.key, &b.primary_key)));
+        Ok(entries)
+    }
+
+    fn run(&mut self, operation: Operation) -> Result<Outcome, Error> {
+        match operation {
+            Operation::Get { index, range } => {
+                let entries = self.entries(index.as_deref(), &range)?;
+                Ok(Outcome::Value(
+                    entries.into_iter().next().map(|entry| entry.value),
+                ))
+            }
+            Operation::GetAll {
+                index,
+                range,
+                limit,
+            } => {
+                let entries = self.entries(index.as_deref(), &range)?;
+                let limit = match limit {
+                    Some(limit) if limit > 0 => limit as usize,
+                    _ => usize::MAX,
+                };
+                Ok(Outcome::Values(
+                    entries
+                        .into_iter()
+                        .take(limit)
+                        .map(|entry| entry.value)
+                        .collect(),
+                ))
+            }
+            Operation::Count { index, range } => {
+                let entries = self.entries(index.as_deref(), &range)?;
+                Ok(Outcome::Count(entries.len() as u32))
+            }
+            Operation::Put { value, key } => self.put(value, key, true).map(Outcome::Key),
+            Operation::Add { value, key } => self.put(value, key, false).map(Outcome::Key),
+            Operation::Delete(range) => {
+                if !range.is_valid() {
+                    return Err(Error::InvalidKey(format!("{range:?} is not a valid range")));
+                }
+                self.records.retain(|key, _| !range.contains(key));
+                Ok(Outcome::Done)
+            }
+            Operation::Clear => {
+                self.records.clear();
+                Ok(Outcome::Done)
+            }
+        }
+    }
+}
+
+/// The keys a record has in an index: none if it has no valid key at the index's path,
+/// and for a multi-entry index whose path holds an array, each valid key in it.
+fn index_keys(index: &IndexSchema, value: &Value) -> Vec<Key> {
+    match value_at(value, &index.key_path) {
+        Some(Value::Array(items)) if index.multi_entry => {
+            let mut keys: Vec<Key> = items.iter().filter_map(Key::from_value).collect();
+            keys.sort();
+            keys.dedup();
+            keys
+        }
+        Some(value) => Key::from_value(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Puts a generated key at a key path, adding objects along it where needed.
+fn insert_at(value: &mut Value, path: &str, key: Value) -> Result<(), Error> {
+    let mut target = value;
+    let mut names = path.split('.').peekable();
+    while let Some(name) = names.next() {
+        let Value::Object(object) = target else {
+            return Err(Error::InvalidKey(format!(
+                "there is no object to hold the key at {path}"
+            )));
+        };
+        if names.peek().is_none() {
+            object.insert(name.to_owned(), key);
+            return Ok(());
+        }
+        target = object
+            .entry(name)
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+    // Only an empty path gets here, and stores with one can't generate keys.
+    Err(Error::InvalidKey(
+        "a generated key needs a key path to go at".to_owned(),
+    ))
+}
+
+/// A database opened in a [`MemoryBackend`].
+pub struct MemoryConnection {
+    database: Rc<RefCell<Database>>,
+}
+
+impl Connection for MemoryConnection {
+    type Transaction = MemoryTransaction;
+
+    fn transaction(&self, stores: &[&str], mode: Mode) -> Result<MemoryTransaction, Error> {
+        let database = self.database.borrow();
+        let stores = (stores.iter())
+            .map(|&name| {
+                let store = database.stores.get(name).ok_or_else(|| no_store(name))?;
+                Ok((name.to_owned(), store.clone()))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(MemoryTransaction {
+            database: self.database.clone(),
+            mode,
+            stores: RefCell::new(stores),
+            state: RefCell::new(State::Active),
+        })
+    }
+}
+
+/// A transaction in a [`MemoryBackend`].
+pub struct MemoryTransaction {
+    database: Rc<RefCell<Database>>,
+    mode: Mode,
+    /// Copies of the stores it covers, with its changes.
+    stores: RefCell<BTreeMap<String, Store>>,
+    state: RefCell<State>,
+}
+
+enum State {
+    Active,
+    /// A write failed, aborting the transaction.
+    Failed(Error),
+    Finished,
+}
+
+impl MemoryTransaction {
+    fn run(&self, store: &str, operation: Operation) -> Result<Outcome, Error> {
+        if !matches!(*self.state.borrow(), State::Active) {
+            return Err(Error::Inactive);
+        }
+        if operation.writes() && self.mode == Mode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+        let mut stores = self.stores.borrow_mut();
+        let store = stores.get_mut(store).ok_or_else(|| {
+            Error::NotFound(format!(
+                "the transaction doesn't cover a store named {store}"
+            ))
+        })?;
+        let outcome = store.run(operation);
+        // Like in the browser, only failures of requests themselves abort the
+        // transaction, not mistakes caught before they are made.
+        if let Err(error @ Error::Constraint(_)) = &outcome {
+            *self.state.borrow_mut() = State::Failed(error.clone());
+        }
+        outcome
+    }
+
+    fn finish(&self, commit: bool) -> Result<(), Error> {
+        match std::mem::replace(&mut *self.state.borrow_mut(), State::Finished) {
+            State::Active if commit && self.mode == Mode::ReadWrite => {
+                let mut database = self.database.borrow_mut();
+                database.stores.extend(self.stores.take());
+                Ok(())
+            }
+            State::Active => Ok(()),
+            State::Failed(error) => Err(error),
+            State::Finished => Err(Error::Inactive),
+        }
+    }
+}
+
+impl BackendTransaction for MemoryTransaction {
+    fn execute(
+        &self,
+        store: &str,
+        operation: Operation,
+    ) -> impl Future<Output = Result<Outcome, Error>> {
+        future::ready(self.run(store, operation))
+    }
+
+    fn cursor(
+        &self,
+        store: &str,
+        index: Option<&str>,
+        range: &KeyRange,
+        direction: Direction,
+        entries: UnboundedSender<Result<Entry, Error>>,
+    ) {
+        let found = match (&*self.state.borrow(), self.stores.borrow().get(store)) {
+            (State::Active, Some(store)) => store.entries(index, range),
+            (State::Active, None) => Err(Error::NotFound(format!(
+                "the transaction doesn't cover a store named {store}"
+            ))),
+            _ => Err(Error::Inactive),
+        };
+        let found = match (found, direction) {
+            (Ok(found), Direction::Next) => found,
+            (Ok(mut found), Direction::Prev) => {
+                found.reverse();
+                found
+            }
+            (Err(error), _) => {
+                let _ = entries.unbounded_send(Err(error));
+                return;
+            }
+        };
+        for entry in found {
+            if entries.unbounded_send(Ok(entry)).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn commit(&self) -> impl Future<Output = Result<(), Error>> {
+        future::ready(self.finish(true))
+    }
+
+    fn abort(&self) {
+        let _ = self.finish(false);
+    }
+}
+
+impl Drop for MemoryTransaction {
+    fn drop(&mut self) {
+        if matches!(*self.state.get_mut(), State::Active) {
+            let _ = self.finish(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use super::super::{Database, IndexSchema, KeyRange, Mode, Schema, StoreSchema, Upgrade};
+    use super::*;
+
+    fn notes_v1() -> Schema {
+        Schema::new("db").version(
+            1,
+            Upgrade::new().create_store(StoreSchema::new("notes").key_path("id")),
+        )
+    }
+
+    fn open(backend: &MemoryBackend, schema: Schema) -> Result<Database<MemoryBackend>, Error> {
+        block_on(Database::open_with(backend.clone(), schema))
+    }
+
+    #[test]
+    fn upgrades_from_the_version_the_database_is_at() {
+        let backend = MemoryBackend::new();
+        let database = open(&backend, notes_v1()).unwrap();
+        block_on(
+            database
+                .store::<Value>("notes")
+                .put(&json!({ "id": 1, "text": "a" })),
+        )
+        .unwrap();
+        assert_eq!(backend.version("db"), Some(1));
+
+        let v2 = notes_v1().version(
+            2,
+            Upgrade::new()
+                .create_index("notes", IndexSchema::new("by_text", "text").unique())
+                .update("notes", |mut note| {
+                    note["text"] = json!(note["text"].as_str().unwrap().to_uppercase());
+                    Ok(note)
+                }),
+        );
+        let database = open(&backend, v2.clone()).unwrap();
+        let notes = database.store::<Value>("notes");
+        assert_eq!(
+            block_on(notes.index("by_text").get("A")).unwrap(),
+            Some(json!({ "id": 1, "text": "A" }))
+        );
+        // Opening again at the same version runs nothing.
+        open(&backend, v2).unwrap();
+        assert_eq!(block_on(notes.get_all(KeyRange::all())).unwrap().len(), 1);
+
+        assert!(matches!(open(&backend, notes_v1()), Err(Error::Version(_))));
+        assert_eq!(backend.version("db"), Some(2));
+        assert_eq!(backend.version("other"), None);
+    }
+
+    /// Each update sees the records as the steps before it left them, as the browser's
+    /// cursors have to as well.
+    #[test]
+    fn chains_updates_across_versions() {
+        let backend = MemoryBackend::new();
+        let database = open(&backend, notes_v1()).unwrap();
+        block_on(
+            database
+                .store::<Value>("notes")
+                .put(&json!({ "id": 1, "text": "a" })),
+        )
+        .unwrap();
+
+        let append = |suffix: &'static str| {
+            move |mut note: Value| {
+                note["text"] = json!(format!("{}{suffix}", note["text"].as_str().unwrap()));
+                Ok(note)
+            }
+        };
+        let v3 = notes_v1()
+            .version(2, Upgrade::new().update("notes", append("b")))
+            .version(
+                3,
+                Upgrade::new()
+                    .update("notes", append("c"))
+                    .create_store(StoreSchema::new("old"))
+                    .update("notes", append("d"))
+                    .delete_store("old"),
+            );
+        let database = open(&backend, v3).unwrap();
+        assert_eq!(
+            block_on(database.store::<Value>("notes").get(1)).unwrap(),
+            Some(json!({ "id": 1, "text": "abcd" }))
+        );
+        assert_eq!(backend.version("db"), Some(3));
+    }
+
+    #[test]
+    fn failed_upgrades_change_nothing() {
+        let backend = MemoryBackend::new();
+        let database = open(&backend, notes_v1()).unwrap();
+        block_on(database.store::<Value>("notes").put(&json!({ "id": 1 }))).unwrap();
+
+        let failing = notes_v1().version(
+            2,
+            Upgrade::new()
+                .create_store(StoreSchema::new("tags"))
+                .update("notes", |_| Err("broken".to_owned())),
+        );
+        assert_eq!(
+            open(&backend, failing).err(),
+            Some(Error::Upgrade("broken".to_owned()))
+        );
+        let moving = notes_v1().version(
+            2,
+            Upgrade::new().update("notes", |note| {
+                Ok(json!({ "id": note["id"].as_u64().unwrap() + 1 }))
+            }),
+        );
+        assert!(matches!(open(&backend, moving), Err(Error::InvalidKey(_))));
+
+        assert_eq!(backend.version("db"), Some(1));
+        let database = open(&backend, notes_v1()).unwrap();
+        assert!(matches!(
+            database.transaction(&["tags"], Mode::ReadOnly),
+            Err(Error::NotFound(_))
+        ));
+        let notes = block_on(database.store::<Value>("notes").get_all(KeyRange::all()));
+        assert_eq!(notes.unwrap(), [json!({ "id": 1 })]);
+    }
+
+    #[test]
+    fn generates_keys_and_enforces_constraints() {
+        let schema = Schema::new("db").version(
+            1,
+            Upgrade::new()
+                .create_store(
+                    StoreSchema::new("users")
+                        .key_path("meta.id")
+                        .auto_increment()
+                        .index(IndexSchema::new("by_email", "email").unique()),
+                )
+                .create_store(StoreSchema::new("plain")),
+        );
+        let database = open(&MemoryBackend::new(), schema).unwrap();
+        let users = database.store::<Value>("users");
+
+        let key = block_on(users.put(&json!({ "email": "a@x" }))).unwrap();
+        assert_eq!(key, 1.into());
+        assert_eq!(
+            block_on(users.get(1)).unwrap(),
+            Some(json!({ "email": "a@x", "meta": { "id": 1.0 } }))
+        );
+        block_on(users.put(&json!({ "email": "b@x", "meta": { "id": 10 } }))).unwrap();
+        assert_eq!(block_on(users.put(&json!({}))).unwrap(), 11.into());
+
+        let duplicate = json!({ "email": "a@x", "meta": { "id": 20 } });
+        assert!(matches!(
+            block_on(users.put(&duplicate)),
+            Err(Error::Constraint(_))
+        ));
+        let existing = json!({ "email": "c@x", "meta": { "id": 10 } });
+        assert!(matches!(
+            block_on(users.add(&existing)),
+            Err(Error::Constraint(_))
+        ));
+        assert!(matches!(
+            block_on(users.put_with_key(5, &json!({}))),
+            Err(Error::InvalidKey(_))
+        ));
+
+        let plain = database.store::<String>("plain");
+        assert!(matches!(
+            block_on(plain.put(&"x".to_owned())),
+            Err(Error::InvalidKey(_))
+        ));
+        block_on(plain.put_with_key("k", &"x".to_owned())).unwrap();
+        assert_eq!(block_on(plain.get("k")).unwrap().as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn applies_transactions_together_or_not_at_all() {
+        let schema = Schema::new("db").version(
+            1,
+            Upgrade::new()
+                .create_store(StoreSchema::new("a"))
+                .create_store(StoreSchema::new("b")),
+        );
+        let database = open(&MemoryBackend::new(), schema).unwrap();
+        let count =
+            |name: &str| block_on(database.store::<u32>(name).count(KeyRange::all())).unwrap();
+
+        let transaction = database.transaction(&["a", "b"], Mode::ReadWrite).unwrap();
+        block_on(transaction.store::<u32>("a").put_with_key(1, &1)).unwrap();
+        block_on(transaction.store::<u32>("b").put_with_key(1, &1)).unwrap();
+        // Not visible outside until committed.
+        assert_eq!(count("a"), 0);
+        block_on(transaction.commit()).unwrap();
+        assert_eq!((count("a"), count("b")), (1, 1));
+
+        let transaction = database.transaction(&["a"], Mode::ReadWrite).unwrap();
+        block_on(transaction.store::<u32>("a").put_with_key(2, &2)).unwrap();
+        transaction.abort();
+        assert_eq!(count("a"), 1);
+
+        // A failed write aborts the rest.
+        let transaction = database.transaction(&["a", "b"], Mode::ReadWrite).unwrap();
+        block_on(transaction.store::<u32>("b").put_with_key(2, &2)).unwrap();
+        let a = transaction.store::<u32>("a");
+        assert!(matches!(
+            block_on(a.add_with_key(1, &0)),
+            Err(Error::Constraint(_))
+        ));
+        assert_eq!(block_on(a.get(1)), Err(Error::Inactive));
+        assert!(matches!(
+            block_on(transaction.commit()),
+            Err(Error::Constraint(_))
+        ));
+        assert_eq!(count("b"), 1);
+
+        let transaction = database.transaction(&["a"], Mode::ReadOnly).unwrap();
+        let a = transaction.store::<u32>("a");
+        assert_eq!(block_on(a.clear()), Err(Error::ReadOnly));
+        assert!(matches!(
+            block_on(transaction.store::<u32>("b").get(1)),
+            Err(Error::NotFound(_))
+        ));
+        block_on(transaction.commit()).unwrap();
+        assert_eq!(block_on(a.get(1)), Err(Error::Inactive));
+    }
+
+    #[test]
+    fn walks_stores_and_indexes_in_order() {
+        let schema = Schema::new("db").version(
+            1,
+            Upgrade::new().create_store(
+                StoreSchema::new("posts")
+                    .key_path("id")
+                    .index(IndexSchema::new("by_tag", "tags").multi_entry()),
+            ),
+        );
+        let database = open(&MemoryBackend::new(), schema).unwrap();
+        let posts = database.store::<Value>("posts");
+        for (id, tags) in [
+            (1, json!(["b", "a"])),
+            (2, json!(["a"])),
+            (3, json!(null)),
+            (4, json!(["c", "c"])),
+        ] {
+            block_on(posts.put(&json!({ "id": id, "tags": tags }))).unwrap();
+        }
+
+        let keys = |cursor: super::super::Cursor<Value, MemoryBackend>| {
+            block_on(cursor.map(|entry| entry.unwrap().0).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            keys(posts.cursor(KeyRange::new(2..), Direction::Prev)),
+            [4.into(), 3.into(), 2.into()]
+        );
+        let by_tag = posts.index("by_tag");
+        assert_eq!(
+            keys(by_tag.cursor(KeyRange::all(), Direction::Next)),
+            [1.into(), 2.into(), 1.into(), 4.into()]
+        );
+        assert_eq!(block_on(by_tag.count("c")).unwrap(), 1);
+        assert_eq!(block_on(by_tag.count(KeyRange::new("a"..="b"))).unwrap(), 3);
+
+        block_on(posts.delete(KeyRange::new(..=2))).unwrap();
+        assert_eq!(
+            keys(posts.cursor(KeyRange::all(), Direction::Next)),
+            [3.into(), 4.into()]
+        );
+        assert!(matches!(
+            block_on(posts.get_all(KeyRange {
+                lower: Bound::Included(5.into()),
+                upper: Bound::Excluded(1.into()),
+            })),
+            Err(Error::InvalidKey(_))
+        ));
+    }
+}
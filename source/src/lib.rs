@@ -1,6 +1,38 @@
+extern crate self as hello_wasm;
+
+mod app;
+pub mod component;
+pub mod dom;
+pub mod http;
+pub mod indexed_db;
+mod logger;
+mod logging;
+mod panic;
+pub mod reactive;
+pub mod router;
+pub mod sse;
+pub mod storage;
+pub mod threads;
+mod tracing_console;
+pub mod vdom;
+pub mod websocket;
+pub mod worker_pool;
+
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-pub fn log_msg() {
-    web_sys::console::log_1(&"Hello, WASM!".into())
+pub use app::{hydrate, mount_app, render_app};
+pub use hello_wasm_macros::html;
+pub use logger::{init_logger, ConsoleLogger};
+pub use logging::{
+    enabled, log_level, log_msg, set_log_level, set_sink, ConsoleSink, Level, MemorySink, Sink,
+};
+pub use panic::{demangle_backtrace, install_panic_hook, panic_message, set_panic_callback};
+pub use router::navigate;
+pub use tracing_console::{
+    init_tracing, Backend, Call, ConsoleLayer, FieldValue, Fields, RecordingBackend, WebBackend,
+};
+
+#[wasm_bindgen(start)]
+fn start() {
+    install_panic_hook();
 }
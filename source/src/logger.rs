@@ -0,0 +1,112 @@
+use wasm_bindgen::prelude::*;
+
+use crate::logging::{self, Level};
+
+/// Backend for the `log` crate that forwards records through [`logging::log_msg`].
+pub struct ConsoleLogger;
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+impl ConsoleLogger {
+    /// Renders a record as `LEVEL target (module src/file.rs:line): message`. The
+    /// module path is left out when it is the same as the target.
+    pub fn format(record: &log::Record) -> String {
+        let mut location = String::new();
+        if let Some(module) = record.module_path() {
+            if module != record.target() {
+                location.push_str(module);
+            }
+        }
+        if let Some(file) = record.file() {
+            if !location.is_empty() {
+                location.push(' ');
+            }
+            location.push_str(file);
+            if let Some(line) = record.line() {
+                location.push_str(&format!(":{line}"));
+            }
+        }
+
+        let level = Level::from(record.level()).as_str();
+        if location.is_empty() {
+            format!("{level} {}: {}", record.target(), record.args())
+        } else {
+            format!(
+                "{level} {} ({location}): {}",
+                record.target(),
+                record.args()
+            )
+        }
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        logging::enabled(metadata.level().into())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            logging::log_msg(record.level().into(), &ConsoleLogger::format(record));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs [`ConsoleLogger`] as the `log` backend and sets the minimum level. Calling
+/// it again only changes the level.
+#[wasm_bindgen]
+pub fn init_logger(level: Level) {
+    // `set_logger` fails if a logger is already installed, which for us is always this one.
+    let _ = log::set_logger(&LOGGER);
+    logging::set_log_level(level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::tests::capture;
+
+    #[test]
+    fn format_includes_the_module_when_it_differs_from_the_target() {
+        let args = format_args!("hi");
+        let record = log::Record::builder()
+            .args(args)
+            .level(log::Level::Info)
+            .target("app")
+            .module_path(Some("app::views"))
+            .file(Some("src/views.rs"))
+            .line(Some(12))
+            .build();
+        assert_eq!(
+            ConsoleLogger::format(&record),
+            "INFO app (app::views src/views.rs:12): hi"
+        );
+    }
+
+    #[test]
+    fn format_leaves_out_a_module_matching_the_target() {
+        let args = format_args!("hi");
+        let record = log::Record::builder()
+            .args(args)
+            .level(log::Level::Warn)
+            .target("app")
+            .module_path(Some("app"))
+            .build();
+        assert_eq!(ConsoleLogger::format(&record), "WARN app: hi");
+    }
+
+    #[test]
+    fn records_reach_the_sink() {
+        let (_guard, sink) = capture(Level::Info);
+        init_logger(Level::Debug);
+        log::debug!(target: "app", "loaded {} items", 3);
+        log::trace!(target: "app", "ignored");
+        assert_eq!(sink.entries().len(), 1);
+        let (level, message) = &sink.entries()[0];
+        assert_eq!(*level, Level::Debug);
+        assert!(message.starts_with("DEBUG app ("), "{message}");
+        assert!(message.ends_with("): loaded 3 items"), "{message}");
+    }
+}
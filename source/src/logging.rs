@@ -0,0 +1,216 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use wasm_bindgen::prelude::*;
+
+/// Severity of a log message, from most to least verbose.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+}
+
+impl Level {
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            _ => Level::Error,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+impl From<log::Level> for Level {
+    fn from(level: log::Level) -> Level {
+        match level {
+            log::Level::Trace => Level::Trace,
+            log::Level::Debug => Level::Debug,
+            log::Level::Info => Level::Info,
+            log::Level::Warn => Level::Warn,
+            log::Level::Error => Level::Error,
+        }
+    }
+}
+
+impl From<Level> for log::LevelFilter {
+    fn from(level: Level) -> log::LevelFilter {
+        match level {
+            Level::Trace => log::LevelFilter::Trace,
+            Level::Debug => log::LevelFilter::Debug,
+            Level::Info => log::LevelFilter::Info,
+            Level::Warn => log::LevelFilter::Warn,
+            Level::Error => log::LevelFilter::Error,
+        }
+    }
+}
+
+/// Destination for formatted log messages.
+pub trait Sink: Send + Sync {
+    fn write(&self, level: Level, message: &str);
+}
+
+/// Writes to the browser console, picking the `console.*` method from the level.
+pub struct ConsoleSink;
+
+impl ConsoleSink {
+    /// Logs `message`, followed by `extra` when given, with the method matching `level`.
+    pub(crate) fn write_value(&self, level: Level, message: &JsValue, extra: Option<&JsValue>) {
+        use web_sys::console;
+
+        // `console.trace` dumps a stack trace with every call, so trace messages go
+        // to `console.debug` instead.
+        match (level, extra) {
+            (Level::Trace | Level::Debug, None) => console::debug_1(message),
+            (Level::Trace | Level::Debug, Some(extra)) => console::debug_2(message, extra),
+            (Level::Info, None) => console::info_1(message),
+            (Level::Info, Some(extra)) => console::info_2(message, extra),
+            (Level::Warn, None) => console::warn_1(message),
+            (Level::Warn, Some(extra)) => console::warn_2(message, extra),
+            (Level::Error, None) => console::error_1(message),
+            (Level::Error, Some(extra)) => console::error_2(message, extra),
+        }
+    }
+}
+
+impl Sink for ConsoleSink {
+    fn write(&self, level: Level, message: &str) {
+        self.write_value(level, &JsValue::from_str(message), None);
+    }
+}
+
+/// Keeps every message in memory. Clones share the same buffer, so one copy can be
+/// installed with [`set_sink`] while another is inspected.
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    entries: Arc<Mutex<Vec<(Level, String)>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    pub fn entries(&self) -> Vec<(Level, String)> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl Sink for MemorySink {
+    fn write(&self, level: Level, message: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .push((level, message.to_owned()));
+    }
+}
+
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static SINK: RwLock<Option<Box<dyn Sink>>> = RwLock::new(None);
+
+/// Replaces the sink messages are written to. The console is used until this is called.
+pub fn set_sink(sink: impl Sink + 'static) {
+    *SINK.write().unwrap() = Some(Box::new(sink));
+}
+
+/// Sets the least severe level that will still reach the console.
+#[wasm_bindgen]
+pub fn set_log_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+    log::set_max_level(level.into());
+}
+
+/// Returns the current minimum level.
+#[wasm_bindgen]
+pub fn log_level() -> Level {
+    Level::from_u8(MIN_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Returns whether a message at `level` would currently be written.
+pub fn enabled(level: Level) -> bool {
+    level >= log_level()
+}
+
+/// Writes `message` to the console method matching `level`.
+#[wasm_bindgen]
+pub fn log_msg(level: Level, message: &str) {
+    if !enabled(level) {
+        return;
+    }
+
+    match &*SINK.read().unwrap() {
+        Some(sink) => sink.write(level, message),
+        None => ConsoleSink.write(level, message),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::MutexGuard;
+
+    use super::*;
+
+    /// The sink and level are global, so tests that install a sink take turns.
+    pub(crate) fn capture(level: Level) -> (MutexGuard<'static, ()>, MemorySink) {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let sink = MemorySink::new();
+        set_sink(sink.clone());
+        set_log_level(level);
+        (guard, sink)
+    }
+
+    #[test]
+    fn messages_below_the_level_are_dropped() {
+        let (_guard, sink) = capture(Level::Warn);
+        log_msg(Level::Info, "quiet");
+        log_msg(Level::Warn, "careful");
+        log_msg(Level::Error, "broken");
+        assert_eq!(
+            sink.entries(),
+            [
+                (Level::Warn, "careful".to_owned()),
+                (Level::Error, "broken".to_owned()),
+            ]
+        );
+        assert!(!enabled(Level::Info));
+        assert_eq!(log_level(), Level::Warn);
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let sink = MemorySink::new();
+        let installed = sink.clone();
+        installed.write(Level::Debug, "one");
+        assert_eq!(sink.entries(), [(Level::Debug, "one".to_owned())]);
+        sink.clear();
+        assert!(installed.entries().is_empty());
+    }
+
+    #[test]
+    fn levels_convert_from_log() {
+        assert_eq!(Level::from(log::Level::Trace), Level::Trace);
+        assert_eq!(Level::from(log::Level::Error), Level::Error);
+        assert_eq!(log::LevelFilter::from(Level::Info), log::LevelFilter::Info);
+        assert!(Level::Trace < Level::Error);
+    }
+}
@@ -0,0 +1,167 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{Location, PanicHookInfo};
+use std::sync::Once;
+
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static CALLBACK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// Installs a panic hook that logs panics with `console.error` and passes them to the
+/// callback set with [`set_panic_callback`]. Installing more than once does nothing.
+#[wasm_bindgen]
+pub fn install_panic_hook() {
+    INSTALL.call_once(|| std::panic::set_hook(Box::new(hook)));
+}
+
+/// Registers a function that receives every panic as a JS `Error`, with `location`
+/// set to `file:line:column`. Passing `undefined` removes it.
+#[wasm_bindgen]
+pub fn set_panic_callback(callback: Option<js_sys::Function>) {
+    CALLBACK.with(|cell| *cell.borrow_mut() = callback);
+}
+
+fn hook(info: &PanicHookInfo) {
+    let message = panic_message(info);
+    let location = format_location(info.location());
+    let backtrace = demangle_backtrace(&capture_backtrace());
+
+    let report = format!("panicked at {location}:\n{message}\n\nStack:\n\n{backtrace}");
+    web_sys::console::error_1(&JsValue::from_str(&report));
+
+    CALLBACK.with(|cell| {
+        if let Some(callback) = &*cell.borrow() {
+            let error = js_sys::Error::new(&message);
+            error.set_name("RustPanic");
+            // Plain property writes on an `Error` object cannot fail.
+            let _ = js_sys::Reflect::set(&error, &"location".into(), &location.into());
+            let _ = js_sys::Reflect::set(&error, &"stack".into(), &report.into());
+            // A throwing callback must not turn into a second panic inside the hook.
+            let _ = callback.call1(&JsValue::NULL, &error);
+        }
+    });
+}
+
+/// Extracts the message from a panic payload, which is a `&str` or `String` for every
+/// `panic!` call with a message.
+pub fn panic_message(info: &PanicHookInfo) -> String {
+    payload_message(info.payload())
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
+
+/// Formats where a panic happened as `file:line:column`.
+fn format_location(location: Option<&Location>) -> String {
+    location
+        .map(|location| {
+            format!(
+                "{}:{}:{}",
+                location.file(),
+                location.line(),
+                location.column()
+            )
+        })
+        .unwrap_or_else(|| "<unknown>".to_owned())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn capture_backtrace() -> String {
+    let error = js_sys::Error::new("");
+    js_sys::Reflect::get(&error, &"stack".into())
+        .ok()
+        .and_then(|stack| stack.as_string())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn capture_backtrace() -> String {
+    std::backtrace::Backtrace::force_capture().to_string()
+}
+
+/// Replaces every mangled Rust symbol in a stack trace with its demangled form, minus
+/// the hash suffix.
+pub fn demangle_backtrace(backtrace: &str) -> String {
+    let mut output = String::with_capacity(backtrace.len());
+    let mut rest = backtrace;
+    while let Some(start) = find_symbol_start(rest) {
+        output.push_str(&rest[..start]);
+        let symbol_len = rest[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(rest.len() - start);
+        let symbol = &rest[start..start + symbol_len];
+        match rustc_demangle::try_demangle(symbol) {
+            Ok(demangled) => output.push_str(&format!("{demangled:#}")),
+            Err(_) => output.push_str(symbol),
+        }
+        rest = &rest[start + symbol_len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Finds the next `_ZN` (legacy) or `_R` (v0) prefix that starts a word.
+fn find_symbol_start(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (0..bytes.len()).find(|&i| {
+        let at_word_start =
+            i == 0 || !(bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        at_word_start && (bytes[i..].starts_with(b"_ZN") || bytes[i..].starts_with(b"_R"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_legacy_symbols() {
+        let line = "   3: _ZN4core9panicking9panic_fmt17h0123456789abcdefE at src/lib.rs:1";
+        assert_eq!(
+            demangle_backtrace(line),
+            "   3: core::panicking::panic_fmt at src/lib.rs:1"
+        );
+    }
+
+    #[test]
+    fn demangles_v0_symbols() {
+        let trace = "wasm-function[12]:_RNvCs15kBYyAo9fc_7mycrate7example\n";
+        assert_eq!(
+            demangle_backtrace(trace),
+            "wasm-function[12]:mycrate::example\n"
+        );
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        let trace =
+            "Error\n    at http://localhost/index.js:3:7\n    at my_ZNfunction\n_Rnot a symbol\n";
+        assert_eq!(demangle_backtrace(trace), trace);
+        assert_eq!(demangle_backtrace(""), "");
+    }
+
+    #[test]
+    fn formats_messages_and_locations() {
+        assert_eq!(payload_message(&"static"), "static");
+        assert_eq!(payload_message(&"owned".to_owned()), "owned");
+        assert_eq!(payload_message(&42), "Box<dyn Any>");
+
+        let location = Location::caller();
+        assert_eq!(
+            format_location(Some(location)),
+            format!("{}:{}:{}", file!(), location.line(), location.column())
+        );
+        assert_eq!(format_location(None), "<unknown>");
+    }
+}
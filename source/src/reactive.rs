@@ -0,0 +1,724 @@
+//! Fine-grained reactivity: signals hold values, memos derive values from them and
+//! effects run side effects whenever the values they read change.
+//!
+//! Dependencies are tracked automatically while a memo or effect runs. Writes mark
+//! direct dependents dirty and everything further downstream as needing a check, and
+//! effects then pull fresh values through the graph. Every node is recomputed at most
+//! once per change and only after all of its sources are up to date, so effects never
+//! observe a half-updated graph.
+//!
+//! The runtime is thread-local and knows nothing about the DOM.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Clean,
+    /// A source further upstream changed; the node's own sources need checking.
+    Check,
+    /// A direct source changed; the node must be recomputed.
+    Dirty,
+}
+
+type Value = Rc<RefCell<Box<dyn Any>>>;
+
+enum Kind {
+    Signal,
+    Memo {
+        compute: Rc<dyn Fn() -> Box<dyn Any>>,
+        equals: fn(&dyn Any, &dyn Any) -> bool,
+    },
+    Effect {
+        run: Rc<RefCell<dyn FnMut()>>,
+    },
+    Scope,
+}
+
+struct Node {
+    kind: Kind,
+    value: Option<Value>,
+    state: State,
+    sources: Vec<NodeId>,
+    subscribers: Vec<NodeId>,
+    /// Nodes created while this one was running, disposed before it runs again.
+    children: Vec<NodeId>,
+    cleanups: Vec<Box<dyn FnOnce()>>,
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+#[derive(Default)]
+struct Runtime {
+    slots: RefCell<Vec<Slot>>,
+    free: RefCell<Vec<u32>>,
+    observer: Cell<Option<NodeId>>,
+    owner: Cell<Option<NodeId>>,
+    batch_depth: Cell<u32>,
+    flushing: Cell<bool>,
+    pending: RefCell<VecDeque<NodeId>>,
+}
+
+thread_local! {
+    static RUNTIME: Runtime = Runtime::default();
+}
+
+fn with_runtime<R>(f: impl FnOnce(&Runtime) -> R) -> R {
+    RUNTIME.with(f)
+}
+
+impl Runtime {
+    fn create(&self, kind: Kind, value: Option<Value>, state: State) -> NodeId {
+        let node = Node {
+            kind,
+            value,
+            state,
+            sources: Vec::new(),
+            subscribers: Vec::new(),
+            children: Vec::new(),
+            cleanups: Vec::new(),
+        };
+
+        let mut slots = self.slots.borrow_mut();
+        let id = match self.free.borrow_mut().pop() {
+            Some(index) => {
+                let slot = &mut slots[index as usize];
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        if let Some(owner) = self.owner.get() {
+            if let Some(owner) = Self::node_mut(&mut slots, owner) {
+                owner.children.push(id);
+            }
+        }
+        id
+    }
+
+    fn node_mut(slots: &mut [Slot], id: NodeId) -> Option<&mut Node> {
+        let slot = slots.get_mut(id.index as usize)?;
+        if slot.generation == id.generation {
+            slot.node.as_mut()
+        } else {
+            None
+        }
+    }
+
+    fn with_node<R>(&self, id: NodeId, f: impl FnOnce(&mut Node) -> R) -> Option<R> {
+        Self::node_mut(&mut self.slots.borrow_mut(), id).map(f)
+    }
+
+    fn value(&self, id: NodeId) -> Value {
+        self.with_node(id, |node| node.value.clone())
+            .flatten()
+            .expect("reactive value used after it was disposed")
+    }
+
+    /// Records that the running memo or effect depends on `id`.
+    fn track(&self, id: NodeId) {
+        let Some(observer) = self.observer.get() else {
+            return;
+        };
+        let mut slots = self.slots.borrow_mut();
+        let Some(node) = Self::node_mut(&mut slots, observer) else {
+            return;
+        };
+        if !node.sources.contains(&id) {
+            node.sources.push(id);
+            if let Some(source) = Self::node_mut(&mut slots, id) {
+                source.subscribers.push(observer);
+            }
+        }
+    }
+
+    fn state(&self, id: NodeId) -> Option<State> {
+        self.with_node(id, |node| node.state)
+    }
+
+    fn set_state(&self, id: NodeId, state: State) {
+        self.with_node(id, |node| node.state = state);
+    }
+
+    fn mark_subscribers(&self, id: NodeId, state: State) {
+        let subscribers = self
+            .with_node(id, |node| node.subscribers.clone())
+            .unwrap_or_default();
+        for subscriber in subscribers {
+            self.mark(subscriber, state);
+        }
+    }
+
+    /// Raises `id` to `state`, queueing it if it is an effect. Nodes that were already
+    /// stale have stale descendants too, so the walk stops there.
+    fn mark(&self, id: NodeId, state: State) {
+        let was_clean = self.with_node(id, |node| {
+            if node.state >= state {
+                return false;
+            }
+            let was_clean = node.state == State::Clean;
+            node.state = state;
+            if was_clean && matches!(node.kind, Kind::Effect { .. }) {
+                self.pending.borrow_mut().push_back(id);
+            }
+            was_clean
+        });
+        if was_clean == Some(true) {
+            self.mark_subscribers(id, State::Check);
+        }
+    }
+
+    fn is_memo(&self, id: NodeId) -> bool {
+        self.with_node(id, |node| matches!(node.kind, Kind::Memo { .. }))
+            .unwrap_or(false)
+    }
+
+    /// Brings `id` up to date, recomputing it only if one of its sources has changed.
+    fn update_if_necessary(&self, id: NodeId) {
+        if self.state(id) == Some(State::Check) {
+            let sources = self
+                .with_node(id, |node| node.sources.clone())
+                .unwrap_or_default();
+            for source in sources {
+                if self.is_memo(source) {
+                    self.update_if_necessary(source);
+                }
+                if self.state(id) == Some(State::Dirty) {
+                    break;
+                }
+            }
+        }
+
+        match self.state(id) {
+            Some(State::Dirty) => {
+                self.set_state(id, State::Clean);
+                self.recompute(id);
+            }
+            Some(_) => self.set_state(id, State::Clean),
+            None => {}
+        }
+    }
+
+    fn recompute(&self, id: NodeId) {
+        self.reset(id);
+
+        enum Job {
+            Memo(Rc<dyn Fn() -> Box<dyn Any>>, fn(&dyn Any, &dyn Any) -> bool),
+            Effect(Rc<RefCell<dyn FnMut()>>),
+        }
+        let job = self.with_node(id, |node| match &node.kind {
+            Kind::Memo { compute, equals } => Some(Job::Memo(compute.clone(), *equals)),
+            Kind::Effect { run } => Some(Job::Effect(run.clone())),
+            Kind::Signal | Kind::Scope => None,
+        });
+        let Some(Some(job)) = job else {
+            return;
+        };
+
+        let observer = self.observer.replace(Some(id));
+        let owner = self.owner.replace(Some(id));
+        match job {
+            Job::Memo(compute, equals) => {
+                let value = compute();
+                let changed = self
+                    .with_node(id, |node| {
+                        let changed = match &node.value {
+                            Some(old) => !equals(&**old.borrow(), &*value),
+                            None => true,
+                        };
+                        if changed {
+                            node.value = Some(Rc::new(RefCell::new(value)));
+                        }
+                        changed
+                    })
+                    .unwrap_or(false);
+                if changed {
+                    self.mark_subscribers(id, State::Dirty);
+                }
+            }
+            Job::Effect(run) => (run.borrow_mut())(),
+        }
+        self.observer.set(observer);
+        self.owner.set(owner);
+    }
+
+    /// Runs cleanups, disposes children and forgets the sources of `id` so it can run
+    /// again from scratch.
+    fn reset(&self, id: NodeId) {
+        let Some((cleanups, children, sources)) = self.with_node(id, |node| {
+            (
+                std::mem::take(&mut node.cleanups),
+                std::mem::take(&mut node.children),
+                std::mem::take(&mut node.sources),
+            )
+        }) else {
+            return;
+        };
+
+        for cleanup in cleanups.into_iter().rev() {
+            cleanup();
+        }
+        for child in children {
+            self.dispose(child);
+        }
+        for source in sources {
+            self.with_node(source, |node| node.subscribers.retain(|&sub| sub != id));
+        }
+    }
+
+    fn dispose(&self, id: NodeId) {
+        self.reset(id);
+        let mut slots = self.slots.borrow_mut();
+        if let Some(slot) = slots.get_mut(id.index as usize) {
+            if slot.generation == id.generation && slot.node.take().is_some() {
+                slot.generation += 1;
+                self.free.borrow_mut().push(id.index);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if self.batch_depth.get() > 0 || self.flushing.replace(true) {
+            return;
+        }
+        loop {
+            let next = self.pending.borrow_mut().pop_front();
+            let Some(id) = next else { break };
+            self.update_if_necessary(id);
+        }
+        self.flushing.set(false);
+    }
+}
+
+/// A reactive value that can be read and written. Reading it inside a memo or effect
+/// makes that memo or effect depend on it.
+pub struct Signal<T: 'static> {
+    id: NodeId,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> Clone for Signal<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: 'static> Copy for Signal<T> {}
+
+impl<T: 'static> Signal<T> {
+    pub fn new(value: T) -> Signal<T> {
+        let value: Value = Rc::new(RefCell::new(Box::new(value)));
+        let id = with_runtime(|rt| rt.create(Kind::Signal, Some(value), State::Clean));
+        Signal {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    /// Calls `f` with a reference to the value.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let value = with_runtime(|rt| {
+            rt.track(self.id);
+            rt.value(self.id)
+        });
+        let value = value.borrow();
+        f(value.downcast_ref().unwrap())
+    }
+
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    /// Reads the value without depending on it.
+    pub fn get_untracked(&self) -> T
+    where
+        T: Clone,
+    {
+        untrack(|| self.get())
+    }
+
+    pub fn set(&self, value: T) {
+        with_runtime(|rt| {
+            let value: Value = Rc::new(RefCell::new(Box::new(value)));
+            rt.with_node(self.id, |node| node.value = Some(value))
+                .expect("reactive value used after it was disposed");
+            rt.mark_subscribers(self.id, State::Dirty);
+            rt.flush();
+        });
+    }
+
+    /// Changes the value in place. `f` must not read this signal.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        with_runtime(|rt| {
+            let value = rt.value(self.id);
+            f(value.borrow_mut().downcast_mut().unwrap());
+            rt.mark_subscribers(self.id, State::Dirty);
+            rt.flush();
+        });
+    }
+
+    pub fn dispose(self) {
+        with_runtime(|rt| rt.dispose(self.id));
+    }
+}
+
+/// A value derived from other signals and memos. It is computed lazily, cached, and
+/// only notifies dependents when the result differs from the previous one.
+pub struct Memo<T: 'static> {
+    id: NodeId,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> Clone for Memo<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: 'static> Copy for Memo<T> {}
+
+impl<T: PartialEq + 'static> Memo<T> {
+    pub fn new(f: impl Fn() -> T + 'static) -> Memo<T> {
+        let kind = Kind::Memo {
+            compute: Rc::new(move || Box::new(f()) as Box<dyn Any>),
+            equals: |old, new| old.downcast_ref::<T>() == new.downcast_ref::<T>(),
+        };
+        let id = with_runtime(|rt| rt.create(kind, None, State::Dirty));
+        Memo {
+            id,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> Memo<T> {
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let value = with_runtime(|rt| {
+            rt.update_if_necessary(self.id);
+            rt.track(self.id);
+            rt.value(self.id)
+        });
+        let value = value.borrow();
+        f(value.downcast_ref().unwrap())
+    }
+
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.with(T::clone)
+    }
+
+    pub fn dispose(self) {
+        with_runtime(|rt| rt.dispose(self.id));
+    }
+}
+
+/// A side effect that runs once immediately and again whenever anything it read last
+/// time changes.
+#[derive(Clone, Copy)]
+pub struct Effect {
+    id: NodeId,
+}
+
+impl Effect {
+    pub fn new(f: impl FnMut() + 'static) -> Effect {
+        let run: Rc<RefCell<dyn FnMut()>> = Rc::new(RefCell::new(f));
+        let id = with_runtime(|rt| rt.create(Kind::Effect { run }, None, State::Dirty));
+        // Writes made by the first run are applied once it has finished, like the writes
+        // of every later run.
+        batch(|| with_runtime(|rt| rt.update_if_necessary(id)));
+        Effect { id }
+    }
+
+    /// Stops the effect and runs its cleanups.
+    pub fn dispose(self) {
+        with_runtime(|rt| rt.dispose(self.id));
+    }
+}
+
+/// Owns every signal, memo and effect created inside it, so they can be disposed
+/// together.
+#[derive(Clone, Copy)]
+pub struct Scope {
+    id: NodeId,
+}
+
+impl Scope {
+    pub fn new<R>(f: impl FnOnce() -> R) -> (R, Scope) {
+        let (id, owner) = with_runtime(|rt| {
+            let id = rt.create(Kind::Scope, None, State::Clean);
+            (id, rt.owner.replace(Some(id)))
+        });
+        let result = f();
+        with_runtime(|rt| rt.owner.set(owner));
+        (result, Scope { id })
+    }
+
+    pub fn dispose(self) {
+        with_runtime(|rt| rt.dispose(self.id));
+    }
+}
+
+/// Runs `f`, holding back effects until it returns so that several writes only cause
+/// one update.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    with_runtime(|rt| rt.batch_depth.set(rt.batch_depth.get() + 1));
+    let result = f();
+    with_runtime(|rt| {
+        rt.batch_depth.set(rt.batch_depth.get() - 1);
+        rt.flush();
+    });
+    result
+}
+
+/// Runs `f` without recording anything it reads as a dependency.
+pub fn untrack<R>(f: impl FnOnce() -> R) -> R {
+    let observer = with_runtime(|rt| rt.observer.take());
+    let result = f();
+    with_runtime(|rt| rt.observer.set(observer));
+    result
+}
+
+/// Registers `f` to run before the current memo or effect runs again, or when it or its
+/// scope is disposed. Does nothing outside of one.
+pub fn on_cleanup(f: impl FnOnce() + 'static) {
+    with_runtime(|rt| {
+        if let Some(owner) = rt.owner.get() {
+            rt.with_node(owner, |node| node.cleanups.push(Box::new(f)));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A log that effects can push to from `'static` closures.
+    #[derive(Clone, Default)]
+    struct Log<T>(Rc<RefCell<Vec<T>>>);
+
+    impl<T: Clone> Log<T> {
+        fn push(&self, entry: T) {
+            self.0.borrow_mut().push(entry);
+        }
+
+        fn take(&self) -> Vec<T> {
+            std::mem::take(&mut self.0.borrow_mut())
+        }
+    }
+
+    #[test]
+    fn effects_run_immediately_and_on_change() {
+        let count = Signal::new(1);
+        let log = Log::default();
+        Effect::new({
+            let log = log.clone();
+            move || log.push(count.get())
+        });
+        count.set(2);
+        count.update(|count| *count += 1);
+        assert_eq!(log.take(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn diamonds_update_without_glitches() {
+        let a = Signal::new(1);
+        let double = Memo::new(move || a.get() * 2);
+        let next = Memo::new(move || a.get() + 1);
+        let log = Log::default();
+        Effect::new({
+            let log = log.clone();
+            move || log.push((double.get(), next.get()))
+        });
+        a.set(5);
+        // Never (10, 2) or (2, 6): both memos are fresh before the effect runs, and it
+        // runs once per write.
+        assert_eq!(log.take(), [(2, 2), (10, 6)]);
+    }
+
+    #[test]
+    fn unchanged_memos_stop_propagation() {
+        let number = Signal::new(2);
+        let computed = Rc::new(Cell::new(0));
+        let even = Memo::new({
+            let computed = computed.clone();
+            move || {
+                computed.set(computed.get() + 1);
+                number.get() % 2 == 0
+            }
+        });
+        let log = Log::default();
+        Effect::new({
+            let log = log.clone();
+            move || log.push(even.get())
+        });
+        number.set(4);
+        number.set(5);
+        assert_eq!(log.take(), [true, false]);
+        assert_eq!(computed.get(), 3);
+    }
+
+    #[test]
+    fn memos_are_lazy() {
+        let number = Signal::new(1);
+        let computed = Rc::new(Cell::new(0));
+        let square = Memo::new({
+            let computed = computed.clone();
+            move || {
+                computed.set(computed.get() + 1);
+                number.get() * number.get()
+            }
+        });
+        number.set(2);
+        number.set(3);
+        assert_eq!(computed.get(), 0);
+        assert_eq!(square.get(), 9);
+        assert_eq!(square.get(), 9);
+        assert_eq!(computed.get(), 1);
+    }
+
+    #[test]
+    fn effects_run_in_the_order_they_were_notified() {
+        let number = Signal::new(0);
+        let log = Log::default();
+        for name in ["first", "second"] {
+            Effect::new({
+                let log = log.clone();
+                move || log.push((name, number.get()))
+            });
+        }
+        log.take();
+        number.set(1);
+        assert_eq!(log.take(), [("first", 1), ("second", 1)]);
+    }
+
+    #[test]
+    fn batches_apply_writes_together() {
+        let first = Signal::new("Ada");
+        let last = Signal::new("Lovelace");
+        let log = Log::default();
+        Effect::new({
+            let log = log.clone();
+            move || log.push(format!("{} {}", first.get(), last.get()))
+        });
+        batch(|| {
+            first.set("Grace");
+            last.set("Hopper");
+        });
+        assert_eq!(log.take(), ["Ada Lovelace", "Grace Hopper"]);
+    }
+
+    #[test]
+    fn writes_from_effects_apply_after_they_finish() {
+        let source = Signal::new(1);
+        let copy = Signal::new(0);
+        let log = Log::default();
+        Effect::new(move || copy.set(source.get() * 10));
+        Effect::new({
+            let log = log.clone();
+            move || log.push(copy.get())
+        });
+        source.set(2);
+        assert_eq!(log.take(), [10, 20]);
+    }
+
+    #[test]
+    fn dependencies_are_tracked_per_run() {
+        let use_a = Signal::new(true);
+        let a = Signal::new("a");
+        let b = Signal::new("b");
+        let log = Log::default();
+        Effect::new({
+            let log = log.clone();
+            move || log.push(if use_a.get() { a.get() } else { b.get() })
+        });
+        use_a.set(false);
+        // `a` is no longer read, so writing it does nothing.
+        a.set("A");
+        b.set("B");
+        assert_eq!(log.take(), ["a", "b", "B"]);
+    }
+
+    #[test]
+    fn untracked_reads_are_not_dependencies() {
+        let tracked = Signal::new(0);
+        let untracked = Signal::new(0);
+        let runs = Rc::new(Cell::new(0));
+        Effect::new({
+            let runs = runs.clone();
+            move || {
+                tracked.get();
+                untracked.get_untracked();
+                runs.set(runs.get() + 1);
+            }
+        });
+        untracked.set(1);
+        assert_eq!(runs.get(), 1);
+        tracked.set(1);
+        assert_eq!(runs.get(), 2);
+    }
+
+    #[test]
+    fn cleanups_run_before_reruns_and_on_dispose() {
+        let number = Signal::new(0);
+        let log = Log::default();
+        let effect = Effect::new({
+            let log = log.clone();
+            move || {
+                let value = number.get();
+                log.push(format!("run {value}"));
+                let log = log.clone();
+                on_cleanup(move || log.push(format!("clean {value}")));
+            }
+        });
+        number.set(1);
+        effect.dispose();
+        number.set(2);
+        assert_eq!(log.take(), ["run 0", "clean 0", "run 1", "clean 1"]);
+    }
+
+    #[test]
+    fn disposing_a_scope_stops_its_effects() {
+        let number = Signal::new(0);
+        let log = Log::default();
+        let ((), scope) = Scope::new(|| {
+            Effect::new({
+                let log = log.clone();
+                move || log.push(number.get())
+            });
+        });
+        number.set(1);
+        scope.dispose();
+        number.set(2);
+        assert_eq!(log.take(), [0, 1]);
+    }
+}
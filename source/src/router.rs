@@ -0,0 +1,475 @@
+//! Client-side routing. A [`Router`] matches URLs against patterns such as `/users/:id`
+//! or `/files/*path` and is pure Rust; [`start`] and [`navigate`] connect it to the
+//! browser's History API.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::{document, Listener};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    /// Matches the rest of the path, including nothing at all.
+    Wildcard(Option<String>),
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<Segment>, PatternError> {
+    let segments: Vec<Segment> = split_path(pattern)
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(name.to_owned())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Wildcard((!name.is_empty()).then(|| name.to_owned()))
+            } else {
+                Segment::Static(segment.to_owned())
+            }
+        })
+        .collect();
+    match segments
+        .iter()
+        .position(|segment| matches!(segment, Segment::Wildcard(_)))
+    {
+        Some(index) if index != segments.len() - 1 => Err(PatternError {
+            pattern: pattern.to_owned(),
+        }),
+        _ => Ok(segments),
+    }
+}
+
+/// A route pattern with a wildcard before its last segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    pattern: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a wildcard must be the last segment of {:?}",
+            self.pattern
+        )
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Captured parameters in the order they appear in the path.
+type Params = Vec<(String, String)>;
+
+/// A pattern and the value it stands for, usually a view. Child patterns are relative
+/// to their parent, and a child with the empty pattern matches the parent's own path.
+#[derive(Clone, Debug)]
+pub struct Route<T> {
+    pattern: Vec<Segment>,
+    value: T,
+    children: Vec<Route<T>>,
+}
+
+impl<T> Route<T> {
+    /// `pattern` is made of `/`-separated segments: literal text, `:name` for a single
+    /// segment captured as a parameter, or a final `*name` (or bare `*`) for the rest of
+    /// the path.
+    ///
+    /// # Panics
+    ///
+    /// If a wildcard comes before the last segment. [`Route::try_new`] returns an error
+    /// instead, for patterns that aren't written into the code.
+    pub fn new(pattern: &str, value: T) -> Route<T> {
+        Route::try_new(pattern, value).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    pub fn try_new(pattern: &str, value: T) -> Result<Route<T>, PatternError> {
+        Ok(Route {
+            pattern: parse_pattern(pattern)?,
+            value,
+            children: Vec::new(),
+        })
+    }
+
+    pub fn child(mut self, route: Route<T>) -> Route<T> {
+        self.children.push(route);
+        self
+    }
+
+    /// Matches the start of `segments`, returning the captured parameters and what is
+    /// left over.
+    fn match_prefix<'p>(&self, segments: &'p [&'p str]) -> Option<(Params, &'p [&'p str])> {
+        let mut params = Vec::new();
+        let mut rest = segments;
+        for segment in &self.pattern {
+            match segment {
+                Segment::Wildcard(name) => {
+                    if let Some(name) = name {
+                        let value = rest.iter().map(|s| decode(s)).collect::<Vec<_>>();
+                        params.push((name.clone(), value.join("/")));
+                    }
+                    return Some((params, &[]));
+                }
+                Segment::Static(text) => {
+                    let (first, tail) = rest.split_first()?;
+                    if decode(first) != *text {
+                        return None;
+                    }
+                    rest = tail;
+                }
+                Segment::Param(name) => {
+                    let (first, tail) = rest.split_first()?;
+                    params.push((name.clone(), decode(first)));
+                    rest = tail;
+                }
+            }
+        }
+        Some((params, rest))
+    }
+}
+
+type Recognized<'a, T> = (Vec<&'a T>, Params);
+
+/// Tries `routes` in order. A route whose pattern matches a prefix of the path is
+/// entered; if none of its children match the rest, it matches only if nothing is left.
+fn recognize_in<'a, T>(routes: &'a [Route<T>], segments: &[&str]) -> Option<Recognized<'a, T>> {
+    for route in routes {
+        let Some((mut params, rest)) = route.match_prefix(segments) else {
+            continue;
+        };
+        if let Some((mut chain, child_params)) = recognize_in(&route.children, rest) {
+            chain.insert(0, &route.value);
+            params.extend(child_params);
+            return Some((chain, params));
+        }
+        if rest.is_empty() {
+            return Some((vec![&route.value], params));
+        }
+    }
+    None
+}
+
+/// An ordered set of routes. The first route that matches wins.
+#[derive(Clone, Debug)]
+pub struct Router<T> {
+    routes: Vec<Route<T>>,
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Router { routes: Vec::new() }
+    }
+}
+
+impl<T> Router<T> {
+    pub fn new() -> Router<T> {
+        Router::default()
+    }
+
+    pub fn route(mut self, route: Route<T>) -> Router<T> {
+        self.routes.push(route);
+        self
+    }
+
+    /// Matches a URL made of a path, optionally followed by a query string and a
+    /// fragment, such as `/users/7?tab=posts#top`.
+    pub fn recognize(&self, url: &str) -> Option<Match<'_, T>> {
+        let url = url.split('#').next().unwrap_or_default();
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<&str> = split_path(path).collect();
+        let (routes, params) = recognize_in(&self.routes, &segments)?;
+        Some(Match {
+            routes,
+            params: params.into_iter().collect(),
+            query: Query::parse(query),
+        })
+    }
+}
+
+/// The result of matching a URL.
+#[derive(Debug)]
+pub struct Match<'a, T> {
+    /// The values of the matched route and its ancestors, outermost first.
+    pub routes: Vec<&'a T>,
+    pub params: BTreeMap<String, String>,
+    pub query: Query,
+}
+
+impl<'a, T> Match<'a, T> {
+    /// The value of the innermost matched route.
+    pub fn value(&self) -> &'a T {
+        self.routes.last().expect("a match has at least one route")
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+}
+
+/// A decoded query string. Keys may repeat and keep their order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query(Vec<(String, String)>);
+
+impl Query {
+    /// Parses `a=1&b=two+words`, with or without a leading `?`.
+    pub fn parse(query: &str) -> Query {
+        let query = query.strip_prefix('?').unwrap_or(query);
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (
+                    decode(&key.replace('+', " ")),
+                    decode(&value.replace('+', " ")),
+                )
+            })
+            .collect();
+        Query(pairs)
+    }
+
+    /// The first value for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Decodes `%XX` escapes. Malformed escapes are kept as they are.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = hex {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+type OnChange = Rc<dyn Fn(String)>;
+
+thread_local! {
+    static ON_CHANGE: RefCell<Option<OnChange>> = const { RefCell::new(None) };
+}
+
+fn window() -> web_sys::Window {
+    web_sys::window().expect_throw("no window; routing only works on the main thread")
+}
+
+/// The path, query string and fragment of the page's URL.
+pub fn current_url() -> String {
+    let location = window().location();
+    let part = |value: Result<String, JsValue>| value.unwrap_or_default();
+    part(location.pathname()) + &part(location.search()) + &part(location.hash())
+}
+
+/// Calls `on_change` with the new URL whenever it changes through [`navigate`], a
+/// same-origin link or the back and forward buttons. Routing stops when the returned
+/// handle is dropped.
+pub fn start(on_change: impl Fn(String) + 'static) -> History {
+    ON_CHANGE.with(|handler| *handler.borrow_mut() = Some(Rc::new(on_change)));
+    History {
+        _popstate: Listener::new(&window(), "popstate", |_| notify()),
+        _click: Listener::new(&document(), "click", intercept_link),
+    }
+}
+
+/// Keeps the router listening to the browser.
+pub struct History {
+    _popstate: Listener,
+    _click: Listener,
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        ON_CHANGE.with(|handler| handler.borrow_mut().take());
+    }
+}
+
+/// Adds `path` to the session history and routes to it.
+#[wasm_bindgen]
+pub fn navigate(path: &str) {
+    window()
+        .history()
+        .and_then(|history| history.push_state_with_url(&JsValue::NULL, "", Some(path)))
+        .expect_throw("failed to push history state");
+    notify();
+}
+
+fn notify() {
+    // Cloned out so the handler can call `navigate` itself.
+    let handler = ON_CHANGE.with(|handler| handler.borrow().clone());
+    if let Some(handler) = handler {
+        handler(current_url());
+    }
+}
+
+/// Routes plain left clicks on same-origin links instead of letting the browser load
+/// the page.
+fn intercept_link(event: web_sys::Event) {
+    let Ok(event) = event.dyn_into::<web_sys::MouseEvent>() else {
+        return;
+    };
+    if event.default_prevented()
+        || event.button() != 0
+        || event.meta_key()
+        || event.ctrl_key()
+        || event.shift_key()
+        || event.alt_key()
+    {
+        return;
+    }
+    let Some(anchor) = event
+        .target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .and_then(|element| element.closest("a[href]").ok().flatten())
+    else {
+        return;
+    };
+    if anchor.has_attribute("download")
+        || anchor
+            .get_attribute("target")
+            .is_some_and(|target| target != "_self")
+    {
+        return;
+    }
+
+    let location = window().location();
+    let (Some(href), Ok(base)) = (anchor.get_attribute("href"), location.href()) else {
+        return;
+    };
+    let Ok(url) = web_sys::Url::new_with_base(&href, &base) else {
+        return;
+    };
+    if Ok(url.origin()) != location.origin() {
+        return;
+    }
+    let path = url.pathname() + &url.search();
+    // Leave jumps within the current page to the browser.
+    let current = current_url();
+    if !url.hash().is_empty() && current.split('#').next() == Some(&path) {
+        return;
+    }
+    event.prevent_default();
+    navigate(&(path + &url.hash()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router<&'static str> {
+        Router::new()
+            .route(Route::new("/", "home"))
+            .route(
+                Route::new("/users", "users")
+                    .child(Route::new("", "user list"))
+                    .child(Route::new(":id", "user").child(Route::new("posts/:post", "post"))),
+            )
+            .route(Route::new("/files/*path", "file"))
+            .route(Route::new("*", "not found"))
+    }
+
+    #[test]
+    fn static_paths_match_exactly() {
+        let router = router();
+        assert_eq!(router.recognize("/").unwrap().value(), &"home");
+        assert_eq!(router.recognize("").unwrap().value(), &"home");
+        assert_eq!(
+            router.recognize("/users/").unwrap().routes,
+            [&"users", &"user list"]
+        );
+    }
+
+    #[test]
+    fn params_are_captured_through_nested_routes() {
+        let router = router();
+        let found = router.recognize("/users/7/posts/42").unwrap();
+        assert_eq!(found.routes, [&"users", &"user", &"post"]);
+        assert_eq!(found.param("id"), Some("7"));
+        assert_eq!(found.param("post"), Some("42"));
+        assert_eq!(router.recognize("/users/7").unwrap().value(), &"user");
+    }
+
+    #[test]
+    fn wildcards_take_the_rest_of_the_path() {
+        let router = router();
+        let found = router.recognize("/files/docs/a%20b.txt").unwrap();
+        assert_eq!(found.value(), &"file");
+        assert_eq!(found.param("path"), Some("docs/a b.txt"));
+        assert_eq!(router.recognize("/files").unwrap().param("path"), Some(""));
+        assert_eq!(
+            router.recognize("/nowhere/at/all").unwrap().value(),
+            &"not found"
+        );
+    }
+
+    #[test]
+    fn routes_are_tried_in_order() {
+        let router = Router::new()
+            .route(Route::new("/users/new", "new user"))
+            .route(Route::new("/users/:id", "user"));
+        assert_eq!(router.recognize("/users/new").unwrap().value(), &"new user");
+        assert!(router.recognize("/users/new/x").is_none());
+    }
+
+    #[test]
+    fn queries_and_fragments_are_split_off() {
+        let router = router();
+        let found = router
+            .recognize("/users/7?tab=posts&tag=a+b&tag=c#top")
+            .unwrap();
+        assert_eq!(found.param("id"), Some("7"));
+        assert_eq!(found.query.get("tab"), Some("posts"));
+        assert_eq!(found.query.get_all("tag").collect::<Vec<_>>(), ["a b", "c"]);
+    }
+
+    #[test]
+    fn malformed_escapes_are_kept() {
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%41"), "%zzA");
+        assert_eq!(Query::parse("?flag").get("flag"), Some(""));
+    }
+
+    #[test]
+    fn wildcards_must_come_last() {
+        let error = Route::try_new("/files/*path/edit", ()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "a wildcard must be the last segment of \"/files/*path/edit\""
+        );
+        assert!(Route::try_new("/files/*path", ()).is_ok());
+    }
+}
@@ -0,0 +1,671 @@
+//! A client for Server-Sent Events. [`EventSource`] is a [`Stream`] of the events a
+//! server pushes, reconnecting with a [`Reconnect`] policy and resuming from the last
+//! event ID it saw; [`Typed`] decodes them into an enum with a variant per event name.
+//!
+//! Unlike the browser's `EventSource`, requests go through `fetch`, so they can carry
+//! headers and the reconnection policy is up to the caller. The wire format is parsed
+//! by [`Parser`], which is plain Rust with no I/O.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+mod parser;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::dom::js_message;
+use crate::http;
+pub use crate::websocket::{Reconnect, Sleep};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{NativeBackend, NativeConnection};
+pub use parser::{Event, Parser};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The request could not be made as described, such as for a malformed URL.
+    InvalidRequest(String),
+    /// The server answered with something other than an event stream, such as an error
+    /// status. This is not retried.
+    Rejected(String),
+    /// The connection closed and will not be reopened, because reconnecting is turned
+    /// off or ran out of attempts.
+    Closed(String),
+    /// An event was not what [`Typed`] expected.
+    Decode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidRequest(message) => write!(f, "invalid request: {message}"),
+            Error::Rejected(message) => write!(f, "the server rejected the request: {message}"),
+            Error::Closed(reason) => write!(f, "the event stream closed: {reason}"),
+            Error::Decode(message) => write!(f, "failed to decode the event: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An event stream's settings. Nothing happens until [`connect`](Config::connect).
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// How to reconnect when the stream ends, or `None` to end with it. A `retry` sent
+    /// by the server replaces the policy's initial wait.
+    pub reconnect: Option<Reconnect>,
+    /// The ID of the last event seen by an earlier stream, to carry on from.
+    pub last_event_id: String,
+}
+
+impl Config {
+    pub fn new(url: impl Into<String>) -> Config {
+        Config {
+            url: url.into(),
+            headers: Vec::new(),
+            reconnect: Some(Reconnect {
+                initial: Duration::from_secs(3),
+                ..Reconnect::default()
+            }),
+            last_event_id: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Config {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: Reconnect) -> Config {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    pub fn no_reconnect(mut self) -> Config {
+        self.reconnect = None;
+        self
+    }
+
+    pub fn last_event_id(mut self, id: impl Into<String>) -> Config {
+        self.last_event_id = id.into();
+        self
+    }
+
+    /// Starts the request with the backend for the current target. Only a request the
+    /// backend rejects outright is an error here; failures to connect are retried.
+    pub fn connect(self) -> Result<EventSource, Error> {
+        self.connect_with(DefaultBackend::default())
+    }
+
+    pub fn connect_with<B: Backend>(self, backend: B) -> Result<EventSource<B>, Error> {
+        let mut source = EventSource {
+            last_event_id: self.last_event_id.clone(),
+            config: self,
+            backend,
+            state: State::Closed(None),
+            inbox: VecDeque::new(),
+            failures: 0,
+            retry: None,
+        };
+        source.state = source.open()?;
+        Ok(source)
+    }
+}
+
+/// A stream of events that reconnects when the response ends, asking the server to
+/// carry on after the last event received. It ends when closed on purpose, or with
+/// [`Error::Rejected`] or [`Error::Closed`] when it stops for good otherwise.
+pub struct EventSource<B: Backend = DefaultBackend> {
+    config: Config,
+    backend: B,
+    state: State<B::Connection>,
+    /// Events parsed but not yet taken from the stream.
+    inbox: VecDeque<Event>,
+    last_event_id: String,
+    /// Attempts to connect that failed since a response was last accepted.
+    failures: u32,
+    /// The wait before reconnecting that the server asked for.
+    retry: Option<Duration>,
+}
+
+enum State<C> {
+    /// A request in flight, accepted once `open` is set.
+    Live {
+        _connection: C,
+        signals: UnboundedReceiver<Signal>,
+        parser: Parser,
+        open: bool,
+    },
+    /// Waiting to try connecting again.
+    Waiting(Sleep),
+    /// Stopped for good, with the error the stream has yet to end with.
+    Closed(Option<Error>),
+}
+
+// Nothing is pinned structurally: the sleeps are boxed and the rest is only moved
+// through `&mut`.
+impl<B: Backend> Unpin for EventSource<B> {}
+
+impl EventSource {
+    /// Connects to `url` with the default [`Config`].
+    pub fn connect(url: impl Into<String>) -> Result<EventSource, Error> {
+        Config::new(url).connect()
+    }
+}
+
+impl<B: Backend> EventSource<B> {
+    pub fn url(&self) -> &str {
+        &self.config.url
+    }
+
+    /// The ID of the last event received, sent as `Last-Event-ID` when reconnecting.
+    pub fn last_event_id(&self) -> &str {
+        &self.last_event_id
+    }
+
+    /// Whether a response is being received at the moment.
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Live { open: true, .. })
+    }
+
+    /// Stops receiving. The stream ends after the events already received.
+    pub fn close(&mut self) {
+        self.state = State::Closed(None);
+    }
+
+    /// Decodes events into `T`, an enum with a variant per event name.
+    pub fn typed<T: DeserializeOwned>(self) -> Typed<T, B> {
+        Typed {
+            source: self,
+            types: PhantomData,
+        }
+    }
+
+    fn open(&self) -> Result<State<B::Connection>, Error> {
+        let mut headers = vec![
+            ("Accept".to_owned(), "text/event-stream".to_owned()),
+            ("Cache-Control".to_owned(), "no-cache".to_owned()),
+        ];
+        if !self.last_event_id.is_empty() {
+            headers.push(("Last-Event-ID".to_owned(), self.last_event_id.clone()));
+        }
+        headers.extend(self.config.headers.iter().cloned());
+        let (sender, signals) = mpsc::unbounded();
+        let connection = self.backend.connect(&self.config.url, &headers, sender)?;
+        Ok(State::Live {
+            _connection: connection,
+            signals,
+            parser: Parser::with_last_event_id(&self.last_event_id),
+            open: false,
+        })
+    }
+
+    /// Handles whatever has happened since the last call, arranging for `cx` to be woken
+    /// when something else does.
+    fn drive(&mut self, cx: &mut Context) {
+        loop {
+            match &mut self.state {
+                State::Live {
+                    signals,
+                    parser,
+                    open,
+                    ..
+                } => match signals.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Signal::Response {
+                        status,
+                        content_type,
+                    })) => {
+                        let event_stream = content_type
+                            .split(';')
+                            .next()
+                            .is_some_and(|mime| mime.trim() == "text/event-stream");
+                        if status != 200 || !event_stream {
+                            let error = Error::Rejected(format!(
+                                "status {status} with content type {content_type:?}"
+                            ));
+                            log::warn!("event stream {} failed: {error}", self.config.url);
+                            self.state = State::Closed(Some(error));
+                            continue;
+                        }
+                        *open = true;
+                        self.failures = 0;
+                        log::debug!("connected to {}", self.config.url);
+                    }
+                    Poll::Ready(Some(Signal::Data(data))) => {
+                        let events = parser.feed(&data);
+                        // An ID only counts once its event has been dispatched, so one
+                        // cut off by a disconnection is not resumed from.
+                        if let Some(event) = events.last() {
+                            self.last_event_id = event.id.clone();
+                        }
+                        self.inbox.extend(events);
+                        self.retry = parser.retry().or(self.retry);
+                    }
+                    Poll::Ready(Some(Signal::Closed(reason))) => self.disconnected(reason),
+                    Poll::Ready(None) => self.disconnected("the backend stopped".to_owned()),
+                    Poll::Pending => return,
+                },
+                State::Waiting(timer) => {
+                    if timer.as_mut().poll(cx).is_pending() {
+                        return;
+                    }
+                    self.state = self
+                        .open()
+                        .unwrap_or_else(|error| State::Closed(Some(error)));
+                }
+                State::Closed(_) => return,
+            }
+        }
+    }
+
+    /// Drops the connection and waits to open another, unless that has been tried
+    /// enough.
+    fn disconnected(&mut self, reason: String) {
+        if !self.is_open() {
+            self.failures += 1;
+        }
+        let url = &self.config.url;
+        let Some(reconnect) = self.config.reconnect.as_ref().filter(|reconnect| {
+            reconnect
+                .attempts
+                .is_none_or(|attempts| self.failures < attempts)
+        }) else {
+            log::warn!("event stream {url} closed: {reason}");
+            self.state = State::Closed(Some(Error::Closed(reason)));
+            return;
+        };
+        let delay = Reconnect {
+            initial: self.retry.unwrap_or(reconnect.initial),
+            ..reconnect.clone()
+        }
+        .delay(self.failures);
+        log::warn!("event stream {url} closed ({reason}), reconnecting in {delay:?}");
+        self.state = State::Waiting(self.backend.sleep(delay));
+    }
+}
+
+impl<B: Backend> Stream for EventSource<B> {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.drive(cx);
+        if let Some(event) = this.inbox.pop_front() {
+            return Poll::Ready(Some(Ok(event)));
+        }
+        match &mut this.state {
+            State::Closed(error) => Poll::Ready(error.take().map(Err)),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// An [`EventSource`] that decodes each event into `T` as if it were the JSON
+/// `{"<event name>": <data>}`, which is how serde represents an enum variant. Data that
+/// is not JSON is taken as a string, so `update` events with JSON data and plain-text
+/// `notice` events could be read into:
+///
+/// ```ignore
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "lowercase")]
+/// enum Message {
+///     Update(Update),
+///     Notice(String),
+/// }
+/// ```
+///
+/// An event that fails to decode, including one whose name has no variant, is an
+/// [`Error::Decode`] in the stream, which carries on after it.
+pub struct Typed<T, B: Backend = DefaultBackend> {
+    source: EventSource<B>,
+    types: PhantomData<fn() -> T>,
+}
+
+impl<T, B: Backend> Typed<T, B> {
+    pub fn get_ref(&self) -> &EventSource<B> {
+        &self.source
+    }
+
+    pub fn get_mut(&mut self) -> &mut EventSource<B> {
+        &mut self.source
+    }
+
+    pub fn into_inner(self) -> EventSource<B> {
+        self.source
+    }
+}
+
+impl<T: DeserializeOwned, B: Backend> Stream for Typed<T, B> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.get_mut().source.poll_next_unpin(cx));
+        Poll::Ready(item.map(|event| event.and_then(|event| decode(&event))))
+    }
+}
+
+fn decode<T: DeserializeOwned>(event: &Event) -> Result<T, Error> {
+    let data = serde_json::from_str(&event.data)
+        .unwrap_or_else(|_| serde_json::Value::String(event.data.clone()));
+    let tagged = serde_json::Value::Object([(event.event.clone(), data)].into_iter().collect());
+    serde_json::from_value(tagged).map_err(|error| Error::Decode(error.to_string()))
+}
+
+/// What a [`Backend`] reports about a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// The response's status and headers arrived.
+    Response { status: u16, content_type: String },
+    /// Part of the response body.
+    Data(Vec<u8>),
+    /// The response ended or the request failed, and why. Always the last signal.
+    Closed(String),
+}
+
+/// Makes the requests for an [`EventSource`], which takes care of making them again.
+pub trait Backend {
+    type Connection;
+
+    /// Starts a `GET` request for `url` and returns without waiting for it, reporting
+    /// what becomes of it through `signals`. Dropping the connection cancels the
+    /// request.
+    fn connect(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        signals: UnboundedSender<Signal>,
+    ) -> Result<Self::Connection, Error>;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub type DefaultBackend = FetchBackend;
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultBackend = NativeBackend;
+
+/// Makes requests with `fetch`, reading the body as it streams in.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchBackend;
+
+impl Backend for FetchBackend {
+    type Connection = FetchConnection;
+
+    fn connect(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        signals: UnboundedSender<Signal>,
+    ) -> Result<FetchConnection, Error> {
+        let init = web_sys::RequestInit::new();
+        init.set_method("GET");
+        let request_headers =
+            web_sys::Headers::new().map_err(|error| Error::InvalidRequest(js_message(&error)))?;
+        for (name, value) in headers {
+            request_headers
+                .append(name, value)
+                .map_err(|_| Error::InvalidRequest(format!("invalid header {name:?}")))?;
+        }
+        init.set_headers(&request_headers);
+        let controller = web_sys::AbortController::new()
+            .map_err(|error| Error::InvalidRequest(js_message(&error)))?;
+        init.set_signal(Some(&controller.signal()));
+        let request = web_sys::Request::new_with_str_and_init(url, &init)
+            .map_err(|error| Error::InvalidRequest(js_message(&error)))?;
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let reason = receive(&request, &signals)
+                .await
+                .unwrap_or_else(|error| js_message(&error));
+            let _ = signals.unbounded_send(Signal::Closed(reason));
+        });
+        Ok(FetchConnection { controller })
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(http::Backend::sleep(&http::FetchBackend, duration))
+    }
+}
+
+/// Sends the response to `request` to `signals` as it arrives, returning why it ended.
+async fn receive(
+    request: &web_sys::Request,
+    signals: &UnboundedSender<Signal>,
+) -> Result<String, JsValue> {
+    let response: web_sys::Response = JsFuture::from(http::global_fetch(request))
+        .await?
+        .unchecked_into();
+    let _ = signals.unbounded_send(Signal::Response {
+        status: response.status(),
+        content_type: response.headers().get("Content-Type")?.unwrap_or_default(),
+    });
+    let Some(body) = response.body() else {
+        return Ok("the response has no body".to_owned());
+    };
+    let reader: web_sys::ReadableStreamDefaultReader = body.get_reader().unchecked_into();
+    loop {
+        let chunk = JsFuture::from(reader.read()).await?;
+        if js_sys::Reflect::get(&chunk, &"done".into())?.is_truthy() {
+            return Ok("the server ended the response".to_owned());
+        }
+        let value = js_sys::Reflect::get(&chunk, &"value".into())?;
+        let data = js_sys::Uint8Array::new(&value).to_vec();
+        if signals.unbounded_send(Signal::Data(data)).is_err() {
+            return Ok("the request was cancelled".to_owned());
+        }
+    }
+}
+
+/// A request made by [`FetchBackend`], aborted when dropped.
+pub struct FetchConnection {
+    controller: web_sys::AbortController,
+}
+
+impl Drop for FetchConnection {
+    fn drop(&mut self) {
+        self.controller.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::rc::Rc;
+
+    use futures::executor::block_on;
+    use futures::FutureExt;
+
+    use super::*;
+
+    /// A request's headers and where to report what becomes of it.
+    type Made = (Vec<(String, String)>, UnboundedSender<Signal>);
+
+    /// Keeps the requests made, for the test to answer, and never waits.
+    #[derive(Clone, Default)]
+    struct Scripted {
+        requests: Rc<RefCell<Vec<Made>>>,
+    }
+
+    impl Scripted {
+        fn send(&self, signal: Signal) {
+            let requests = self.requests.borrow();
+            requests.last().unwrap().1.unbounded_send(signal).unwrap();
+        }
+
+        fn open(&self) {
+            self.send(Signal::Response {
+                status: 200,
+                content_type: "text/event-stream; charset=utf-8".to_owned(),
+            });
+        }
+
+        fn data(&self, data: &str) {
+            self.send(Signal::Data(data.as_bytes().to_vec()));
+        }
+
+        fn header(&self, request: usize, name: &str) -> Option<String> {
+            let requests = self.requests.borrow();
+            (requests[request].0.iter())
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        }
+    }
+
+    impl Backend for Scripted {
+        type Connection = ();
+
+        fn connect(
+            &self,
+            _url: &str,
+            headers: &[(String, String)],
+            signals: UnboundedSender<Signal>,
+        ) -> Result<(), Error> {
+            self.requests.borrow_mut().push((headers.to_vec(), signals));
+            Ok(())
+        }
+
+        fn sleep(&self, _duration: Duration) -> Sleep {
+            Box::pin(async {})
+        }
+    }
+
+    fn next<B: Backend>(source: &mut EventSource<B>) -> Option<Option<Result<Event, Error>>> {
+        source.next().now_or_never()
+    }
+
+    #[test]
+    fn resumes_from_the_last_dispatched_event() {
+        let backend = Scripted::default();
+        let mut source = Config::new("http://test/events")
+            .connect_with(backend.clone())
+            .unwrap();
+        assert_eq!(backend.header(0, "Last-Event-ID"), None);
+        backend.open();
+        backend.data("id: 1\ndata: a\n\n");
+        assert_eq!(next(&mut source).unwrap().unwrap().unwrap().data, "a");
+        assert_eq!(source.last_event_id(), "1");
+
+        // Cut off between the ID and the blank line that would dispatch its event.
+        backend.data("id: 2\ndata: b\n");
+        backend.send(Signal::Closed("reset".to_owned()));
+        assert!(next(&mut source).is_none());
+        assert_eq!(source.last_event_id(), "1");
+        assert_eq!(backend.header(1, "Last-Event-ID").as_deref(), Some("1"));
+
+        // The new parser starts from that ID too.
+        backend.open();
+        backend.data("data: c\n\n");
+        let event = next(&mut source).unwrap().unwrap().unwrap();
+        assert_eq!((event.data.as_str(), event.id.as_str()), ("c", "1"));
+    }
+
+    #[test]
+    fn rejects_other_responses() {
+        let backend = Scripted::default();
+        let mut source = Config::new("http://test/")
+            .connect_with(backend.clone())
+            .unwrap();
+        backend.send(Signal::Response {
+            status: 200,
+            content_type: "text/html".to_owned(),
+        });
+        assert!(matches!(
+            next(&mut source),
+            Some(Some(Err(Error::Rejected(_))))
+        ));
+        assert!(matches!(next(&mut source), Some(None)));
+    }
+
+    #[test]
+    fn gives_up_after_the_attempts_run_out() {
+        let backend = Scripted::default();
+        let reconnect = Reconnect {
+            attempts: Some(2),
+            ..Reconnect::default()
+        };
+        let mut source = Config::new("http://test/")
+            .reconnect(reconnect)
+            .connect_with(backend.clone())
+            .unwrap();
+        backend.send(Signal::Closed("refused".to_owned()));
+        assert!(next(&mut source).is_none());
+        backend.send(Signal::Closed("refused".to_owned()));
+        assert_eq!(
+            next(&mut source),
+            Some(Some(Err(Error::Closed("refused".to_owned()))))
+        );
+        assert_eq!(backend.requests.borrow().len(), 2);
+    }
+
+    #[test]
+    fn decodes_typed_events() {
+        // Decoded as `{"<event name>": <data>}`, like an enum with string variants.
+        type Message = std::collections::BTreeMap<String, String>;
+        let message = |name: &str, data: &str| Message::from([(name.to_owned(), data.to_owned())]);
+
+        let backend = Scripted::default();
+        let source = Config::new("http://test/")
+            .connect_with(backend.clone())
+            .unwrap();
+        let mut typed = source.typed::<Message>();
+        backend.open();
+        backend.data("data: \"quoted\"\n\nevent: notice\ndata: hi\n\n");
+        backend.data("event: update\ndata: {\"count\": 3}\n\n");
+        let mut next = || typed.next().now_or_never().unwrap().unwrap();
+        assert_eq!(next(), Ok(message("message", "quoted")));
+        assert_eq!(next(), Ok(message("notice", "hi")));
+        assert!(matches!(next(), Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn receives_events_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let n = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                      Transfer-Encoding: chunked\r\n\r\n9\r\ndata: hi\n\r\n1\r\n\n\r\n",
+                )
+                .unwrap();
+            // Dropping the event source shuts the socket down.
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let closed = stream.read(&mut buffer).unwrap();
+            (String::from_utf8(request).unwrap(), closed)
+        });
+
+        let mut source = Config::new(url).last_event_id("0").connect().unwrap();
+        let event = block_on(source.next()).unwrap().unwrap();
+        assert_eq!(event.data, "hi");
+        drop(source);
+
+        let (request, closed) = server.join().unwrap();
+        assert!(request.starts_with("GET /events HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nLast-Event-ID: 0\r\n"));
+        assert_eq!(closed, 0);
+    }
+}
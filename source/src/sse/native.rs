@@ -0,0 +1,185 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedSender;
+
+use super::{Backend, Error, Signal, Sleep};
+use crate::{http, websocket};
+
+/// Makes requests over HTTP/1.1 with blocking sockets, on a thread per request. Only
+/// `http://` URLs are supported. Like [`http::NativeBackend`], it is meant for tests
+/// and tools.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeBackend;
+
+impl Backend for NativeBackend {
+    type Connection = NativeConnection;
+
+    fn connect(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        signals: UnboundedSender<Signal>,
+    ) -> Result<NativeConnection, Error> {
+        let (authority, path, address) =
+            http::split_url(url).map_err(|error| Error::InvalidRequest(error.to_string()))?;
+        let mut head = format!("GET {path} HTTP/1.1\r\nHost: {authority}\r\n");
+        for (name, value) in headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let (streams, stream) = futures::channel::oneshot::channel();
+        thread::spawn(move || {
+            let reason = match TcpStream::connect(&address) {
+                Ok(stream) => match streams.send(stream.try_clone().ok()) {
+                    Ok(()) => receive(stream, &head, &signals),
+                    // The connection was dropped while connecting.
+                    Err(_) => {
+                        let _ = stream.shutdown(Shutdown::Both);
+                        return;
+                    }
+                },
+                Err(error) => error.to_string(),
+            };
+            let _ = signals.unbounded_send(Signal::Closed(reason));
+        });
+        Ok(NativeConnection { stream })
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        websocket::Backend::sleep(&websocket::NativeBackend, duration)
+    }
+}
+
+/// A request made by [`NativeBackend`], whose socket is shut down when this is dropped.
+pub struct NativeConnection {
+    stream: futures::channel::oneshot::Receiver<Option<TcpStream>>,
+}
+
+impl Drop for NativeConnection {
+    fn drop(&mut self) {
+        // Once closed, a stream is either here already or fails to send, in which case
+        // the thread shuts it down itself.
+        self.stream.close();
+        if let Ok(Some(Some(stream))) = self.stream.try_recv() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Sends the request and then the response to `signals` as it arrives, returning why it
+/// ended.
+fn receive(mut stream: TcpStream, head: &str, signals: &UnboundedSender<Signal>) -> String {
+    if let Err(error) = stream.write_all(head.as_bytes()) {
+        return error.to_string();
+    }
+
+    let mut raw = Vec::new();
+    let mut buffer = [0; 8192];
+    let body_start = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return "the connection closed before the response".to_owned(),
+            Ok(n) => raw.extend_from_slice(&buffer[..n]),
+            Err(error) => return error.to_string(),
+        }
+        if let Some(end) = raw.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+    let response = match http::parse_response(&raw[..body_start], true) {
+        Ok(response) => response,
+        Err(error) => return error.to_string(),
+    };
+    let _ = signals.unbounded_send(Signal::Response {
+        status: response.status,
+        content_type: response
+            .header("Content-Type")
+            .unwrap_or_default()
+            .to_owned(),
+    });
+    let mut chunks = response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+        .then(Dechunker::default);
+
+    let mut data = raw.split_off(body_start);
+    loop {
+        if let Some(chunks) = &mut chunks {
+            data = match chunks.feed(&data) {
+                Some(data) => data,
+                None => return "malformed chunked response".to_owned(),
+            };
+        }
+        if !data.is_empty() && signals.unbounded_send(Signal::Data(data)).is_err() {
+            return "the request was cancelled".to_owned();
+        }
+        if chunks.as_ref().is_some_and(|chunks| chunks.done) {
+            return "the server ended the response".to_owned();
+        }
+        data = match stream.read(&mut buffer) {
+            Ok(0) => return "the server ended the response".to_owned(),
+            Ok(n) => buffer[..n].to_vec(),
+            Err(error) => return error.to_string(),
+        };
+    }
+}
+
+/// Undoes `Transfer-Encoding: chunked` on a body that arrives in pieces.
+#[derive(Default)]
+struct Dechunker {
+    /// Input not yet decoded, because it ends partway through a chunk's framing.
+    pending: Vec<u8>,
+    /// Bytes left of the current chunk's data, with `Some(0)` until the line break after
+    /// it has been read, or `None` between chunks.
+    remaining: Option<usize>,
+    done: bool,
+}
+
+impl Dechunker {
+    /// Decodes `input`, returning the data in it, or `None` if it is malformed.
+    fn feed(&mut self, input: &[u8]) -> Option<Vec<u8>> {
+        self.pending.extend_from_slice(input);
+        let mut data = Vec::new();
+        let mut start = 0;
+        while !self.done {
+            let rest = &self.pending[start..];
+            match self.remaining {
+                Some(0) => {
+                    if rest.len() < 2 {
+                        break;
+                    }
+                    if &rest[..2] != b"\r\n" {
+                        return None;
+                    }
+                    start += 2;
+                    self.remaining = None;
+                }
+                Some(remaining) => {
+                    if rest.is_empty() {
+                        break;
+                    }
+                    let taken = remaining.min(rest.len());
+                    data.extend_from_slice(&rest[..taken]);
+                    start += taken;
+                    self.remaining = Some(remaining - taken);
+                }
+                None => {
+                    let Some(line_end) = rest.windows(2).position(|window| window == b"\r\n")
+                    else {
+                        break;
+                    };
+                    let size = std::str::from_utf8(&rest[..line_end]).ok()?;
+                    let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+                    start += line_end + 2;
+                    self.remaining = Some(size);
+                    self.done = size == 0;
+                }
+            }
+        }
+        self.pending.drain(..start);
+        Some(data)
+    }
+}
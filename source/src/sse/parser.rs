@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+/// A message from an event stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    /// The event's name, `message` unless the server gave one.
+    pub event: String,
+    /// The `data` lines, joined with newlines.
+    pub data: String,
+    /// The last ID the server sent, on this event or an earlier one. Empty if none.
+    pub id: String,
+}
+
+/// Turns the bytes of a `text/event-stream` into events, following the WHATWG
+/// specification. It does no I/O and accepts input split anywhere, so it can be fed
+/// whatever arrives, and never panics on malformed input.
+///
+/// ```
+/// use hello_wasm::sse::Parser;
+///
+/// let mut parser = Parser::new();
+/// assert!(parser.feed(b"event: greeting\ndata: hel").is_empty());
+/// let events = parser.feed(b"lo\ndata: world\nid: 7\n\n: a comment\n");
+/// assert_eq!(events[0].event, "greeting");
+/// assert_eq!(events[0].data, "hello\nworld");
+/// assert_eq!(parser.last_event_id(), "7");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    /// The incomplete line at the end of the input so far.
+    line: Vec<u8>,
+    /// Whether the last byte was a carriage return, so a line feed after it ends nothing.
+    after_cr: bool,
+    /// Whether the start of the stream, where a byte order mark may be, has been seen.
+    started: bool,
+    event: String,
+    data: String,
+    has_data: bool,
+    last_event_id: String,
+    retry: Option<Duration>,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// A parser for a stream that resumes an earlier one, whose events up to `id` have
+    /// been seen.
+    pub fn with_last_event_id(id: impl Into<String>) -> Parser {
+        Parser {
+            last_event_id: id.into(),
+            ..Parser::default()
+        }
+    }
+
+    /// Parses `bytes`, returning the events they complete.
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Vec<Event> {
+        if !self.started {
+            let bom = b"\xEF\xBB\xBF";
+            let seen = self.line.len() + bytes.len();
+            let prefix: Vec<u8> = self.line.iter().chain(bytes).copied().take(3).collect();
+            if !bom.starts_with(&prefix) {
+                self.started = true;
+            } else if seen >= 3 {
+                self.started = true;
+                bytes = &bytes[3 - self.line.len()..];
+                self.line.clear();
+            } else {
+                self.line.extend_from_slice(bytes);
+                return Vec::new();
+            }
+        }
+
+        let mut events = Vec::new();
+        for &byte in bytes {
+            let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(event) = self.process(&String::from_utf8_lossy(&line)) {
+                        events.push(event);
+                    }
+                }
+                byte => self.line.push(byte),
+            }
+        }
+        events
+    }
+
+    /// The ID to send as `Last-Event-ID` when reconnecting. Empty if none.
+    pub fn last_event_id(&self) -> &str {
+        &self.last_event_id
+    }
+
+    /// How long the server last asked clients to wait before reconnecting.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    fn process(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            // A comment.
+            "" => {}
+            "event" => self.event = value.to_owned(),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.to_owned(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = std::mem::take(&mut self.event);
+        let data = std::mem::take(&mut self.data);
+        if !std::mem::take(&mut self.has_data) {
+            return None;
+        }
+        Some(Event {
+            event: if event.is_empty() {
+                "message".to_owned()
+            } else {
+                event
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<Event> {
+        Parser::new().feed(input.as_bytes())
+    }
+
+    fn event(event: &str, data: &str, id: &str) -> Event {
+        Event {
+            event: event.to_owned(),
+            data: data.to_owned(),
+            id: id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parses_fields() {
+        let events = parse("event: a\ndata:1\ndata:  2\nid: x\n\ndata\n\n: comment\nevent: b\n\n");
+        assert_eq!(
+            events,
+            [event("a", "1\n 2", "x"), event("message", "", "x")]
+        );
+    }
+
+    #[test]
+    fn accepts_any_line_ending() {
+        let expected = [event("message", "a", ""), event("message", "b", "")];
+        assert_eq!(parse("data: a\r\n\r\ndata: b\r\r"), expected);
+        assert_eq!(parse("data: a\n\ndata: b\r\n\n"), expected);
+    }
+
+    #[test]
+    fn waits_for_the_blank_line() {
+        let mut parser = Parser::new();
+        assert!(parser.feed(b"data: a\n").is_empty());
+        assert!(parser.feed(b"data: b").is_empty());
+        assert_eq!(parser.feed(b"\n\n"), [event("message", "a\nb", "")]);
+        // Nothing is dispatched for a trailing event that never ends.
+        assert!(parser.feed(b"data: c\n").is_empty());
+    }
+
+    #[test]
+    fn keeps_ids_and_retries() {
+        let mut parser = Parser::with_last_event_id("3");
+        assert_eq!(parser.feed(b"data: a\n\n"), [event("message", "a", "3")]);
+        assert!(parser.feed(b"id: 4\nretry: 1500\n\n").is_empty());
+        assert_eq!(parser.last_event_id(), "4");
+        assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+
+        // IDs with NUL and retries that are not plain digits are ignored.
+        parser.feed(b"id: a\0b\nretry: 1.5\nretry: -1\nretry:\n\n");
+        assert_eq!(parser.last_event_id(), "4");
+        assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+        // An empty ID resets it.
+        parser.feed(b"id\n\n");
+        assert_eq!(parser.last_event_id(), "");
+    }
+
+    #[test]
+    fn skips_a_leading_byte_order_mark() {
+        let mut parser = Parser::new();
+        assert!(parser.feed(b"\xEF").is_empty());
+        assert!(parser.feed(b"\xBB").is_empty());
+        assert_eq!(parser.feed(b"\xBFdata: a\n\n"), [event("message", "a", "")]);
+        // Only at the very start.
+        assert_eq!(
+            parse("data: a\n\n\u{feff}data: b\n\n"),
+            [event("message", "a", "")]
+        );
+    }
+
+    /// A small deterministic generator, so failures can be reproduced from the seed.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) as usize % bound
+        }
+    }
+
+    #[test]
+    fn parses_the_same_however_the_input_is_split() {
+        let pieces: &[&[u8]] = &[
+            b"data: ",
+            b"event: ",
+            b"id: ",
+            b"retry: ",
+            b"12",
+            b"x",
+            b":",
+            b" ",
+            b"\n",
+            b"\r",
+            b"\r\n",
+            b"\xEF\xBB\xBF",
+            b"\xff",
+            b"\0",
+            "é".as_bytes(),
+        ];
+        for seed in 0..500 {
+            let mut rng = Lcg(seed);
+            let mut input = Vec::new();
+            for _ in 0..rng.next(60) {
+                input.extend_from_slice(pieces[rng.next(pieces.len())]);
+            }
+
+            let mut whole = Parser::new();
+            let expected = whole.feed(&input);
+
+            let mut split = Parser::new();
+            let mut events = Vec::new();
+            let mut rest = input.as_slice();
+            while !rest.is_empty() {
+                let (chunk, after) = rest.split_at(1 + rng.next(rest.len()));
+                events.extend(split.feed(chunk));
+                rest = after;
+            }
+            assert_eq!(events, expected, "seed {seed}: {input:?}");
+            assert_eq!(split.last_event_id(), whole.last_event_id(), "seed {seed}");
+            assert_eq!(split.retry(), whole.retry(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn never_panics_on_arbitrary_bytes() {
+        let mut rng = Lcg(7);
+        for _ in 0..2000 {
+            let mut parser = Parser::new();
+            for _ in 0..rng.next(8) {
+                let bytes: Vec<u8> = (0..rng.next(40)).map(|_| rng.next(256) as u8).collect();
+                for event in parser.feed(&bytes) {
+                    assert!(!event.event.is_empty());
+                }
+            }
+        }
+    }
+}
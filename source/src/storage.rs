@@ -0,0 +1,659 @@
+//! Typed, versioned values in `localStorage` and `sessionStorage`.
+//!
+//! A [`Storage`] keeps values of one type under a namespace, so `theme` in the
+//! `settings` namespace is stored as `settings:theme`. Values are saved as JSON along
+//! with the storage's version; when the version goes up, values saved under an older
+//! one are passed through the registered migrations as they are read. [`MemoryBackend`]
+//! stands in for the browser natively:
+//!
+//! ```
+//! use hello_wasm::storage::{MemoryBackend, Storage};
+//! use serde_json::json;
+//!
+//! let backend = MemoryBackend::new();
+//! let old = Storage::<u32, _>::with_backend(backend.clone(), "settings");
+//! old.set("font", &14).unwrap();
+//!
+//! // Version 2 keeps the font size in points and adds a family.
+//! let settings = Storage::<serde_json::Value, _>::with_backend(backend, "settings")
+//!     .version(2)
+//!     .migration(1, |size| Ok(json!({ "points": size, "family": "serif" })));
+//! let font = settings.get("font").unwrap();
+//! assert_eq!(font, Some(json!({ "points": 14, "family": "serif" })));
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::{js_message, Listener};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The storage cannot be used, such as when the browser blocks it for the page.
+    Unavailable(String),
+    /// Writing the value would take the storage over its quota.
+    QuotaExceeded,
+    /// The value could not be serialised.
+    Encode(String),
+    /// The stored value is not what was expected, even after migrating it.
+    Decode(String),
+    /// A migration failed, or the value was saved by a newer version than this one.
+    Migration(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unavailable(message) => write!(f, "storage is unavailable: {message}"),
+            Error::QuotaExceeded => write!(f, "the storage quota is exceeded"),
+            Error::Encode(message) => write!(f, "failed to encode the value: {message}"),
+            Error::Decode(message) => write!(f, "failed to decode the stored value: {message}"),
+            Error::Migration(message) => write!(f, "failed to migrate the stored value: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A change made to a [`Storage`]'s namespace from another tab.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<T> {
+    Set {
+        key: String,
+        value: T,
+    },
+    Removed {
+        key: String,
+    },
+    /// The whole storage was cleared, namespaces and all.
+    Cleared,
+}
+
+type Migration = Box<dyn Fn(Value) -> Result<Value, String>>;
+
+/// Values of type `T` under a namespace. Values saved by versions before the current one
+/// are migrated when they are read and saved again; a version with no migration
+/// registered leaves values as they are.
+pub struct Storage<T, B: Backend = WebStorage> {
+    backend: B,
+    schema: Rc<Schema>,
+    types: PhantomData<fn(T) -> T>,
+}
+
+struct Schema {
+    namespace: String,
+    version: u32,
+    /// Migrations keyed by the version they migrate from.
+    migrations: BTreeMap<u32, Migration>,
+}
+
+impl<T: Serialize + DeserializeOwned> Storage<T> {
+    /// Values in `localStorage`, which outlive the page.
+    pub fn local(namespace: impl Into<String>) -> Result<Storage<T>, Error> {
+        Ok(Storage::with_backend(WebStorage::local()?, namespace))
+    }
+
+    /// Values in `sessionStorage`, which last as long as the tab.
+    pub fn session(namespace: impl Into<String>) -> Result<Storage<T>, Error> {
+        Ok(Storage::with_backend(WebStorage::session()?, namespace))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, B: Backend> Storage<T, B> {
+    pub fn with_backend(backend: B, namespace: impl Into<String>) -> Storage<T, B> {
+        Storage {
+            backend,
+            schema: Rc::new(Schema {
+                namespace: namespace.into(),
+                version: 1,
+                migrations: BTreeMap::new(),
+            }),
+            types: PhantomData,
+        }
+    }
+
+    /// The version values are saved under, 1 unless set. Values stored before there
+    /// was a version, without the wrapper this saves them in, count as version 0.
+    pub fn version(mut self, version: u32) -> Storage<T, B> {
+        self.schema_mut().version = version;
+        self
+    }
+
+    /// Registers how to turn a value saved under version `from` into one for `from + 1`.
+    pub fn migration(
+        mut self,
+        from: u32,
+        migrate: impl Fn(Value) -> Result<Value, String> + 'static,
+    ) -> Storage<T, B> {
+        self.schema_mut().migrations.insert(from, Box::new(migrate));
+        self
+    }
+
+    fn schema_mut(&mut self) -> &mut Schema {
+        Rc::get_mut(&mut self.schema).expect("the schema is only changed while building")
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.schema.namespace
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<T>, Error> {
+        let full_key = self.schema.key(key);
+        let Some(raw) = self.backend.get(&full_key)? else {
+            return Ok(None);
+        };
+        let (value, version) = self.schema.decode(&raw)?;
+        if version != self.schema.version {
+            if let Err(error) = self.set(key, &value) {
+                log::warn!("failed to save {full_key} after migrating it: {error}");
+            }
+        }
+        Ok(Some(value))
+    }
+
+    pub fn get_or_default(&self, key: &str) -> Result<T, Error>
+    where
+        T: Default,
+    {
+        Ok(self.get(key)?.unwrap_or_default())
+    }
+
+    pub fn set(&self, key: &str, value: &T) -> Result<(), Error> {
+        let value =
+            serde_json::to_value(value).map_err(|error| Error::Encode(error.to_string()))?;
+        let raw = serde_json::json!({ "version": self.schema.version, "value": value });
+        self.backend.set(&self.schema.key(key), &raw.to_string())
+    }
+
+    pub fn remove(&self, key: &str) -> Result<(), Error> {
+        self.backend.remove(&self.schema.key(key))
+    }
+
+    /// The keys in the namespace, without its prefix.
+    pub fn keys(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .backend
+            .keys()?
+            .iter()
+            .filter_map(|key| self.schema.strip(key))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Removes every key in the namespace, leaving the rest of the storage alone.
+    pub fn clear(&self) -> Result<(), Error> {
+        for key in self.keys()? {
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Calls `on_change` whenever another tab changes the namespace, until the returned
+    /// subscription is dropped. Values that fail to decode are logged and skipped.
+    pub fn watch(&self, mut on_change: impl FnMut(Change<T>) + 'static) -> Subscription
+    where
+        T: 'static,
+    {
+        let schema = self.schema.clone();
+        self.backend.subscribe(Box::new(move |key, raw| {
+            let change = match (key, raw) {
+                (None, _) => Change::Cleared,
+                (Some(key), raw) => {
+                    let Some(key) = schema.strip(key) else {
+                        return;
+                    };
+                    let key = key.to_owned();
+                    match raw.map(|raw| schema.decode(raw)) {
+                        None => Change::Removed { key },
+                        Some(Ok((value, _))) => Change::Set { key, value },
+                        Some(Err(error)) => {
+                            log::warn!("ignoring a change to {key}: {error}");
+                            return;
+                        }
+                    }
+                }
+            };
+            on_change(change);
+        }))
+    }
+}
+
+impl Schema {
+    fn key(&self, key: &str) -> String {
+        format!("{}:{key}", self.namespace)
+    }
+
+    fn strip<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(&self.namespace)?.strip_prefix(':')
+    }
+
+    /// Decodes a stored value, migrating it first if needed. Returns the version it was
+    /// stored under too.
+    fn decode<T: DeserializeOwned>(&self, raw: &str) -> Result<(T, u32), Error> {
+        let (stored, mut value) = match serde_json::from_str::<Value>(raw) {
+            Ok(Value::Object(mut object)) if object.len() == 2 && object.contains_key("value") => {
+                match object.get("version").and_then(Value::as_u64) {
+                    Some(version) => (
+                        u32::try_from(version).unwrap_or(u32::MAX),
+                        object.remove("value").unwrap_or_default(),
+                    ),
+                    None => (0, Value::Object(object)),
+                }
+            }
+            Ok(value) => (0, value),
+            // Plain text saved by something else.
+            Err(_) => (0, Value::String(raw.to_owned())),
+        };
+        if stored > self.version {
+            return Err(Error::Migration(format!(
+                "saved by version {stored}, but this is version {}",
+                self.version
+            )));
+        }
+        for version in stored..self.version {
+            if let Some(migrate) = self.migrations.get(&version) {
+                value = migrate(value).map_err(Error::Migration)?;
+            }
+        }
+        let value =
+            serde_json::from_value(value).map_err(|error| Error::Decode(error.to_string()))?;
+        Ok((value, stored))
+    }
+}
+
+/// Called with the key and new value of each change from elsewhere, or no key when the
+/// whole storage was cleared.
+pub type OnChange = Box<dyn FnMut(Option<&str>, Option<&str>)>;
+
+/// String keys and values, like the Web Storage API.
+pub trait Backend {
+    fn get(&self, key: &str) -> Result<Option<String>, Error>;
+
+    /// Stores `value`, failing with [`Error::QuotaExceeded`] if there is no room.
+    fn set(&self, key: &str, value: &str) -> Result<(), Error>;
+
+    fn remove(&self, key: &str) -> Result<(), Error>;
+
+    fn keys(&self) -> Result<Vec<String>, Error>;
+
+    /// Calls `on_change` for changes made through other handles to the same storage,
+    /// such as from other tabs, but not for this one's.
+    fn subscribe(&self, on_change: OnChange) -> Subscription;
+}
+
+/// Keeps a [`Backend::subscribe`] callback registered until dropped.
+pub struct Subscription {
+    _guard: Box<dyn Any>,
+}
+
+impl Subscription {
+    /// A subscription that lasts as long as `guard`, whose `Drop` unregisters it.
+    pub fn new(guard: impl Any) -> Subscription {
+        Subscription {
+            _guard: Box::new(guard),
+        }
+    }
+}
+
+/// `localStorage` or `sessionStorage`.
+#[derive(Clone, Debug)]
+pub struct WebStorage {
+    storage: web_sys::Storage,
+}
+
+impl WebStorage {
+    pub fn local() -> Result<WebStorage, Error> {
+        WebStorage::new(web_sys::Window::local_storage)
+    }
+
+    pub fn session() -> Result<WebStorage, Error> {
+        WebStorage::new(web_sys::Window::session_storage)
+    }
+
+    fn new(
+        storage: fn(&web_sys::Window) -> Result<Option<web_sys::Storage>, JsValue>,
+    ) -> Result<WebStorage, Error> {
+        let window =
+            web_sys::window().ok_or_else(|| Error::Unavailable("there is no window".to_owned()))?;
+        match storage(&window) {
+            Ok(Some(storage)) => Ok(WebStorage { storage }),
+            Ok(None) => Err(Error::Unavailable("the browser has no storage".to_owned())),
+            // Thrown when storage is disabled, for example for third-party frames.
+            Err(error) => Err(Error::Unavailable(js_message(&error))),
+        }
+    }
+}
+
+impl Backend for WebStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        self.storage
+            .get_item(key)
+            .map_err(|error| Error::Unavailable(js_message(&error)))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.storage.set_item(key, value).map_err(|error| {
+            let quota = error
+                .dyn_ref::<web_sys::DomException>()
+                .is_some_and(|error| {
+                    // Firefox used its own name before adopting the standard one.
+                    matches!(
+                        error.name().as_str(),
+                        "QuotaExceededError" | "NS_ERROR_DOM_QUOTA_REACHED"
+                    )
+                });
+            if quota {
+                Error::QuotaExceeded
+            } else {
+                Error::Unavailable(js_message(&error))
+            }
+        })
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        self.storage
+            .remove_item(key)
+            .map_err(|error| Error::Unavailable(js_message(&error)))
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        let length = self
+            .storage
+            .length()
+            .map_err(|error| Error::Unavailable(js_message(&error)))?;
+        Ok((0..length)
+            .filter_map(|index| self.storage.key(index).ok().flatten())
+            .collect())
+    }
+
+    /// Listens for the window's `storage` event, which browsers fire in every other tab
+    /// of the same origin.
+    fn subscribe(&self, mut on_change: OnChange) -> Subscription {
+        let storage = self.storage.clone();
+        let window = web_sys::window().expect_throw("no window; storage events need one");
+        Subscription::new(Listener::new(&window, "storage", move |event| {
+            let event: web_sys::StorageEvent = event.unchecked_into();
+            // The event fires for both storage areas.
+            let same_area = event.storage_area().is_some_and(|area| area == storage);
+            if same_area {
+                on_change(event.key().as_deref(), event.new_value().as_deref());
+            }
+        }))
+    }
+}
+
+/// Storage kept in memory, for running natively. Clones share the same storage, and
+/// [`other_tab`](MemoryBackend::other_tab) gives a handle whose changes are reported to
+/// this one's subscribers, the way a change in another tab is.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    shared: Rc<Shared>,
+    tab: u32,
+}
+
+#[derive(Default)]
+struct Shared {
+    items: RefCell<BTreeMap<String, String>>,
+    /// How many bytes of keys and values fit, or `None` for no limit.
+    quota: Option<usize>,
+    subscribers: RefCell<Vec<Subscriber>>,
+    /// The last ID given to a tab or subscriber.
+    last_id: RefCell<u32>,
+}
+
+impl Shared {
+    fn new_id(&self) -> u32 {
+        let mut last_id = self.last_id.borrow_mut();
+        *last_id += 1;
+        *last_id
+    }
+}
+
+struct Subscriber {
+    id: u32,
+    tab: u32,
+    on_change: Rc<RefCell<OnChange>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// Storage that fails with [`Error::QuotaExceeded`] once its keys and values take
+    /// more than `bytes`.
+    pub fn with_quota(bytes: usize) -> MemoryBackend {
+        MemoryBackend {
+            shared: Rc::new(Shared {
+                quota: Some(bytes),
+                ..Shared::default()
+            }),
+            tab: 0,
+        }
+    }
+
+    /// A handle to the same storage that acts as if it were in another tab.
+    pub fn other_tab(&self) -> MemoryBackend {
+        MemoryBackend {
+            shared: self.shared.clone(),
+            tab: self.shared.new_id(),
+        }
+    }
+
+    /// Empties the storage, like `localStorage.clear()`.
+    pub fn clear(&self) {
+        self.shared.items.borrow_mut().clear();
+        self.notify(None, None);
+    }
+
+    fn notify(&self, key: Option<&str>, value: Option<&str>) {
+        // Collected first, so callbacks can use the storage.
+        let subscribers: Vec<_> = (self.shared.subscribers.borrow().iter())
+            .filter(|subscriber| subscriber.tab != self.tab)
+            .map(|subscriber| subscriber.on_change.clone())
+            .collect();
+        for on_change in subscribers {
+            (on_change.borrow_mut())(key, value);
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.shared.items.borrow().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), Error> {
+        {
+            let mut items = self.shared.items.borrow_mut();
+            if let Some(quota) = self.shared.quota {
+                let used: usize = (items.iter())
+                    .filter(|(existing, _)| existing.as_str() != key)
+                    .map(|(key, value)| key.len() + value.len())
+                    .sum();
+                if used + key.len() + value.len() > quota {
+                    return Err(Error::QuotaExceeded);
+                }
+            }
+            items.insert(key.to_owned(), value.to_owned());
+        }
+        self.notify(Some(key), Some(value));
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        if self.shared.items.borrow_mut().remove(key).is_some() {
+            self.notify(Some(key), None);
+        }
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        Ok(self.shared.items.borrow().keys().cloned().collect())
+    }
+
+    fn subscribe(&self, on_change: OnChange) -> Subscription {
+        let id = self.shared.new_id();
+        self.shared.subscribers.borrow_mut().push(Subscriber {
+            id,
+            tab: self.tab,
+            on_change: Rc::new(RefCell::new(on_change)),
+        });
+        Subscription::new(Unsubscribe {
+            shared: Rc::downgrade(&self.shared),
+            id,
+        })
+    }
+}
+
+struct Unsubscribe {
+    shared: std::rc::Weak<Shared>,
+    id: u32,
+}
+
+impl Drop for Unsubscribe {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            (shared.subscribers.borrow_mut()).retain(|subscriber| subscriber.id != self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage<T: Serialize + DeserializeOwned>(
+        backend: &MemoryBackend,
+        namespace: &str,
+    ) -> Storage<T, MemoryBackend> {
+        Storage::with_backend(backend.clone(), namespace)
+    }
+
+    #[test]
+    fn keeps_namespaces_apart() {
+        let backend = MemoryBackend::new();
+        let settings = storage::<String>(&backend, "settings");
+        let drafts = storage::<String>(&backend, "drafts");
+        settings.set("theme", &"dark".to_owned()).unwrap();
+        drafts.set("theme", &"light".to_owned()).unwrap();
+        drafts.set("post", &"hi".to_owned()).unwrap();
+
+        assert_eq!(settings.get("theme").unwrap().as_deref(), Some("dark"));
+        assert_eq!(settings.keys().unwrap(), ["theme"]);
+        assert_eq!(
+            backend.get("settings:theme").unwrap().as_deref(),
+            Some(r#"{"value":"dark","version":1}"#)
+        );
+
+        drafts.clear().unwrap();
+        assert_eq!(drafts.keys().unwrap(), Vec::<String>::new());
+        assert_eq!(settings.get("theme").unwrap().as_deref(), Some("dark"));
+        assert_eq!(settings.get("missing").unwrap(), None);
+        assert_eq!(settings.get_or_default("missing").unwrap(), "");
+    }
+
+    #[test]
+    fn migrates_values_through_each_version() {
+        let backend = MemoryBackend::new();
+        // Saved before there were versions.
+        backend.set("counter:clicks", "3").unwrap();
+        let counter = storage::<Value>(&backend, "counter")
+            .version(3)
+            .migration(0, |value| Ok(serde_json::json!({ "count": value })))
+            .migration(2, |mut value| {
+                value["unit"] = "clicks".into();
+                Ok(value)
+            });
+        let expected = serde_json::json!({ "count": 3, "unit": "clicks" });
+        assert_eq!(counter.get("clicks").unwrap(), Some(expected.clone()));
+        // Saved again under the current version, so it is not migrated twice.
+        assert_eq!(
+            serde_json::from_str::<Value>(&backend.get("counter:clicks").unwrap().unwrap())
+                .unwrap(),
+            serde_json::json!({ "version": 3, "value": expected })
+        );
+    }
+
+    #[test]
+    fn reports_failed_migrations_and_newer_versions() {
+        let backend = MemoryBackend::new();
+        storage::<u32>(&backend, "n")
+            .version(5)
+            .set("a", &1)
+            .unwrap();
+        assert!(matches!(
+            storage::<u32>(&backend, "n").version(4).get("a"),
+            Err(Error::Migration(_))
+        ));
+
+        let failing = storage::<u32>(&backend, "n")
+            .version(6)
+            .migration(5, |_| Err("no".to_owned()));
+        assert_eq!(failing.get("a"), Err(Error::Migration("no".to_owned())));
+
+        backend.set("n:b", "not a number").unwrap();
+        assert!(matches!(
+            storage::<u32>(&backend, "n").get("b"),
+            Err(Error::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn enforces_the_quota() {
+        let backend = MemoryBackend::with_quota(40);
+        let small = storage::<String>(&backend, "s");
+        small.set("a", &"x".repeat(10)).unwrap();
+        assert_eq!(small.set("b", &"x".repeat(10)), Err(Error::QuotaExceeded));
+        // Replacing a value only counts the new one.
+        small.set("a", &"y".repeat(10)).unwrap();
+        assert_eq!(small.keys().unwrap(), ["a"]);
+    }
+
+    #[test]
+    fn notifies_other_tabs() {
+        let backend = MemoryBackend::new();
+        let other = backend.other_tab();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let seen = changes.clone();
+        let subscription = storage::<u32>(&backend, "n").watch(move |change| {
+            seen.borrow_mut().push(change);
+        });
+
+        // Changes from this tab are not reported back to it.
+        storage::<u32>(&backend, "n").set("mine", &0).unwrap();
+        let theirs = storage::<u32>(&other, "n");
+        theirs.set("a", &1).unwrap();
+        storage::<u32>(&other, "elsewhere").set("a", &2).unwrap();
+        other.set("n:bad", "{").unwrap();
+        theirs.remove("a").unwrap();
+        other.clear();
+        assert_eq!(
+            *changes.borrow(),
+            [
+                Change::Set {
+                    key: "a".to_owned(),
+                    value: 1
+                },
+                Change::Removed {
+                    key: "a".to_owned()
+                },
+                Change::Cleared,
+            ]
+        );
+
+        drop(subscription);
+        theirs.set("a", &1).unwrap();
+        assert_eq!(changes.borrow().len(), 3);
+    }
+}
@@ -0,0 +1,201 @@
+//! Real threads for parallel work, through Rayon. [`init_thread_pool`] sets up Rayon's
+//! global pool, after which parallel iterators, `rayon::join` and the like spread their
+//! work over it.
+//!
+//! Threads need the build that `cargo xtask build --threads` makes with wasm atomics,
+//! whose memory is a `SharedArrayBuffer` that every thread's instance of the module
+//! shares. Only cross-origin isolated pages have `SharedArrayBuffer`, so the generated
+//! bootstrap loads that build where it can and the usual one elsewhere. With the usual
+//! build the pool is the calling thread alone, and the same code runs one piece after
+//! another.
+//!
+//! The main thread of a page can't block, which waiting for the pool does, so parallel
+//! work has to be started from a worker, such as in a [`worker_pool`](crate::worker_pool)
+//! task. In the threaded build those workers share the module's memory, and so the pool,
+//! which means [`init_thread_pool`] has to have resolved before a task uses Rayon.
+
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+use rayon::{ThreadBuilder, ThreadPoolBuilder};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::{js_message, set_property, Listener};
+use crate::worker_pool::worker_script;
+
+/// How [`init_thread_pool`] set up the pool.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// On threads of their own, which for a page are Web Workers sharing its memory.
+    Threads,
+    /// On the thread that started it, because the build has no wasm atomics or the page
+    /// has no `SharedArrayBuffer`.
+    SingleThread,
+}
+
+/// Whether this is the build with wasm atomics, which can use threads.
+pub fn is_threaded_build() -> bool {
+    cfg!(target_feature = "atomics")
+}
+
+/// Sets up Rayon's global pool with `threads` threads, resolving once they have all
+/// started, or with the calling thread alone where threads aren't available. It can only
+/// be set up once.
+///
+/// In the threaded build, call it on the page before anything uses Rayon, including
+/// [`worker_pool`](crate::worker_pool) tasks: nothing else sets up the pool there, and
+/// Rayon can't start a default one on the web.
+#[wasm_bindgen]
+pub async fn init_thread_pool(threads: usize) -> Result<Mode, JsError> {
+    let threads = threads.max(1);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+        Ok(Mode::Threads)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        if is_threaded_build() && shared_memory_available() {
+            spawn_web_threads(threads).await?;
+            Ok(Mode::Threads)
+        } else {
+            single_thread()?;
+            Ok(Mode::SingleThread)
+        }
+    }
+}
+
+/// Makes the calling thread the whole of Rayon's global pool, since a build without wasm
+/// atomics can't start threads. Each instance of the module has a pool of its own, so the
+/// workers of a [`worker_pool`](crate::worker_pool) set theirs up with this.
+pub(crate) fn single_thread() -> Result<(), rayon::ThreadPoolBuildError> {
+    ThreadPoolBuilder::new()
+        .num_threads(1)
+        .use_current_thread()
+        .build_global()
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+fn shared_memory_available() -> bool {
+    let global = js_sys::global();
+    js_sys::Reflect::get(&global, &"SharedArrayBuffer".into())
+        .is_ok_and(|value| value.is_function())
+        && js_sys::Reflect::get(&global, &"crossOriginIsolated".into())
+            .is_ok_and(|value| value.is_truthy())
+}
+
+/// The pool's threads, waiting for a worker each to run them.
+static PENDING: Mutex<Vec<ThreadBuilder>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static WORKERS: RefCell<Vec<(web_sys::Worker, [Listener; 2])>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Starts a Web Worker for each of the pool's threads and waits for them to say they
+/// have started.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+async fn spawn_web_threads(threads: usize) -> Result<(), JsError> {
+    let mut builders = Vec::with_capacity(threads);
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .spawn_handler(|thread| {
+            builders.push(thread);
+            Ok(())
+        })
+        .build_global()?;
+    // Handed over before any worker exists to take one, since the main thread can't
+    // wait for a contended lock.
+    *PENDING.lock().unwrap() = builders;
+
+    let (started, mut starts) = mpsc::unbounded();
+    let options = web_sys::WorkerOptions::new();
+    options.set_type(web_sys::WorkerType::Module);
+    let message = js_sys::Object::new();
+    set_property(&message, "module", &wasm_bindgen::module());
+    set_property(&message, "memory", &wasm_bindgen::memory());
+    set_property(&message, "threads", &true.into());
+    set_property(&message, "start", &"thread_pool_run".into());
+    let script = worker_script();
+    for _ in 0..threads {
+        let worker = web_sys::Worker::new_with_options(&script, &options)
+            .map_err(|error| JsError::new(&js_message(&error)))?;
+        let report = |started: mpsc::UnboundedSender<Result<(), String>>| {
+            move |event: web_sys::Event| {
+                let error = match event.dyn_ref::<web_sys::ErrorEvent>() {
+                    Some(event) => Some(event.message()),
+                    None => js_sys::Reflect::get(
+                        &event.unchecked_into::<web_sys::MessageEvent>().data(),
+                        &"error".into(),
+                    )
+                    .ok()
+                    .and_then(|error| error.as_string()),
+                };
+                let _ = started.unbounded_send(error.map_or(Ok(()), Err));
+            }
+        };
+        let listeners = [
+            Listener::new(&worker, "message", report(started.clone())),
+            Listener::new(&worker, "error", report(started.clone())),
+        ];
+        worker
+            .post_message(&message)
+            .map_err(|error| JsError::new(&js_message(&error)))?;
+        WORKERS.with(|workers| workers.borrow_mut().push((worker, listeners)));
+    }
+    for _ in 0..threads {
+        match starts.next().await {
+            Some(Ok(())) => {}
+            Some(Err(error)) => return Err(JsError::new(&format!("a thread failed: {error}"))),
+            None => unreachable!("`started` is still held"),
+        }
+    }
+    Ok(())
+}
+
+/// Runs one of the pool's threads on this worker, which it keeps until the page closes.
+/// Called by the worker script once the module has loaded.
+#[doc(hidden)]
+#[wasm_bindgen]
+pub fn thread_pool_run() {
+    let thread = PENDING.lock().unwrap().pop();
+    let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let _ = scope.post_message(&"started".into());
+    if let Some(thread) = thread {
+        thread.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use rayon::prelude::*;
+
+    use super::*;
+
+    /// Builds the global pool, so it must stay the only test in the library that uses
+    /// Rayon.
+    #[test]
+    fn single_thread_runs_everything_on_the_calling_thread() {
+        single_thread().unwrap();
+        assert!(single_thread().is_err());
+        assert_eq!(rayon::current_num_threads(), 1);
+        assert_eq!(rayon::current_thread_index(), Some(0));
+
+        let caller = thread::current().id();
+        let (a, b) = rayon::join(|| thread::current().id(), || thread::current().id());
+        assert_eq!((a, b), (caller, caller));
+        let threads: Vec<_> = (0..100)
+            .into_par_iter()
+            .map(|_| thread::current().id())
+            .collect();
+        assert!(threads.iter().all(|&id| id == caller));
+        assert_eq!((1..=100u32).into_par_iter().sum::<u32>(), 5050);
+    }
+}
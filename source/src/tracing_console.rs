@@ -0,0 +1,424 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+use wasm_bindgen::prelude::*;
+
+use crate::logging::{self, Level};
+
+/// A single recorded field value, kept typed so it can become a real JS value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(String),
+}
+
+impl FieldValue {
+    fn to_js(&self) -> JsValue {
+        match self {
+            FieldValue::Bool(value) => JsValue::from_bool(*value),
+            // As `BigInt`s, since a number would round IDs and counters above 2^53.
+            FieldValue::I64(value) => js_sys::BigInt::from(*value).into(),
+            FieldValue::U64(value) => js_sys::BigInt::from(*value).into(),
+            FieldValue::F64(value) => JsValue::from_f64(*value),
+            FieldValue::Str(value) => JsValue::from_str(value),
+        }
+    }
+}
+
+impl From<tracing::Level> for Level {
+    fn from(level: tracing::Level) -> Level {
+        match level {
+            tracing::Level::TRACE => Level::Trace,
+            tracing::Level::DEBUG => Level::Debug,
+            tracing::Level::INFO => Level::Info,
+            tracing::Level::WARN => Level::Warn,
+            tracing::Level::ERROR => Level::Error,
+        }
+    }
+}
+
+pub type Fields = Vec<(String, FieldValue)>;
+
+/// The calls [`ConsoleLayer`] makes. Implemented for the browser by [`WebBackend`] and
+/// natively by [`RecordingBackend`].
+pub trait Backend: Send + Sync + 'static {
+    fn group(&self, label: &str, fields: &Fields);
+    fn group_end(&self);
+    fn event(&self, level: Level, message: &str, fields: &Fields);
+    fn mark(&self, name: &str);
+    fn measure(&self, name: &str, start_mark: &str, end_mark: &str);
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = mark)]
+    fn performance_mark(name: &str);
+
+    #[wasm_bindgen(js_namespace = performance, js_name = measure)]
+    fn performance_measure(name: &str, start_mark: &str, end_mark: &str);
+}
+
+/// Renders to `console.group` and the `performance` timeline.
+pub struct WebBackend;
+
+impl WebBackend {
+    fn object(fields: &Fields) -> js_sys::Object {
+        let object = js_sys::Object::new();
+        for (name, value) in fields {
+            // Setting a property on a fresh plain object cannot fail.
+            let _ = js_sys::Reflect::set(&object, &JsValue::from_str(name), &value.to_js());
+        }
+        object
+    }
+}
+
+impl Backend for WebBackend {
+    fn group(&self, label: &str, fields: &Fields) {
+        let label = JsValue::from_str(label);
+        if fields.is_empty() {
+            web_sys::console::group_1(&label);
+        } else {
+            web_sys::console::group_2(&label, &WebBackend::object(fields));
+        }
+    }
+
+    fn group_end(&self) {
+        web_sys::console::group_end();
+    }
+
+    fn event(&self, level: Level, message: &str, fields: &Fields) {
+        let message = JsValue::from_str(message);
+        if fields.is_empty() {
+            logging::ConsoleSink.write_value(level, &message, None);
+        } else {
+            let fields = WebBackend::object(fields);
+            logging::ConsoleSink.write_value(level, &message, Some(&fields));
+        }
+    }
+
+    fn mark(&self, name: &str) {
+        performance_mark(name);
+    }
+
+    fn measure(&self, name: &str, start_mark: &str, end_mark: &str) {
+        performance_measure(name, start_mark, end_mark);
+    }
+}
+
+/// One call made on a [`RecordingBackend`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    Group {
+        label: String,
+        fields: Fields,
+    },
+    GroupEnd,
+    Event {
+        level: Level,
+        message: String,
+        fields: Fields,
+    },
+    Mark(String),
+    Measure {
+        name: String,
+        start_mark: String,
+        end_mark: String,
+    },
+}
+
+/// Records every call in memory. Clones share the same buffer.
+#[derive(Clone, Default)]
+pub struct RecordingBackend {
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        RecordingBackend::default()
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn push(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn group(&self, label: &str, fields: &Fields) {
+        self.push(Call::Group {
+            label: label.to_owned(),
+            fields: fields.clone(),
+        });
+    }
+
+    fn group_end(&self) {
+        self.push(Call::GroupEnd);
+    }
+
+    fn event(&self, level: Level, message: &str, fields: &Fields) {
+        self.push(Call::Event {
+            level,
+            message: message.to_owned(),
+            fields: fields.clone(),
+        });
+    }
+
+    fn mark(&self, name: &str) {
+        self.push(Call::Mark(name.to_owned()));
+    }
+
+    fn measure(&self, name: &str, start_mark: &str, end_mark: &str) {
+        self.push(Call::Measure {
+            name: name.to_owned(),
+            start_mark: start_mark.to_owned(),
+            end_mark: end_mark.to_owned(),
+        });
+    }
+}
+
+/// Collects fields into [`Fields`], pulling the `message` field out separately.
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Fields,
+}
+
+impl FieldVisitor {
+    fn push(&mut self, field: &Field, value: FieldValue) {
+        self.fields.push((field.name().to_owned(), value));
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, FieldValue::Bool(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, FieldValue::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, FieldValue::U64(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, FieldValue::F64(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_owned());
+        } else {
+            self.push(field, FieldValue::Str(value.to_owned()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        } else {
+            self.push(field, FieldValue::Str(format!("{value:?}")));
+        }
+    }
+}
+
+/// Fields recorded on a span, stored in its extensions.
+struct SpanFields(Fields);
+
+/// A `tracing` layer that opens a console group for every entered span and records
+/// each enter/exit pair as a `performance.measure` named after the span.
+pub struct ConsoleLayer<B = WebBackend> {
+    backend: B,
+}
+
+impl<B: Backend> ConsoleLayer<B> {
+    pub fn new(backend: B) -> ConsoleLayer<B> {
+        ConsoleLayer { backend }
+    }
+
+    fn mark_names(name: &str, id: &Id) -> (String, String) {
+        let id = id.into_u64();
+        (format!("{name}#{id}:start"), format!("{name}#{id}:end"))
+    }
+}
+
+impl<S, B> Layer<S> for ConsoleLayer<B>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    B: Backend,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(message) = visitor.message {
+            visitor
+                .fields
+                .insert(0, ("message".to_owned(), FieldValue::Str(message)));
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            for (name, value) in visitor.fields {
+                match fields.iter_mut().find(|(existing, _)| *existing == name) {
+                    Some((_, existing)) => *existing = value,
+                    None => fields.push((name, value)),
+                }
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = Level::from(*event.metadata().level());
+        if !logging::enabled(level) {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let message = visitor.message.unwrap_or_default();
+        self.backend.event(level, &message, &visitor.fields);
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let extensions = span.extensions();
+        let fields = extensions.get::<SpanFields>().map(|fields| &fields.0);
+        self.backend
+            .group(span.name(), fields.unwrap_or(&Vec::new()));
+
+        let (start, _) = Self::mark_names(span.name(), id);
+        self.backend.mark(&start);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let (start, end) = Self::mark_names(span.name(), id);
+        self.backend.mark(&end);
+        self.backend.measure(span.name(), &start, &end);
+        self.backend.group_end();
+    }
+}
+
+/// Installs a [`ConsoleLayer`] writing to the browser as the global `tracing`
+/// subscriber. Events below the level set with `set_log_level` are dropped.
+#[wasm_bindgen]
+pub fn init_tracing() {
+    let subscriber = Registry::default().with(ConsoleLayer::new(WebBackend));
+    // Fails only if a global subscriber is already installed, in which case we keep it.
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::tests::capture;
+
+    fn record(f: impl FnOnce()) -> Vec<Call> {
+        let backend = RecordingBackend::new();
+        let subscriber = Registry::default().with(ConsoleLayer::new(backend.clone()));
+        tracing::subscriber::with_default(subscriber, f);
+        backend.calls()
+    }
+
+    #[test]
+    fn spans_become_groups_with_measures() {
+        let (_guard, _) = capture(Level::Trace);
+        let calls = record(|| {
+            let span = tracing::info_span!("load", id = 7_u64, name = "users");
+            let _entered = span.enter();
+            tracing::info!(count = 3_i64, "fetched");
+        });
+        let [Call::Group { label, fields }, Call::Mark(start), event, Call::Mark(end), Call::Measure {
+            name,
+            start_mark,
+            end_mark,
+        }, Call::GroupEnd] = calls.as_slice()
+        else {
+            panic!("unexpected calls: {calls:?}");
+        };
+        assert_eq!(label, "load");
+        assert_eq!(
+            *fields,
+            [
+                ("id".to_owned(), FieldValue::U64(7)),
+                ("name".to_owned(), FieldValue::Str("users".to_owned())),
+            ]
+        );
+        assert!(
+            start.starts_with("load#") && start.ends_with(":start"),
+            "{start}"
+        );
+        assert_eq!(*end, start.replace(":start", ":end"));
+        assert_eq!(name, "load");
+        assert_eq!((start_mark, end_mark), (start, end));
+        assert_eq!(
+            *event,
+            Call::Event {
+                level: Level::Info,
+                message: "fetched".to_owned(),
+                fields: vec![("count".to_owned(), FieldValue::I64(3))],
+            }
+        );
+    }
+
+    #[test]
+    fn recorded_values_replace_and_extend_span_fields() {
+        let (_guard, _) = capture(Level::Trace);
+        let calls = record(|| {
+            let span =
+                tracing::info_span!("job", state = "queued", attempts = tracing::field::Empty);
+            span.record("state", "running");
+            span.record("attempts", 2_u64);
+            let _entered = span.enter();
+        });
+        assert_eq!(
+            calls[0],
+            Call::Group {
+                label: "job".to_owned(),
+                fields: vec![
+                    ("state".to_owned(), FieldValue::Str("running".to_owned())),
+                    ("attempts".to_owned(), FieldValue::U64(2)),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn events_below_the_level_are_dropped() {
+        let (_guard, _) = capture(Level::Warn);
+        let calls = record(|| {
+            tracing::debug!("hidden");
+            tracing::warn!(retry = true, "slow");
+        });
+        assert_eq!(
+            calls,
+            [Call::Event {
+                level: Level::Warn,
+                message: "slow".to_owned(),
+                fields: vec![("retry".to_owned(), FieldValue::Bool(true))],
+            }]
+        );
+    }
+}
@@ -0,0 +1,710 @@
+//! A virtual DOM: a lightweight tree describing what the DOM should look like, and a
+//! diff that turns the difference between two trees into a list of [`Patch`]es.
+//!
+//! Diffing and [`render_to_string`] are pure Rust; only [`Root`] touches the real DOM.
+
+mod apply;
+mod render;
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+pub(crate) use apply::DomNode;
+pub use apply::Root;
+pub use render::render_to_string;
+
+use crate::component::Instance;
+
+/// An event handler. Two handlers are equal only if they are the same closure.
+#[derive(Clone)]
+pub struct Handler(Rc<dyn Fn(web_sys::Event)>);
+
+impl Handler {
+    pub fn new(f: impl Fn(web_sys::Event) + 'static) -> Handler {
+        Handler(Rc::new(f))
+    }
+
+    pub fn call(&self, event: web_sys::Event) {
+        (self.0)(event)
+    }
+}
+
+impl PartialEq for Handler {
+    fn eq(&self, other: &Handler) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handler({:p})", Rc::as_ptr(&self.0))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VNode {
+    Element(VElement),
+    Text(String),
+    Component(VComponent),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VElement {
+    pub tag: String,
+    /// Identifies the element among its siblings so it can be moved instead of
+    /// recreated when the list is reordered.
+    pub key: Option<String>,
+    pub attrs: BTreeMap<String, String>,
+    pub listeners: BTreeMap<String, Handler>,
+    pub children: Vec<VNode>,
+}
+
+/// A component to instantiate with the given props. Created with
+/// [`comp`](crate::component::comp).
+#[derive(Clone)]
+pub struct VComponent {
+    pub(crate) type_id: TypeId,
+    pub(crate) name: &'static str,
+    pub key: Option<String>,
+    pub(crate) props: Rc<dyn Any>,
+    pub(crate) props_eq: fn(&dyn Any, &dyn Any) -> bool,
+    /// Content passed in by the parent, which the component places with
+    /// [`Context::children`](crate::component::Context::children).
+    pub children: Vec<VNode>,
+    pub(crate) instantiate: fn(&VComponent, Option<web_sys::Node>) -> Rc<dyn Instance>,
+    pub(crate) render: fn(&VComponent) -> VNode,
+}
+
+impl VComponent {
+    pub fn key(mut self, key: impl Into<String>) -> VComponent {
+        self.key = Some(key.into());
+        self
+    }
+
+    pub fn child(mut self, child: impl Into<VNode>) -> VComponent {
+        self.children.push(child.into());
+        self
+    }
+
+    pub fn children(mut self, children: impl IntoIterator<Item = VNode>) -> VComponent {
+        self.children.extend(children);
+        self
+    }
+
+    fn same_component(&self, other: &VComponent) -> bool {
+        self.type_id == other.type_id && self.key == other.key
+    }
+}
+
+impl PartialEq for VComponent {
+    fn eq(&self, other: &VComponent) -> bool {
+        self.same_component(other)
+            && (self.props_eq)(&*self.props, &*other.props)
+            && self.children == other.children
+    }
+}
+
+impl fmt::Debug for VComponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VComponent")
+            .field("name", &self.name)
+            .field("key", &self.key)
+            .field("children", &self.children)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Starts an element with the given tag name.
+pub fn h(tag: &str) -> VElement {
+    VElement {
+        tag: tag.to_owned(),
+        key: None,
+        attrs: BTreeMap::new(),
+        listeners: BTreeMap::new(),
+        children: Vec::new(),
+    }
+}
+
+impl VElement {
+    pub fn key(mut self, key: impl Into<String>) -> VElement {
+        self.key = Some(key.into());
+        self
+    }
+
+    pub fn attr(mut self, name: &str, value: impl Into<String>) -> VElement {
+        self.attrs.insert(name.to_owned(), value.into());
+        self
+    }
+
+    pub fn class(self, value: impl Into<String>) -> VElement {
+        self.attr("class", value)
+    }
+
+    pub fn on(mut self, event: &str, handler: impl Fn(web_sys::Event) + 'static) -> VElement {
+        self.listeners
+            .insert(event.to_owned(), Handler::new(handler));
+        self
+    }
+
+    pub fn child(mut self, child: impl Into<VNode>) -> VElement {
+        self.children.push(child.into());
+        self
+    }
+
+    pub fn children(mut self, children: impl IntoIterator<Item = VNode>) -> VElement {
+        self.children.extend(children);
+        self
+    }
+
+    pub fn text(self, text: impl Into<String>) -> VElement {
+        self.child(VNode::Text(text.into()))
+    }
+}
+
+impl VNode {
+    pub fn text(text: impl Into<String>) -> VNode {
+        VNode::Text(text.into())
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            VNode::Element(element) => element.key.as_deref(),
+            VNode::Component(component) => component.key.as_deref(),
+            VNode::Text(_) => None,
+        }
+    }
+}
+
+impl From<VElement> for VNode {
+    fn from(element: VElement) -> VNode {
+        VNode::Element(element)
+    }
+}
+
+impl From<VComponent> for VNode {
+    fn from(component: VComponent) -> VNode {
+        VNode::Component(component)
+    }
+}
+
+impl From<&str> for VNode {
+    fn from(text: &str) -> VNode {
+        VNode::Text(text.to_owned())
+    }
+}
+
+impl From<String> for VNode {
+    fn from(text: String) -> VNode {
+        VNode::Text(text)
+    }
+}
+
+/// Child indices leading from the root to a node. The empty path is the root.
+pub type Path = Vec<usize>;
+
+/// One DOM operation. Patches are applied in order, and every path and index refers to
+/// the tree as it is after the patches before it have been applied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Patch {
+    Replace {
+        path: Path,
+        node: VNode,
+    },
+    SetText {
+        path: Path,
+        text: String,
+    },
+    SetAttribute {
+        path: Path,
+        name: String,
+        value: String,
+    },
+    RemoveAttribute {
+        path: Path,
+        name: String,
+    },
+    SetListener {
+        path: Path,
+        event: String,
+        handler: Handler,
+    },
+    RemoveListener {
+        path: Path,
+        event: String,
+    },
+    InsertChild {
+        parent: Path,
+        index: usize,
+        node: VNode,
+    },
+    RemoveChild {
+        parent: Path,
+        index: usize,
+    },
+    /// Takes the child at `from` out and reinserts it so that it ends up at `to`.
+    MoveChild {
+        parent: Path,
+        from: usize,
+        to: usize,
+    },
+    /// Hands new props or children to a mounted component.
+    UpdateComponent {
+        path: Path,
+        node: VComponent,
+    },
+}
+
+/// Returns the patches that turn `old` into `new`.
+pub fn diff(old: &VNode, new: &VNode) -> Vec<Patch> {
+    let mut patches = Vec::new();
+    diff_node(old, new, &mut Vec::new(), &mut patches);
+    patches
+}
+
+fn diff_node(old: &VNode, new: &VNode, path: &mut Path, patches: &mut Vec<Patch>) {
+    match (old, new) {
+        (VNode::Text(old), VNode::Text(new)) => {
+            if old != new {
+                patches.push(Patch::SetText {
+                    path: path.clone(),
+                    text: new.clone(),
+                });
+            }
+        }
+        (VNode::Element(old), VNode::Element(new)) if old.tag == new.tag && old.key == new.key => {
+            diff_attrs(old, new, path, patches);
+            diff_listeners(old, new, path, patches);
+            diff_children(&old.children, &new.children, path, patches);
+        }
+        (VNode::Component(old), VNode::Component(new)) if old.same_component(new) => {
+            if old != new {
+                patches.push(Patch::UpdateComponent {
+                    path: path.clone(),
+                    node: new.clone(),
+                });
+            }
+        }
+        _ => patches.push(Patch::Replace {
+            path: path.clone(),
+            node: new.clone(),
+        }),
+    }
+}
+
+fn diff_attrs(old: &VElement, new: &VElement, path: &Path, patches: &mut Vec<Patch>) {
+    for name in old.attrs.keys() {
+        if !new.attrs.contains_key(name) {
+            patches.push(Patch::RemoveAttribute {
+                path: path.clone(),
+                name: name.clone(),
+            });
+        }
+    }
+    for (name, value) in &new.attrs {
+        if old.attrs.get(name) != Some(value) {
+            patches.push(Patch::SetAttribute {
+                path: path.clone(),
+                name: name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+fn diff_listeners(old: &VElement, new: &VElement, path: &Path, patches: &mut Vec<Patch>) {
+    for event in old.listeners.keys() {
+        if !new.listeners.contains_key(event) {
+            patches.push(Patch::RemoveListener {
+                path: path.clone(),
+                event: event.clone(),
+            });
+        }
+    }
+    for (event, handler) in &new.listeners {
+        if old.listeners.get(event) != Some(handler) {
+            patches.push(Patch::SetListener {
+                path: path.clone(),
+                event: event.clone(),
+                handler: handler.clone(),
+            });
+        }
+    }
+}
+
+fn diff_children(old: &[VNode], new: &[VNode], path: &mut Path, patches: &mut Vec<Patch>) {
+    if is_keyed(old) && is_keyed(new) {
+        diff_keyed_children(old, new, path, patches);
+        return;
+    }
+
+    let common = old.len().min(new.len());
+    for (index, (old, new)) in old.iter().zip(new).enumerate() {
+        path.push(index);
+        diff_node(old, new, path, patches);
+        path.pop();
+    }
+    for index in (common..old.len()).rev() {
+        patches.push(Patch::RemoveChild {
+            parent: path.clone(),
+            index,
+        });
+    }
+    for (index, node) in new.iter().enumerate().skip(common) {
+        patches.push(Patch::InsertChild {
+            parent: path.clone(),
+            index,
+            node: node.clone(),
+        });
+    }
+}
+
+/// Whether every child has a key, and no key is used twice.
+fn is_keyed(children: &[VNode]) -> bool {
+    let mut keys = HashSet::new();
+    !children.is_empty()
+        && children
+            .iter()
+            .all(|child| child.key().is_some_and(|key| keys.insert(key)))
+}
+
+/// Reorders keyed children with as few moves as possible. Children whose old positions
+/// form the longest increasing run in the new order stay put, and everything else is
+/// moved or inserted in front of its new next sibling, working backwards.
+fn diff_keyed_children(old: &[VNode], new: &[VNode], path: &mut Path, patches: &mut Vec<Patch>) {
+    let new_keys: HashSet<&str> = new.iter().filter_map(VNode::key).collect();
+
+    // Drop the children that are gone, from the back so indices stay valid.
+    let mut current: Vec<&str> = Vec::with_capacity(old.len());
+    let mut old_by_key = HashMap::new();
+    for (index, child) in old.iter().enumerate().rev() {
+        let key = child.key().unwrap();
+        if new_keys.contains(key) {
+            old_by_key.insert(key, child);
+        } else {
+            patches.push(Patch::RemoveChild {
+                parent: path.clone(),
+                index,
+            });
+        }
+    }
+    current.extend(
+        old.iter()
+            .filter_map(VNode::key)
+            .filter(|key| new_keys.contains(key)),
+    );
+
+    let positions: HashMap<&str, usize> =
+        current.iter().enumerate().map(|(i, &k)| (k, i)).collect();
+    let sequence: Vec<(usize, usize)> = new
+        .iter()
+        .enumerate()
+        .filter_map(|(index, child)| Some((index, *positions.get(child.key()?)?)))
+        .collect();
+    let stable: HashSet<usize> = longest_increasing_subsequence(&sequence)
+        .into_iter()
+        .collect();
+
+    for (index, child) in new.iter().enumerate().rev() {
+        let key = child.key().unwrap();
+        if stable.contains(&index) {
+            continue;
+        }
+        // The child goes right before its new next sibling, which is already in place.
+        let before = match new.get(index + 1) {
+            Some(next) => current
+                .iter()
+                .position(|&k| k == next.key().unwrap())
+                .unwrap(),
+            None => current.len(),
+        };
+        match current.iter().position(|&k| k == key) {
+            Some(from) => {
+                let to = if from < before { before - 1 } else { before };
+                if from != to {
+                    patches.push(Patch::MoveChild {
+                        parent: path.clone(),
+                        from,
+                        to,
+                    });
+                }
+                current.remove(from);
+                current.insert(to, key);
+            }
+            None => {
+                patches.push(Patch::InsertChild {
+                    parent: path.clone(),
+                    index: before,
+                    node: child.clone(),
+                });
+                current.insert(before, key);
+            }
+        }
+    }
+
+    for (index, child) in new.iter().enumerate() {
+        if let Some(old) = old_by_key.get(child.key().unwrap()) {
+            path.push(index);
+            diff_node(old, child, path, patches);
+            path.pop();
+        }
+    }
+}
+
+/// Takes `(new index, old index)` pairs ordered by new index and returns the new
+/// indices of a longest run whose old indices are increasing.
+fn longest_increasing_subsequence(sequence: &[(usize, usize)]) -> Vec<usize> {
+    // `tails[len]` is the position in `sequence` of the smallest old index that ends an
+    // increasing run of length `len + 1`.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; sequence.len()];
+    for (i, &(_, old)) in sequence.iter().enumerate() {
+        let len = tails.partition_point(|&tail| sequence[tail].1 < old);
+        if len > 0 {
+            previous[i] = Some(tails[len - 1]);
+        }
+        if len == tails.len() {
+            tails.push(i);
+        } else {
+            tails[len] = i;
+        }
+    }
+
+    let mut result = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        result.push(sequence[i].0);
+        next = previous[i];
+    }
+    result.reverse();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies `patches` to a copy of `tree`, the way [`Root`] applies them to the DOM.
+    fn apply(tree: &VNode, patches: &[Patch]) -> VNode {
+        fn at<'a>(node: &'a mut VNode, path: &[usize]) -> &'a mut VNode {
+            path.iter().fold(node, |node, &index| match node {
+                VNode::Element(element) => &mut element.children[index],
+                _ => panic!("path goes through a non-element"),
+            })
+        }
+        fn children<'a>(node: &'a mut VNode, path: &[usize]) -> &'a mut Vec<VNode> {
+            match at(node, path) {
+                VNode::Element(element) => &mut element.children,
+                _ => panic!("parent is not an element"),
+            }
+        }
+        fn element<'a>(node: &'a mut VNode, path: &[usize]) -> &'a mut VElement {
+            match at(node, path) {
+                VNode::Element(element) => element,
+                _ => panic!("not an element"),
+            }
+        }
+
+        let mut tree = tree.clone();
+        for patch in patches {
+            match patch.clone() {
+                Patch::Replace { path, node } => *at(&mut tree, &path) = node,
+                Patch::SetText { path, text } => *at(&mut tree, &path) = VNode::Text(text),
+                Patch::SetAttribute { path, name, value } => {
+                    element(&mut tree, &path).attrs.insert(name, value);
+                }
+                Patch::RemoveAttribute { path, name } => {
+                    element(&mut tree, &path).attrs.remove(&name);
+                }
+                Patch::SetListener {
+                    path,
+                    event,
+                    handler,
+                } => {
+                    element(&mut tree, &path).listeners.insert(event, handler);
+                }
+                Patch::RemoveListener { path, event } => {
+                    element(&mut tree, &path).listeners.remove(&event);
+                }
+                Patch::InsertChild {
+                    parent,
+                    index,
+                    node,
+                } => children(&mut tree, &parent).insert(index, node),
+                Patch::RemoveChild { parent, index } => {
+                    children(&mut tree, &parent).remove(index);
+                }
+                Patch::MoveChild { parent, from, to } => {
+                    let children = children(&mut tree, &parent);
+                    let child = children.remove(from);
+                    children.insert(to, child);
+                }
+                Patch::UpdateComponent { path, node } => {
+                    *at(&mut tree, &path) = VNode::Component(node)
+                }
+            }
+        }
+        tree
+    }
+
+    fn list(keys: &[u32]) -> VNode {
+        h("ul")
+            .children(
+                keys.iter()
+                    .map(|key| h("li").key(key.to_string()).text(key.to_string()).into()),
+            )
+            .into()
+    }
+
+    #[test]
+    fn identical_trees_need_no_patches() {
+        let tree: VNode = h("p").class("intro").text("hi").into();
+        assert_eq!(diff(&tree, &tree.clone()), []);
+    }
+
+    #[test]
+    fn text_and_attributes_are_patched_in_place() {
+        let old = h("a").attr("href", "/").attr("title", "home").text("Home");
+        let new = h("a")
+            .attr("href", "/about")
+            .attr("rel", "next")
+            .text("About");
+        assert_eq!(
+            diff(&old.into(), &new.into()),
+            [
+                Patch::RemoveAttribute {
+                    path: vec![],
+                    name: "title".to_owned(),
+                },
+                Patch::SetAttribute {
+                    path: vec![],
+                    name: "href".to_owned(),
+                    value: "/about".to_owned(),
+                },
+                Patch::SetAttribute {
+                    path: vec![],
+                    name: "rel".to_owned(),
+                    value: "next".to_owned(),
+                },
+                Patch::SetText {
+                    path: vec![0],
+                    text: "About".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_different_tag_replaces_the_node() {
+        let new: VNode = h("section").into();
+        assert_eq!(
+            diff(&h("div").text("x").into(), &new),
+            [Patch::Replace {
+                path: vec![],
+                node: new.clone(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unkeyed_children_are_removed_from_the_back_and_appended() {
+        let old: VNode = h("div").text("a").text("b").text("c").into();
+        let shorter: VNode = h("div").text("a").into();
+        assert_eq!(
+            diff(&old, &shorter),
+            [
+                Patch::RemoveChild {
+                    parent: vec![],
+                    index: 2,
+                },
+                Patch::RemoveChild {
+                    parent: vec![],
+                    index: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            diff(&shorter, &old),
+            [
+                Patch::InsertChild {
+                    parent: vec![],
+                    index: 1,
+                    node: VNode::text("b"),
+                },
+                Patch::InsertChild {
+                    parent: vec![],
+                    index: 2,
+                    node: VNode::text("c"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keyed_children_move_instead_of_being_recreated() {
+        // Moving the last item to the front is a single move.
+        assert_eq!(
+            diff(&list(&[1, 2, 3, 4]), &list(&[4, 1, 2, 3])),
+            [Patch::MoveChild {
+                parent: vec![],
+                from: 3,
+                to: 0,
+            }]
+        );
+        let patches = diff(&list(&[1, 2, 3]), &list(&[3, 2, 1]));
+        assert!(patches
+            .iter()
+            .all(|patch| matches!(patch, Patch::MoveChild { .. })));
+        assert_eq!(patches.len(), 2);
+    }
+
+    #[test]
+    fn keyed_children_are_inserted_and_removed() {
+        assert_eq!(
+            diff(&list(&[1, 2, 3]), &list(&[1, 4, 3])),
+            [
+                Patch::RemoveChild {
+                    parent: vec![],
+                    index: 1,
+                },
+                Patch::InsertChild {
+                    parent: vec![],
+                    index: 1,
+                    node: h("li").key("4").text("4").into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn patches_turn_the_old_tree_into_the_new_one() {
+        // A small linear congruential generator keeps the cases the same on every run.
+        let mut seed = 0x2545_f491_u64;
+        let mut next = move |bound: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        let mut pick = move || {
+            let mut keys: Vec<u32> = (0..10).collect();
+            for i in (1..keys.len()).rev() {
+                keys.swap(i, next(i as u64 + 1) as usize);
+            }
+            keys.truncate(next(8) as usize);
+            list(&keys)
+        };
+        for _ in 0..500 {
+            let (old, new) = (pick(), pick());
+            assert_eq!(apply(&old, &diff(&old, &new)), new, "{old:?} -> {new:?}");
+        }
+    }
+
+    #[test]
+    fn longest_increasing_subsequence_keeps_the_longest_run() {
+        let sequence = [(0, 3), (1, 0), (2, 1), (3, 4), (4, 2)];
+        assert_eq!(longest_increasing_subsequence(&sequence), [1, 2, 4]);
+        assert_eq!(longest_increasing_subsequence(&[]), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use super::{diff, Handler, Patch, VNode};
+use crate::component::Instance;
+use crate::dom::{document, Listener};
+
+/// The real DOM node behind a [`VNode`], with its listeners and children. Patch paths
+/// are resolved through this tree rather than `childNodes`, so nodes added to the DOM
+/// by other code do not throw the indices off.
+pub(crate) struct DomNode {
+    node: web_sys::Node,
+    listeners: HashMap<String, Listener>,
+    children: Vec<DomNode>,
+    /// Set for component nodes, whose DOM node is whatever the component last rendered.
+    component: Option<Rc<dyn Instance>>,
+}
+
+impl DomNode {
+    pub(crate) fn create(vnode: &VNode) -> DomNode {
+        match vnode {
+            VNode::Text(text) => DomNode {
+                node: document().create_text_node(text).into(),
+                listeners: HashMap::new(),
+                children: Vec::new(),
+                component: None,
+            },
+            VNode::Element(element) => {
+                let node = document()
+                    .create_element(&element.tag)
+                    .expect_throw("invalid tag name");
+                for (name, value) in &element.attrs {
+                    node.set_attribute(name, value)
+                        .expect_throw("invalid attribute name");
+                }
+                let listeners = element
+                    .listeners
+                    .iter()
+                    .map(|(event, handler)| (event.clone(), listen(&node, event, handler)))
+                    .collect();
+                let children = element
+                    .children
+                    .iter()
+                    .map(|child| {
+                        let child = DomNode::create(child);
+                        node.append_child(&child.dom())
+                            .expect_throw("failed to append child");
+                        child
+                    })
+                    .collect();
+                DomNode {
+                    node: node.into(),
+                    listeners,
+                    children,
+                    component: None,
+                }
+            }
+            VNode::Component(component) => {
+                let instance = (component.instantiate)(component, None);
+                DomNode {
+                    node: instance.node(),
+                    listeners: HashMap::new(),
+                    children: Vec::new(),
+                    component: Some(instance),
+                }
+            }
+        }
+    }
+
+    /// Adopts `node`, which should have been rendered from `vnode` by
+    /// [`render_to_string`](super::render_to_string), attaching listeners and
+    /// components to it instead of creating new DOM. Where the markup does not match,
+    /// fresh DOM replaces it.
+    pub(crate) fn hydrate(vnode: &VNode, node: web_sys::Node) -> DomNode {
+        match vnode {
+            VNode::Text(text) if node.node_type() == web_sys::Node::TEXT_NODE => {
+                if node.text_content().as_deref() != Some(text.as_str()) {
+                    node.unchecked_ref::<web_sys::CharacterData>()
+                        .set_data(text);
+                }
+                DomNode {
+                    node,
+                    listeners: HashMap::new(),
+                    children: Vec::new(),
+                    component: None,
+                }
+            }
+            VNode::Element(element) if node.node_name().eq_ignore_ascii_case(&element.tag) => {
+                let listeners = element
+                    .listeners
+                    .iter()
+                    .map(|(event, handler)| (event.clone(), listen(&node, event, handler)))
+                    .collect();
+                let existing = content_nodes(&node);
+                let mut existing = existing.into_iter().peekable();
+                let children = element
+                    .children
+                    .iter()
+                    .map(|child| {
+                        let empty_text = matches!(child, VNode::Text(text) if text.is_empty());
+                        // Empty text renders to nothing, so there is no node to adopt.
+                        match existing.next_if(|_| !empty_text) {
+                            Some(existing) => DomNode::hydrate(child, existing),
+                            None => {
+                                let created = DomNode::create(child);
+                                node.insert_before(&created.dom(), existing.peek())
+                                    .expect_throw("failed to insert node");
+                                created
+                            }
+                        }
+                    })
+                    .collect();
+                for extra in existing {
+                    let _ = node.remove_child(&extra);
+                }
+                DomNode {
+                    node,
+                    listeners,
+                    children,
+                    component: None,
+                }
+            }
+            VNode::Component(component) => {
+                let instance = (component.instantiate)(component, Some(node));
+                DomNode {
+                    node: instance.node(),
+                    listeners: HashMap::new(),
+                    children: Vec::new(),
+                    component: Some(instance),
+                }
+            }
+            _ => {
+                log::warn!(
+                    "hydration mismatch: found <{}>, expected {vnode:?}",
+                    node.node_name()
+                );
+                let created = DomNode::create(vnode);
+                if let Some(parent) = node.parent_node() {
+                    parent
+                        .replace_child(&created.dom(), &node)
+                        .expect_throw("failed to replace node");
+                }
+                created
+            }
+        }
+    }
+
+    /// The DOM node currently standing for this node.
+    /// A node with no DOM behind it, for testing components natively. Patching it
+    /// panics, so their views must not change.
+    #[cfg(test)]
+    pub(crate) fn detached() -> DomNode {
+        DomNode {
+            node: JsValue::UNDEFINED.unchecked_into(),
+            listeners: HashMap::new(),
+            children: Vec::new(),
+            component: None,
+        }
+    }
+
+    pub(crate) fn dom(&self) -> web_sys::Node {
+        match &self.component {
+            Some(component) => component.node(),
+            None => self.node.clone(),
+        }
+    }
+
+    fn at(&mut self, path: &[usize]) -> &mut DomNode {
+        path.iter()
+            .fold(self, |node, &index| &mut node.children[index])
+    }
+
+    fn element(&self) -> &web_sys::Element {
+        self.node.unchecked_ref()
+    }
+
+    /// Runs the `mounted` hook of every component in the tree, children first.
+    pub(crate) fn notify_mounted(&self) {
+        for child in &self.children {
+            child.notify_mounted();
+        }
+        if let Some(component) = &self.component {
+            component.mounted();
+        }
+    }
+
+    /// Applies one patch, where paths are relative to this node.
+    pub(crate) fn apply(&mut self, patch: Patch) {
+        match patch {
+            Patch::Replace { path, node } => {
+                let new = DomNode::create(&node);
+                let old = self.at(&path);
+                let old_dom = old.dom();
+                if let Some(parent) = old_dom.parent_node() {
+                    parent
+                        .replace_child(&new.dom(), &old_dom)
+                        .expect_throw("failed to replace node");
+                }
+                *old = new;
+                old.notify_mounted();
+            }
+            Patch::SetText { path, text } => {
+                self.at(&path).node.set_text_content(Some(&text));
+            }
+            Patch::SetAttribute { path, name, value } => {
+                self.at(&path)
+                    .element()
+                    .set_attribute(&name, &value)
+                    .expect_throw("invalid attribute name");
+            }
+            Patch::RemoveAttribute { path, name } => {
+                let _ = self.at(&path).element().remove_attribute(&name);
+            }
+            Patch::SetListener {
+                path,
+                event,
+                handler,
+            } => {
+                let node = self.at(&path);
+                let listener = listen(&node.node, &event, &handler);
+                node.listeners.insert(event, listener);
+            }
+            Patch::RemoveListener { path, event } => {
+                self.at(&path).listeners.remove(&event);
+            }
+            Patch::InsertChild {
+                parent,
+                index,
+                node,
+            } => {
+                let parent = self.at(&parent);
+                let child = DomNode::create(&node);
+                let reference = parent.children.get(index).map(DomNode::dom);
+                parent
+                    .node
+                    .insert_before(&child.dom(), reference.as_ref())
+                    .expect_throw("failed to insert node");
+                child.notify_mounted();
+                parent.children.insert(index, child);
+            }
+            Patch::RemoveChild { parent, index } => {
+                let parent = self.at(&parent);
+                let child = parent.children.remove(index);
+                let _ = parent.node.remove_child(&child.dom());
+            }
+            Patch::MoveChild { parent, from, to } => {
+                let parent = self.at(&parent);
+                let child = parent.children.remove(from);
+                let reference = parent.children.get(to).map(DomNode::dom);
+                parent
+                    .node
+                    .insert_before(&child.dom(), reference.as_ref())
+                    .expect_throw("failed to move node");
+                parent.children.insert(to, child);
+            }
+            Patch::UpdateComponent { path, node } => {
+                if let Some(component) = &self.at(&path).component {
+                    component.set_props(&node);
+                }
+            }
+        }
+    }
+}
+
+/// The child nodes of `node` that stand for virtual nodes, which leaves out the comments
+/// separating adjacent text.
+fn content_nodes(node: &web_sys::Node) -> Vec<web_sys::Node> {
+    let mut nodes = Vec::new();
+    let mut child = node.first_child();
+    while let Some(node) = child {
+        child = node.next_sibling();
+        if node.node_type() != web_sys::Node::COMMENT_NODE {
+            nodes.push(node);
+        }
+    }
+    nodes
+}
+
+fn listen(target: &web_sys::EventTarget, event: &str, handler: &Handler) -> Listener {
+    let handler = handler.clone();
+    Listener::new(target, event, move |event| handler.call(event))
+}
+
+/// A virtual tree rendered into the DOM. Updating it applies only the differences, and
+/// dropping it removes what it rendered.
+pub struct Root {
+    parent: web_sys::Node,
+    vnode: VNode,
+    tree: DomNode,
+}
+
+impl Root {
+    /// Renders `vnode` and appends it to `parent`.
+    pub fn mount(parent: &web_sys::Node, vnode: VNode) -> Root {
+        let tree = DomNode::create(&vnode);
+        parent
+            .append_child(&tree.dom())
+            .expect_throw("failed to append node");
+        tree.notify_mounted();
+        Root {
+            parent: parent.clone(),
+            vnode,
+            tree,
+        }
+    }
+
+    /// Adopts markup that [`render_to_string`](super::render_to_string) rendered from
+    /// `vnode` into `parent`, attaching event handlers and starting components without
+    /// recreating the DOM.
+    pub fn hydrate(parent: &web_sys::Node, vnode: VNode) -> Root {
+        let tree = match content_nodes(parent).into_iter().next() {
+            Some(existing) => DomNode::hydrate(&vnode, existing),
+            None => {
+                let tree = DomNode::create(&vnode);
+                parent
+                    .append_child(&tree.dom())
+                    .expect_throw("failed to append node");
+                tree
+            }
+        };
+        tree.notify_mounted();
+        Root {
+            parent: parent.clone(),
+            vnode,
+            tree,
+        }
+    }
+
+    pub fn vnode(&self) -> &VNode {
+        &self.vnode
+    }
+
+    /// The DOM node the root virtual node was rendered to.
+    pub fn node(&self) -> web_sys::Node {
+        self.tree.dom()
+    }
+
+    /// Changes the DOM to match `vnode`.
+    pub fn update(&mut self, vnode: VNode) {
+        for patch in diff(&self.vnode, &vnode) {
+            self.tree.apply(patch);
+        }
+        self.vnode = vnode;
+    }
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        let _ = self.parent.remove_child(&self.tree.dom());
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::Write;
+
+use super::VNode;
+
+/// Elements that have no closing tag.
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Renders a tree to HTML, running components' views without mounting them. Event
+/// handlers are left out; [`Root::hydrate`](super::Root::hydrate) attaches them in the
+/// browser.
+///
+/// ```
+/// use hello_wasm::vdom::{h, render_to_string};
+///
+/// let node = h("p").class("note").text("1 < 2").child(h("br")).into();
+/// assert_eq!(render_to_string(&node), r#"<p class="note">1 &lt; 2<br></p>"#);
+/// ```
+pub fn render_to_string(node: &VNode) -> String {
+    let mut html = String::new();
+    render(node, &mut html);
+    html
+}
+
+fn render(node: &VNode, html: &mut String) {
+    match node {
+        VNode::Text(text) => escape(text, html),
+        VNode::Element(element) => {
+            let _ = write!(html, "<{}", element.tag);
+            for (name, value) in &element.attrs {
+                let _ = write!(html, " {name}=\"");
+                escape(value, html);
+                html.push('"');
+            }
+            html.push('>');
+            if VOID_TAGS.contains(&element.tag.as_str()) {
+                return;
+            }
+            let mut previous_text = false;
+            for child in &element.children {
+                let text = matches!(child, VNode::Text(_));
+                // The parser would merge adjacent text into one node, which hydration
+                // would then fail to line up with the virtual tree.
+                if text && previous_text {
+                    html.push_str("<!---->");
+                }
+                previous_text = text;
+                render(child, html);
+            }
+            let _ = write!(html, "</{}>", element.tag);
+        }
+        VNode::Component(component) => render(&(component.render)(component), html),
+    }
+}
+
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{comp, Component, Context};
+    use crate::vdom::{h, VNode};
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        let node = h("a")
+            .attr("title", r#"say "hi" & <bye>"#)
+            .text("Tom & Jerry <3")
+            .into();
+        assert_eq!(
+            render_to_string(&node),
+            r#"<a title="say &quot;hi&quot; &amp; &lt;bye&gt;">Tom &amp; Jerry &lt;3</a>"#
+        );
+    }
+
+    #[test]
+    fn leaves_void_elements_unclosed() {
+        let node = h("p")
+            .child(h("img").attr("src", "a.png"))
+            .child(h("br"))
+            .child(h("input").attr("value", "x"))
+            .into();
+        assert_eq!(
+            render_to_string(&node),
+            r#"<p><img src="a.png"><br><input value="x"></p>"#
+        );
+    }
+
+    #[test]
+    fn separates_adjacent_text() {
+        let node = h("p").text("a").text("b").child(h("br")).text("c").into();
+        assert_eq!(render_to_string(&node), "<p>a<!---->b<br>c</p>");
+
+        // Empty text renders nothing, but still needs the separator so that hydration
+        // does not adopt the next text node for it.
+        let node = h("p").text("").text("a").into();
+        assert_eq!(render_to_string(&node), "<p><!---->a</p>");
+    }
+
+    #[test]
+    fn renders_component_views() {
+        #[derive(PartialEq)]
+        struct Props {
+            name: &'static str,
+        }
+        struct Greeting;
+        impl Component for Greeting {
+            type Props = Props;
+            fn create(_ctx: &Context<Self>) -> Self {
+                Greeting
+            }
+            fn view(&self, ctx: &Context<Self>) -> VNode {
+                h("div")
+                    .text(format!("Hello, {}!", ctx.props().name))
+                    .children(ctx.children().iter().cloned())
+                    .into()
+            }
+        }
+
+        let node = h("main")
+            .child(comp::<Greeting>(Props { name: "Ferris" }).child(h("hr")))
+            .into();
+        assert_eq!(
+            render_to_string(&node),
+            "<main><div>Hello, Ferris!<hr></div></main>"
+        );
+    }
+
+    #[test]
+    fn renders_the_app() {
+        let nav = concat!(
+            r#"<nav><a href="/">Home</a> | <a href="/about">About</a>"#,
+            r#" | <a href="/users">Users</a></nav>"#,
+        );
+        assert_eq!(
+            crate::render_app("/"),
+            format!(
+                "<main><h1>Hello WASM!</h1>{nav}<section class=\"card\"><h2>Counter</h2>\
+                 <p>Clicked 0 times</p><button>Click me</button></section></main>"
+            )
+        );
+        assert_eq!(
+            crate::render_app("/users"),
+            format!(
+                "<main><h1>Hello WASM!</h1>{nav}<ul>\
+                 <li><a href=\"/users/1\">User 1</a></li>\
+                 <li><a href=\"/users/2\">User 2</a></li>\
+                 <li><a href=\"/users/3\">User 3</a></li></ul></main>"
+            )
+        );
+        assert_eq!(
+            crate::render_app("/nowhere"),
+            format!("<main><h1>Hello WASM!</h1>{nav}<p>Page not found.</p></main>")
+        );
+    }
+}
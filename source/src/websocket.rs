@@ -0,0 +1,946 @@
+//! A WebSocket client that stays connected. [`WebSocket`] is a [`Stream`] of incoming
+//! messages and a [`Sink`] for outgoing ones. It reopens dropped connections with
+//! exponential backoff, can send heartbeat pings to notice connections that died
+//! quietly, and holds on to messages sent while it is disconnected. [`Framed`] encodes
+//! and decodes messages as JSON or MessagePack.
+//!
+//! Connections are made by a [`Backend`]: [`WebBackend`] uses the browser's
+//! `WebSocket`, and `NativeBackend` a blocking client on a thread of its own, so the
+//! same code can be run natively against an `EchoServer`:
+//!
+//! ```
+//! use futures::executor::block_on;
+//! use futures::{SinkExt, StreamExt};
+//! use hello_wasm::websocket::{Config, EchoServer, Message, NativeBackend};
+//!
+//! let server = EchoServer::start().unwrap();
+//! let mut socket = Config::new(server.url()).connect_with(NativeBackend).unwrap();
+//! block_on(async {
+//!     socket.send(Message::from("hello")).await.unwrap();
+//!     assert_eq!(socket.next().await, Some(Ok(Message::from("hello"))));
+//! });
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{Sink, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::js_message;
+use crate::http;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{EchoServer, NativeBackend, NativeConnection};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Message {
+    /// The message's payload, which for text is its UTF-8 encoding.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(data) => data,
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Message {
+        Message::Text(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Message {
+        Message::Text(text.to_owned())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(data: Vec<u8>) -> Message {
+        Message::Binary(data)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The backend cannot connect to the URL, such as one that is not `ws://` or
+    /// `wss://`.
+    InvalidUrl(String),
+    /// The connection closed and will not be reopened, because reconnecting is turned
+    /// off, ran out of attempts or the socket was closed on purpose.
+    Closed(String),
+    /// An outgoing message could not be serialised.
+    Encode(String),
+    /// An incoming message was not what was expected.
+    Decode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(message) => write!(f, "invalid WebSocket URL: {message}"),
+            Error::Closed(reason) => write!(f, "the connection closed: {reason}"),
+            Error::Encode(message) => write!(f, "failed to encode the message: {message}"),
+            Error::Decode(message) => write!(f, "failed to decode the message: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// How to reopen a dropped connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconnect {
+    /// The wait before the first attempt, doubled after each one that fails.
+    pub initial: Duration,
+    /// The longest wait between attempts.
+    pub max: Duration,
+    /// How many attempts in a row may fail before giving up, or `None` to keep trying.
+    pub attempts: Option<u32>,
+}
+
+impl Reconnect {
+    /// The wait before trying again after `failures` failed attempts in a row.
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.max)
+    }
+}
+
+impl Default for Reconnect {
+    fn default() -> Reconnect {
+        Reconnect {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            attempts: None,
+        }
+    }
+}
+
+/// Pings sent while the connection is open. If nothing at all arrives between one ping
+/// and the next, the connection is taken to be dead and reopened.
+///
+/// Browsers do not expose the protocol's own ping frames, so these are ordinary
+/// messages that the server has to answer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub ping: Message,
+    /// The server's answer to `ping`, which is dropped rather than passed on.
+    pub pong: Option<Message>,
+}
+
+impl Heartbeat {
+    /// Sends `"ping"` every `interval` and expects `"pong"` back.
+    pub fn new(interval: Duration) -> Heartbeat {
+        Heartbeat {
+            interval,
+            ping: Message::from("ping"),
+            pong: Some(Message::from("pong")),
+        }
+    }
+}
+
+/// A connection's settings. Nothing happens until [`connect`](Config::connect).
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub url: String,
+    /// How to reopen dropped connections, or `None` to end the stream when the first
+    /// one closes.
+    pub reconnect: Option<Reconnect>,
+    pub heartbeat: Option<Heartbeat>,
+    /// How many outgoing messages are held while disconnected before sending waits.
+    pub buffer: usize,
+}
+
+impl Config {
+    pub fn new(url: impl Into<String>) -> Config {
+        Config {
+            url: url.into(),
+            reconnect: Some(Reconnect::default()),
+            heartbeat: None,
+            buffer: 256,
+        }
+    }
+
+    pub fn reconnect(mut self, reconnect: Reconnect) -> Config {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    pub fn no_reconnect(mut self) -> Config {
+        self.reconnect = None;
+        self
+    }
+
+    pub fn heartbeat(mut self, heartbeat: Heartbeat) -> Config {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    pub fn buffer(mut self, messages: usize) -> Config {
+        self.buffer = messages;
+        self
+    }
+
+    /// Starts connecting with the backend for the current target. Only a URL the
+    /// backend rejects outright is an error here; failures to connect are retried.
+    pub fn connect(self) -> Result<WebSocket, Error> {
+        self.connect_with(DefaultBackend::default())
+    }
+
+    pub fn connect_with<B: Backend>(self, backend: B) -> Result<WebSocket<B>, Error> {
+        let mut socket = WebSocket {
+            config: self,
+            backend,
+            state: State::Closed(None),
+            inbox: VecDeque::new(),
+            outbox: VecDeque::new(),
+            failures: 0,
+            heartbeat: None,
+            alive: false,
+        };
+        socket.state = socket.open()?;
+        Ok(socket)
+    }
+}
+
+/// A connection that reopens itself. Receiving from the stream and sending through the
+/// sink both keep it going, so it needs to be polled one way or the other for pings to
+/// be sent and dropped connections to be reopened.
+///
+/// The stream ends after the messages already received when the socket is closed on
+/// purpose. When it closes for good otherwise, the last item is [`Error::Closed`].
+pub struct WebSocket<B: Backend = DefaultBackend> {
+    config: Config,
+    backend: B,
+    state: State<B::Connection>,
+    /// Messages received but not yet taken from the stream.
+    inbox: VecDeque<Message>,
+    /// Messages waiting for the connection to open.
+    outbox: VecDeque<Message>,
+    /// Attempts to connect that failed since the connection was last open.
+    failures: u32,
+    /// When to send the next heartbeat ping.
+    heartbeat: Option<Sleep>,
+    /// Whether anything has arrived since the last ping.
+    alive: bool,
+}
+
+enum State<C> {
+    /// A connection that is opening, or open once `open` is set.
+    Live {
+        connection: C,
+        events: UnboundedReceiver<Event>,
+        open: bool,
+    },
+    /// Waiting to try connecting again.
+    Waiting(Sleep),
+    /// Closed for good, with the error the stream has yet to end with.
+    Closed(Option<Error>),
+}
+
+// Nothing is pinned structurally: the sleeps are boxed and the rest is only moved
+// through `&mut`.
+impl<B: Backend> Unpin for WebSocket<B> {}
+
+impl WebSocket {
+    /// Connects to `url` with the default [`Config`].
+    pub fn connect(url: impl Into<String>) -> Result<WebSocket, Error> {
+        Config::new(url).connect()
+    }
+}
+
+impl<B: Backend> WebSocket<B> {
+    pub fn url(&self) -> &str {
+        &self.config.url
+    }
+
+    /// Whether the connection is open at the moment, so sending goes straight out
+    /// rather than to the buffer.
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Live { open: true, .. })
+    }
+
+    /// Closes the connection for good, dropping any messages still waiting to be sent.
+    /// Closing through the sink sends them first.
+    pub fn close(&mut self) {
+        self.state = State::Closed(None);
+        self.outbox.clear();
+        self.heartbeat = None;
+    }
+
+    /// Encodes outgoing messages from `Out` and decodes incoming ones as `In` with `C`.
+    pub fn framed<C: Codec, In: DeserializeOwned, Out: Serialize>(self) -> Framed<C, In, Out, B> {
+        Framed {
+            socket: self,
+            types: PhantomData,
+        }
+    }
+
+    fn open(&self) -> Result<State<B::Connection>, Error> {
+        let (sender, events) = mpsc::unbounded();
+        let connection = self.backend.connect(&self.config.url, sender)?;
+        Ok(State::Live {
+            connection,
+            events,
+            open: false,
+        })
+    }
+
+    /// Handles whatever has happened since the last call, arranging for `cx` to be woken
+    /// when something else does.
+    fn drive(&mut self, cx: &mut Context) {
+        loop {
+            match &mut self.state {
+                State::Live { events, open, .. } => match events.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => self.handle(event),
+                    Poll::Ready(None) => self.disconnected("the backend stopped".to_owned()),
+                    Poll::Pending => {
+                        let ping_due = *open
+                            && self
+                                .heartbeat
+                                .as_mut()
+                                .is_some_and(|timer| timer.as_mut().poll(cx).is_ready());
+                        if !ping_due {
+                            return;
+                        }
+                        self.ping();
+                    }
+                },
+                State::Waiting(timer) => {
+                    if timer.as_mut().poll(cx).is_pending() {
+                        return;
+                    }
+                    self.state = self
+                        .open()
+                        .unwrap_or_else(|error| State::Closed(Some(error)));
+                }
+                State::Closed(_) => return,
+            }
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Open => {
+                let State::Live {
+                    connection, open, ..
+                } = &mut self.state
+                else {
+                    return;
+                };
+                *open = true;
+                for message in self.outbox.drain(..) {
+                    connection.send(&message);
+                }
+                self.failures = 0;
+                self.alive = true;
+                self.heartbeat = self
+                    .config
+                    .heartbeat
+                    .as_ref()
+                    .map(|heartbeat| self.backend.sleep(heartbeat.interval));
+                log::debug!("connected to {}", self.config.url);
+            }
+            Event::Message(message) => {
+                self.alive = true;
+                let pong = self
+                    .config
+                    .heartbeat
+                    .as_ref()
+                    .and_then(|heartbeat| heartbeat.pong.as_ref());
+                if pong != Some(&message) {
+                    self.inbox.push_back(message);
+                }
+            }
+            Event::Closed(reason) => self.disconnected(reason),
+        }
+    }
+
+    fn ping(&mut self) {
+        if !self.alive {
+            self.disconnected("no answer to the heartbeat".to_owned());
+            return;
+        }
+        let (Some(heartbeat), State::Live { connection, .. }) =
+            (&self.config.heartbeat, &self.state)
+        else {
+            return;
+        };
+        connection.send(&heartbeat.ping);
+        self.alive = false;
+        self.heartbeat = Some(self.backend.sleep(heartbeat.interval));
+    }
+
+    /// Drops the connection and waits to open another, unless that has been tried
+    /// enough.
+    fn disconnected(&mut self, reason: String) {
+        if !self.is_open() {
+            self.failures += 1;
+        }
+        self.heartbeat = None;
+        let url = &self.config.url;
+        let Some(reconnect) = self.config.reconnect.as_ref().filter(|reconnect| {
+            reconnect
+                .attempts
+                .is_none_or(|attempts| self.failures < attempts)
+        }) else {
+            log::warn!("connection to {url} closed: {reason}");
+            self.state = State::Closed(Some(Error::Closed(reason)));
+            return;
+        };
+        let delay = reconnect.delay(self.failures);
+        log::warn!("connection to {url} closed ({reason}), reconnecting in {delay:?}");
+        self.state = State::Waiting(self.backend.sleep(delay));
+    }
+
+    fn closed_error(&self) -> Error {
+        match &self.state {
+            State::Closed(Some(error)) => error.clone(),
+            _ => Error::Closed("the socket was closed".to_owned()),
+        }
+    }
+}
+
+impl<B: Backend> Stream for WebSocket<B> {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.drive(cx);
+        if let Some(message) = this.inbox.pop_front() {
+            return Poll::Ready(Some(Ok(message)));
+        }
+        match &mut this.state {
+            State::Closed(error) => Poll::Ready(error.take().map(Err)),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// Sending waits only while the buffer is full. Flushing waits until the buffer has
+/// been sent, which means until the connection is open.
+impl<B: Backend> Sink<Message> for WebSocket<B> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        this.drive(cx);
+        match this.state {
+            State::Closed(_) => Poll::Ready(Err(this.closed_error())),
+            State::Live { open: true, .. } => Poll::Ready(Ok(())),
+            _ if this.outbox.len() < this.config.buffer => Poll::Ready(Ok(())),
+            _ => Poll::Pending,
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Message) -> Result<(), Error> {
+        let this = self.get_mut();
+        match &this.state {
+            State::Closed(_) => return Err(this.closed_error()),
+            State::Live {
+                connection,
+                open: true,
+                ..
+            } => connection.send(&message),
+            _ => this.outbox.push_back(message),
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        this.drive(cx);
+        match this.state {
+            State::Closed(_) => Poll::Ready(Err(this.closed_error())),
+            _ if this.outbox.is_empty() => Poll::Ready(Ok(())),
+            _ => Poll::Pending,
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Turns values into messages and back.
+pub trait Codec {
+    fn encode<T: Serialize>(value: &T) -> Result<Message, Error>;
+
+    fn decode<T: DeserializeOwned>(message: &Message) -> Result<T, Error>;
+}
+
+/// JSON, sent as text messages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(value: &T) -> Result<Message, Error> {
+        serde_json::to_string(value)
+            .map(Message::Text)
+            .map_err(|error| Error::Encode(error.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(message: &Message) -> Result<T, Error> {
+        serde_json::from_slice(message.as_bytes()).map_err(|error| Error::Decode(error.to_string()))
+    }
+}
+
+/// MessagePack, sent as binary messages. Structs are encoded as maps, keeping the field
+/// names, which is what JavaScript MessagePack libraries expect.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn encode<T: Serialize>(value: &T) -> Result<Message, Error> {
+        rmp_serde::to_vec_named(value)
+            .map(Message::Binary)
+            .map_err(|error| Error::Encode(error.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(message: &Message) -> Result<T, Error> {
+        rmp_serde::from_slice(message.as_bytes()).map_err(|error| Error::Decode(error.to_string()))
+    }
+}
+
+/// A [`WebSocket`] that is a [`Stream`] of `In` and a [`Sink`] for `Out`, encoded with
+/// `C`. A message that fails to decode is an [`Error::Decode`] in the stream, which
+/// carries on after it.
+///
+/// ```
+/// use futures::executor::block_on;
+/// use futures::{SinkExt, StreamExt};
+/// use hello_wasm::websocket::{Config, EchoServer, Json, NativeBackend};
+/// use serde_json::{json, Value};
+///
+/// let server = EchoServer::start().unwrap();
+/// let socket = Config::new(server.url()).connect_with(NativeBackend).unwrap();
+/// let mut socket = socket.framed::<Json, Value, Value>();
+/// block_on(async {
+///     socket.send(json!({ "id": 1 })).await.unwrap();
+///     assert_eq!(socket.next().await, Some(Ok(json!({ "id": 1 }))));
+/// });
+/// ```
+pub struct Framed<C, In, Out, B: Backend = DefaultBackend> {
+    socket: WebSocket<B>,
+    types: PhantomData<fn(Out) -> (C, In)>,
+}
+
+impl<C, In, Out, B: Backend> Framed<C, In, Out, B> {
+    pub fn get_ref(&self) -> &WebSocket<B> {
+        &self.socket
+    }
+
+    pub fn get_mut(&mut self) -> &mut WebSocket<B> {
+        &mut self.socket
+    }
+
+    pub fn into_inner(self) -> WebSocket<B> {
+        self.socket
+    }
+}
+
+impl<C: Codec, In: DeserializeOwned, Out, B: Backend> Stream for Framed<C, In, Out, B> {
+    type Item = Result<In, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.get_mut().socket.poll_next_unpin(cx));
+        Poll::Ready(item.map(|message| message.and_then(|message| C::decode(&message))))
+    }
+}
+
+impl<C: Codec, In, Out: Serialize, B: Backend> Sink<Out> for Framed<C, In, Out, B> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, value: Out) -> Result<(), Error> {
+        let message = C::encode(&value)?;
+        Pin::new(&mut self.get_mut().socket).start_send(message)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().socket).poll_close(cx)
+    }
+}
+
+/// Something that happened to a connection, as reported by its [`Backend`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Open,
+    Message(Message),
+    /// The connection closed or could not be opened, and why. Always the last event.
+    Closed(String),
+}
+
+/// A future that completes after a while, for backoff and heartbeats.
+pub type Sleep = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Opens connections for a [`WebSocket`], which takes care of reopening them.
+pub trait Backend {
+    type Connection: Connection;
+
+    /// Starts opening a connection to `url` and returns without waiting for it. What
+    /// becomes of it is reported through `events`: [`Event::Open`] once it can be used,
+    /// the messages received, and finally [`Event::Closed`], which is also how a
+    /// failure to connect is reported.
+    fn connect(&self, url: &str, events: UnboundedSender<Event>)
+        -> Result<Self::Connection, Error>;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// A connection opened by a [`Backend`], closed when dropped.
+pub trait Connection {
+    /// Sends `message`. Only called once the connection is open; if sending fails, the
+    /// connection reports [`Event::Closed`].
+    fn send(&self, message: &Message);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub type DefaultBackend = WebBackend;
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultBackend = NativeBackend;
+
+/// Connects with the browser's `WebSocket`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebBackend;
+
+impl Backend for WebBackend {
+    type Connection = WebConnection;
+
+    fn connect(&self, url: &str, events: UnboundedSender<Event>) -> Result<WebConnection, Error> {
+        let socket =
+            web_sys::WebSocket::new(url).map_err(|error| Error::InvalidUrl(js_message(&error)))?;
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+        let onopen = Closure::<dyn FnMut()>::new({
+            let events = events.clone();
+            move || {
+                let _ = events.unbounded_send(Event::Open);
+            }
+        });
+        let onmessage = Closure::<dyn FnMut(_)>::new({
+            let events = events.clone();
+            move |event: web_sys::MessageEvent| {
+                let data = event.data();
+                let message = match data.as_string() {
+                    Some(text) => Message::Text(text),
+                    None => Message::Binary(js_sys::Uint8Array::new(&data).to_vec()),
+                };
+                let _ = events.unbounded_send(Event::Message(message));
+            }
+        });
+        // Failures are followed by a close event, which says more than the error event.
+        let onclose = Closure::<dyn FnMut(_)>::new(move |event: web_sys::CloseEvent| {
+            let reason = match event.reason() {
+                reason if reason.is_empty() => format!("code {}", event.code()),
+                reason => format!("code {}: {reason}", event.code()),
+            };
+            let _ = events.unbounded_send(Event::Closed(reason));
+        });
+        socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+        Ok(WebConnection {
+            socket,
+            _onopen: onopen,
+            _onmessage: onmessage,
+            _onclose: onclose,
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(http::Backend::sleep(&http::FetchBackend, duration))
+    }
+}
+
+pub struct WebConnection {
+    socket: web_sys::WebSocket,
+    _onopen: Closure<dyn FnMut()>,
+    _onmessage: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _onclose: Closure<dyn FnMut(web_sys::CloseEvent)>,
+}
+
+impl Connection for WebConnection {
+    fn send(&self, message: &Message) {
+        let _ = match message {
+            Message::Text(text) => self.socket.send_with_str(text),
+            Message::Binary(data) => self.socket.send_with_u8_array(data),
+        };
+    }
+}
+
+impl Drop for WebConnection {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+    use futures::task::noop_waker_ref;
+    use futures::SinkExt;
+
+    use super::*;
+
+    /// A [`NativeBackend`] that counts the connections it opens.
+    #[derive(Clone, Default)]
+    struct Counting {
+        connects: Rc<Cell<u32>>,
+    }
+
+    impl Backend for Counting {
+        type Connection = NativeConnection;
+
+        fn connect(
+            &self,
+            url: &str,
+            events: UnboundedSender<Event>,
+        ) -> Result<NativeConnection, Error> {
+            self.connects.set(self.connects.get() + 1);
+            NativeBackend.connect(url, events)
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            NativeBackend.sleep(duration)
+        }
+    }
+
+    fn quick() -> Reconnect {
+        Reconnect {
+            initial: Duration::from_millis(5),
+            max: Duration::from_millis(20),
+            attempts: None,
+        }
+    }
+
+    /// Keeps the socket going without taking anything from its stream, until `done`.
+    fn drive_until<B: Backend>(socket: &mut WebSocket<B>, done: impl Fn(&WebSocket<B>) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        while !done(socket) {
+            assert!(Instant::now() < deadline, "timed out");
+            socket.drive(&mut cx);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn pause<B: Backend>(socket: &mut WebSocket<B>, duration: Duration) {
+        let until = Instant::now() + duration;
+        drive_until(socket, |_| Instant::now() >= until);
+    }
+
+    /// A URL nothing is listening on.
+    fn refused_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("ws://{}", listener.local_addr().unwrap())
+    }
+
+    /// Accepts one connection and never sends anything on it.
+    fn silent_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            while socket.read().is_ok() {}
+        });
+        url
+    }
+
+    #[test]
+    fn doubles_the_delay_up_to_the_maximum() {
+        let reconnect = Reconnect {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            attempts: None,
+        };
+        let delays: Vec<_> = (0..6)
+            .map(|failures| reconnect.delay(failures).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(reconnect.delay(u32::MAX), reconnect.max);
+    }
+
+    #[test]
+    fn reconnects_and_sends_what_was_buffered_in_order() {
+        let server = EchoServer::start().unwrap();
+        let backend = Counting::default();
+        let mut socket = Config::new(server.url())
+            .reconnect(quick())
+            .connect_with(backend.clone())
+            .unwrap();
+        block_on(async {
+            socket.send(Message::from("before")).await.unwrap();
+            assert_eq!(socket.next().await, Some(Ok(Message::from("before"))));
+        });
+
+        server.disconnect_all();
+        drive_until(&mut socket, |socket| !socket.is_open());
+        block_on(async {
+            socket.feed(Message::from("first")).await.unwrap();
+            socket.feed(Message::from(vec![2])).await.unwrap();
+        });
+        assert_eq!(socket.outbox.len(), 2);
+
+        drive_until(&mut socket, WebSocket::is_open);
+        assert!(socket.outbox.is_empty());
+        assert_eq!(backend.connects.get(), 2);
+        block_on(async {
+            assert_eq!(socket.next().await, Some(Ok(Message::from("first"))));
+            assert_eq!(socket.next().await, Some(Ok(Message::from(vec![2]))));
+        });
+    }
+
+    #[test]
+    fn waits_while_the_buffer_is_full() {
+        let mut socket = Config::new(refused_url())
+            .reconnect(Reconnect {
+                initial: Duration::from_secs(60),
+                ..quick()
+            })
+            .buffer(1)
+            .connect_with(NativeBackend)
+            .unwrap();
+        block_on(socket.feed(Message::from("held"))).unwrap();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(Pin::new(&mut socket).poll_ready(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn gives_up_after_the_attempts() {
+        let backend = Counting::default();
+        let mut socket = Config::new(refused_url())
+            .reconnect(Reconnect {
+                attempts: Some(3),
+                ..quick()
+            })
+            .connect_with(backend.clone())
+            .unwrap();
+        block_on(async {
+            assert!(matches!(socket.next().await, Some(Err(Error::Closed(_)))));
+            assert_eq!(socket.next().await, None);
+            assert!(matches!(
+                socket.send(Message::from("late")).await,
+                Err(Error::Closed(_))
+            ));
+        });
+        assert_eq!(backend.connects.get(), 3);
+
+        let mut socket = Config::new(refused_url())
+            .no_reconnect()
+            .connect_with(NativeBackend)
+            .unwrap();
+        assert!(matches!(
+            block_on(socket.next()),
+            Some(Err(Error::Closed(_)))
+        ));
+    }
+
+    #[test]
+    fn filters_out_heartbeat_answers() {
+        let server = EchoServer::start().unwrap();
+        // The echo server answers the ping with itself.
+        let heartbeat = Heartbeat {
+            // Well over the native connection's read timeout, which the answers can wait on.
+            interval: Duration::from_millis(100),
+            ping: Message::from("beat"),
+            pong: Some(Message::from("beat")),
+        };
+        let backend = Counting::default();
+        let mut socket = Config::new(server.url())
+            .heartbeat(heartbeat)
+            .connect_with(backend.clone())
+            .unwrap();
+        drive_until(&mut socket, WebSocket::is_open);
+        pause(&mut socket, Duration::from_millis(350));
+        assert!(socket.inbox.is_empty());
+        block_on(async {
+            socket.send(Message::from("data")).await.unwrap();
+            assert_eq!(socket.next().await, Some(Ok(Message::from("data"))));
+        });
+        // Each ping was answered, so the connection was never given up on.
+        assert_eq!(backend.connects.get(), 1);
+    }
+
+    #[test]
+    fn drops_connections_that_stop_answering() {
+        let mut socket = Config::new(silent_server())
+            .heartbeat(Heartbeat::new(Duration::from_millis(10)))
+            .no_reconnect()
+            .connect_with(NativeBackend)
+            .unwrap();
+        assert_eq!(
+            block_on(socket.next()),
+            Some(Err(Error::Closed("no answer to the heartbeat".to_owned())))
+        );
+    }
+
+    #[test]
+    fn frames_message_pack() {
+        let server = EchoServer::start().unwrap();
+        let socket = Config::new(server.url())
+            .connect_with(NativeBackend)
+            .unwrap();
+        let mut socket =
+            socket.framed::<MessagePack, BTreeMap<String, u32>, BTreeMap<String, u32>>();
+        let value = BTreeMap::from([("id".to_owned(), 7), ("count".to_owned(), 300)]);
+        let encoded = MessagePack::encode(&value).unwrap();
+        assert!(matches!(encoded, Message::Binary(_)));
+        block_on(async {
+            socket.send(value.clone()).await.unwrap();
+            assert_eq!(socket.next().await, Some(Ok(value.clone())));
+
+            // A message that doesn't decode is reported, and the stream carries on.
+            socket
+                .get_mut()
+                .send(Message::from("not MessagePack"))
+                .await
+                .unwrap();
+            assert!(matches!(socket.next().await, Some(Err(Error::Decode(_)))));
+            socket.send(value.clone()).await.unwrap();
+            assert_eq!(socket.next().await, Some(Ok(value)));
+        });
+    }
+}
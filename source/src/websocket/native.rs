@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedSender;
+use futures::channel::oneshot;
+use tungstenite::stream::MaybeTlsStream;
+
+use super::{Backend, Connection, Error, Event, Message, Sleep};
+
+/// How long a connection's thread waits for a message before looking for ones to send.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Connects with a blocking client, on a thread per connection. Only `ws://` URLs are
+/// supported. Like [`http::NativeBackend`](crate::http::NativeBackend), it is meant
+/// for tests and tools, and its sleeps take a thread each too.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeBackend;
+
+impl Backend for NativeBackend {
+    type Connection = NativeConnection;
+
+    fn connect(
+        &self,
+        url: &str,
+        events: UnboundedSender<Event>,
+    ) -> Result<NativeConnection, Error> {
+        if !url.starts_with("ws://") {
+            return Err(Error::InvalidUrl(format!("{url} is not a ws:// URL")));
+        }
+        let (commands, received) = mpsc::channel();
+        let url = url.to_owned();
+        thread::spawn(move || {
+            let reason = run(&url, &events, &received);
+            let _ = events.unbounded_send(Event::Closed(reason));
+        });
+        Ok(NativeConnection { commands })
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let (done, finished) = oneshot::channel();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            let _ = done.send(());
+        });
+        Box::pin(async move {
+            let _ = finished.await;
+        })
+    }
+}
+
+/// Hands messages to the connection's thread, which closes the connection once this is
+/// dropped.
+pub struct NativeConnection {
+    commands: Sender<Message>,
+}
+
+impl Connection for NativeConnection {
+    fn send(&self, message: &Message) {
+        let _ = self.commands.send(message.clone());
+    }
+}
+
+/// Runs a connection until it closes, returning why.
+fn run(url: &str, events: &UnboundedSender<Event>, commands: &Receiver<Message>) -> String {
+    let mut socket = match tungstenite::connect(url) {
+        Ok((socket, _)) => socket,
+        Err(error) => return error.to_string(),
+    };
+    if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        if let Err(error) = stream.set_read_timeout(Some(POLL_INTERVAL)) {
+            return error.to_string();
+        }
+    }
+    let _ = events.unbounded_send(Event::Open);
+
+    let mut reason = "the server closed the connection".to_owned();
+    loop {
+        loop {
+            let message = match commands.try_recv() {
+                Ok(Message::Text(text)) => tungstenite::Message::Text(text),
+                Ok(Message::Binary(data)) => tungstenite::Message::Binary(data),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return "closed by the client".to_owned();
+                }
+            };
+            if let Err(error) = socket.send(message) {
+                return error.to_string();
+            }
+        }
+
+        let message = match socket.read() {
+            Ok(tungstenite::Message::Text(text)) => Message::Text(text),
+            Ok(tungstenite::Message::Binary(data)) => Message::Binary(data),
+            Ok(tungstenite::Message::Close(Some(frame))) => {
+                reason = format!("code {}: {}", u16::from(frame.code), frame.reason);
+                continue;
+            }
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(error))
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return reason,
+            Err(error) => return error.to_string(),
+        };
+        if events.unbounded_send(Event::Message(message)).is_err() {
+            return "the socket was dropped".to_owned();
+        }
+    }
+}
+
+/// A WebSocket server that sends every text and binary message back to the client it
+/// came from, for tests and for trying the client out: `cargo xtask echo-server` runs
+/// one for the browser. It stops when dropped.
+pub struct EchoServer {
+    address: SocketAddr,
+    connections: Arc<Mutex<HashMap<u64, TcpStream>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl EchoServer {
+    /// Starts a server on a free port of the loopback interface.
+    pub fn start() -> io::Result<EchoServer> {
+        EchoServer::bind("127.0.0.1:0")
+    }
+
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<EchoServer> {
+        let listener = TcpListener::bind(address)?;
+        let server = EchoServer {
+            address: listener.local_addr()?,
+            connections: Arc::default(),
+            stopped: Arc::default(),
+        };
+        let connections = server.connections.clone();
+        let stopped = server.stopped.clone();
+        thread::spawn(move || {
+            for (id, stream) in (0..).zip(listener.incoming()) {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                if let Ok(clone) = stream.try_clone() {
+                    connections.lock().unwrap().insert(id, clone);
+                }
+                let connections = connections.clone();
+                thread::spawn(move || {
+                    echo(stream);
+                    connections.lock().unwrap().remove(&id);
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Cuts every open connection without a closing handshake, as a network failure
+    /// would. Clients can connect again afterwards.
+    pub fn disconnect_all(&self) {
+        for (_, stream) in self.connections.lock().unwrap().drain() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for EchoServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.disconnect_all();
+        // Wakes the accepting thread so it notices.
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+fn echo(stream: TcpStream) {
+    let Ok(mut socket) = tungstenite::accept(stream) else {
+        return;
+    };
+    while let Ok(message) = socket.read() {
+        if (message.is_text() || message.is_binary()) && socket.send(message).is_err() {
+            break;
+        }
+    }
+}
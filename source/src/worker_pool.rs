@@ -0,0 +1,1038 @@
+//! Runs work off the main thread. A [`Pool`] keeps a number of workers, each running
+//! its own instance of this module, and hands them [`Task`]s in the order they were
+//! started, one at a time per worker. Each task's result is a future, a [`TaskHandle`],
+//! and cancelling it or dropping it takes the task back from the pool.
+//!
+//! Inputs and outputs travel between threads as MessagePack. Larger binary data can go
+//! along as [`Buffer`]s, which in the browser are `ArrayBuffer`s transferred to the
+//! other thread rather than copied.
+//!
+//! A worker can only run the tasks [registered](register) on it, and registering has to
+//! happen on every thread: in the browser, that means somewhere that runs whenever the
+//! module loads, such as its `#[wasm_bindgen(start)]` function. Workers come from a
+//! [`Backend`]: [`WebBackend`] starts Web Workers with the `worker.js` that
+//! `cargo xtask site` generates, and `NativeBackend` threads, which share one registry,
+//! so the scheduling can be tried natively:
+//!
+//! ```
+//! use futures::executor::block_on;
+//! use hello_wasm::worker_pool::{self, Buffer, NativeBackend, Pool, Task};
+//!
+//! struct Sum;
+//!
+//! impl Task for Sum {
+//!     const NAME: &'static str = "sum";
+//!     type Input = Vec<u64>;
+//!     type Output = u64;
+//!
+//!     fn run(input: Vec<u64>, _: &mut Vec<Buffer>) -> Result<u64, String> {
+//!         Ok(input.iter().sum())
+//!     }
+//! }
+//!
+//! worker_pool::register::<Sum>();
+//! let pool = Pool::with_backend(NativeBackend, 2).unwrap();
+//! let sums: Vec<_> = (1..=3).map(|n| pool.run::<Sum>(&vec![n; 4])).collect();
+//! let sums: Result<Vec<u64>, _> = block_on(futures::future::join_all(sums)).into_iter().collect();
+//! assert_eq!(sums, Ok(vec![4, 8, 12]));
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::dom::{js_message, set_property, Listener};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{NativeBackend, NativeWorker};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Workers could not be started, such as where Web Workers aren't supported.
+    Unavailable(String),
+    /// No task with this name is registered on the thread that was to run it.
+    NotRegistered(String),
+    /// The input or output could not be serialised.
+    Encode(String),
+    /// The input or output was not what the task expects.
+    Decode(String),
+    /// The task returned an error or panicked.
+    Failed(String),
+    /// The worker running the task stopped. It is replaced by a new one.
+    Crashed(String),
+    /// The task was cancelled before it finished.
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unavailable(message) => write!(f, "workers are unavailable: {message}"),
+            Error::NotRegistered(name) => write!(f, "no task is registered as {name:?}"),
+            Error::Encode(message) => write!(f, "failed to encode the task data: {message}"),
+            Error::Decode(message) => write!(f, "failed to decode the task data: {message}"),
+            Error::Failed(message) => write!(f, "the task failed: {message}"),
+            Error::Crashed(reason) => write!(f, "the worker stopped: {reason}"),
+            Error::Cancelled => write!(f, "the task was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Work that can be sent to a worker.
+pub trait Task {
+    /// Identifies the task between threads, so no two registered tasks may share it.
+    const NAME: &'static str;
+    type Input: Serialize + DeserializeOwned;
+    type Output: Serialize + DeserializeOwned;
+
+    /// Runs on a worker. `buffers` holds those sent along with the input, and whatever
+    /// it holds afterwards is sent back with the output.
+    fn run(input: Self::Input, buffers: &mut Vec<Buffer>) -> Result<Self::Output, String>;
+}
+
+/// A task's encoded output, and the buffers sent back with it.
+pub type Output = (Vec<u8>, Vec<Buffer>);
+
+/// Runs a task from its encoded input.
+type Runner = fn(&[u8], Vec<Buffer>) -> Result<Output, Error>;
+
+static TASKS: Mutex<BTreeMap<&'static str, Runner>> = Mutex::new(BTreeMap::new());
+
+/// Lets workers on this thread, or natively in this process, run `T`.
+pub fn register<T: Task>() {
+    TASKS.lock().unwrap().insert(T::NAME, run_task::<T>);
+}
+
+fn run_task<T: Task>(input: &[u8], mut buffers: Vec<Buffer>) -> Result<Output, Error> {
+    let input = rmp_serde::from_slice(input).map_err(|error| Error::Decode(error.to_string()))?;
+    let output = T::run(input, &mut buffers).map_err(Error::Failed)?;
+    let output = encode(&output)?;
+    Ok((output, buffers))
+}
+
+/// Runs the registered task `name`, on the thread that calls it.
+fn execute(name: &str, input: &[u8], buffers: Vec<Buffer>) -> Result<Output, Error> {
+    let runner = TASKS.lock().unwrap().get(name).copied();
+    let runner = runner.ok_or_else(|| Error::NotRegistered(name.to_owned()))?;
+    runner(input, buffers)
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    rmp_serde::to_vec_named(value).map_err(|error| Error::Encode(error.to_string()))
+}
+
+/// Binary data sent along with a task or its output. In the browser it is an
+/// `ArrayBuffer`, which moves to the thread it is sent to without being copied, and
+/// natively it is a `Vec<u8>`.
+#[derive(Debug)]
+pub struct Buffer {
+    #[cfg(target_arch = "wasm32")]
+    array: js_sys::ArrayBuffer,
+    #[cfg(not(target_arch = "wasm32"))]
+    bytes: Vec<u8>,
+}
+
+impl Buffer {
+    pub fn len(&self) -> usize {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.array.byte_length() as usize
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.bytes.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the contents into this thread's memory.
+    pub fn to_vec(&self) -> Vec<u8> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            js_sys::Uint8Array::new(&self.array).to_vec()
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.bytes.clone()
+        }
+    }
+
+    /// The `ArrayBuffer`. Natively this panics, since there is no JS to make one with.
+    pub fn into_array_buffer(self) -> js_sys::ArrayBuffer {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.array
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            js_sys::Uint8Array::from(self.bytes.as_slice()).buffer()
+        }
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(bytes: Vec<u8>) -> Buffer {
+        #[cfg(target_arch = "wasm32")]
+        {
+            Buffer {
+                array: js_sys::Uint8Array::from(bytes.as_slice()).buffer(),
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Buffer { bytes }
+        }
+    }
+}
+
+/// Takes the `ArrayBuffer` over. Natively this panics, like any use of a JS value.
+impl From<js_sys::ArrayBuffer> for Buffer {
+    fn from(array: js_sys::ArrayBuffer) -> Buffer {
+        #[cfg(target_arch = "wasm32")]
+        {
+            Buffer { array }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Buffer {
+                bytes: js_sys::Uint8Array::new(&array).to_vec(),
+            }
+        }
+    }
+}
+
+/// A task on its way to a worker.
+#[derive(Debug)]
+pub struct Job {
+    pub id: u64,
+    pub task: &'static str,
+    pub input: Vec<u8>,
+    pub buffers: Vec<Buffer>,
+}
+
+/// What a worker reports, identified by the ID it was started with.
+#[derive(Debug)]
+pub enum Event {
+    /// The worker can take jobs.
+    Ready(u64),
+    /// The worker finished the job with ID `job`, and can take another.
+    Done {
+        worker: u64,
+        job: u64,
+        result: Result<Output, Error>,
+    },
+    /// The worker stopped and can't be used again.
+    Crashed { worker: u64, reason: String },
+}
+
+/// Starts workers for a [`Pool`].
+pub trait Backend {
+    type Worker: Worker;
+
+    /// Starts a worker and returns without waiting for it to load. It reports through
+    /// `events`, identified by `id`: [`Event::Ready`] once it can take jobs, then an
+    /// [`Event::Done`] for each job, or [`Event::Crashed`] if it stops.
+    fn spawn(&self, id: u64, events: UnboundedSender<Event>) -> Result<Self::Worker, Error>;
+}
+
+/// A worker started by a [`Backend`], stopped when dropped. Once stopped, whatever it
+/// still reports is ignored.
+pub trait Worker {
+    /// Runs `job`. Only called once the worker is ready and has finished its last job.
+    fn run(&self, job: Job);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub type DefaultBackend = WebBackend;
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultBackend = NativeBackend;
+
+/// Workers and the tasks waiting for them.
+pub struct Pool<B: Backend = DefaultBackend> {
+    shared: Rc<RefCell<Shared<B>>>,
+}
+
+impl Pool {
+    /// Starts `size` workers with the [`DefaultBackend`].
+    pub fn new(size: usize) -> Result<Pool, Error> {
+        Pool::with_backend(DefaultBackend::default(), size)
+    }
+}
+
+impl<B: Backend> Pool<B> {
+    pub fn with_backend(backend: B, size: usize) -> Result<Pool<B>, Error> {
+        if size == 0 {
+            return Err(Error::Unavailable(
+                "a pool needs at least one worker".to_owned(),
+            ));
+        }
+        let (sender, events) = mpsc::unbounded();
+        let mut shared = Shared {
+            backend,
+            sender,
+            events,
+            workers: Vec::with_capacity(size),
+            queue: VecDeque::new(),
+            tasks: HashMap::new(),
+            next_job: 0,
+            next_worker: 0,
+        };
+        for _ in 0..size {
+            let slot = shared.spawn();
+            if let Err(error) = &slot.worker {
+                return Err(error.clone());
+            }
+            shared.workers.push(slot);
+        }
+        Ok(Pool {
+            shared: Rc::new(RefCell::new(shared)),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.shared.borrow().workers.len()
+    }
+
+    /// How many tasks are waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.shared.borrow().queue.len()
+    }
+
+    /// Starts `T` with `input` on the next worker to be free.
+    pub fn run<T: Task>(&self, input: &T::Input) -> TaskHandle<T::Output, B> {
+        self.start::<T, _>(input, Vec::new(), |output, _| decode(&output))
+    }
+
+    /// Starts `T` with `input` and `buffers`, giving back the buffers the task left for
+    /// the output along with it.
+    pub fn run_with<T: Task>(
+        &self,
+        input: &T::Input,
+        buffers: Vec<Buffer>,
+    ) -> TaskHandle<(T::Output, Vec<Buffer>), B> {
+        self.start::<T, _>(input, buffers, |output, buffers| {
+            Ok((decode(&output)?, buffers))
+        })
+    }
+
+    fn start<T: Task, R>(
+        &self,
+        input: &T::Input,
+        buffers: Vec<Buffer>,
+        decode: fn(Vec<u8>, Vec<Buffer>) -> Result<R, Error>,
+    ) -> TaskHandle<R, B> {
+        let mut shared = self.shared.borrow_mut();
+        let id = shared.next_job;
+        shared.next_job += 1;
+        let mut pending = Pending::default();
+        match encode(input) {
+            Ok(input) => shared.queue.push_back(Job {
+                id,
+                task: T::NAME,
+                input,
+                buffers,
+            }),
+            Err(error) => pending.result = Some(Err(error)),
+        }
+        shared.tasks.insert(id, pending);
+        shared.dispatch();
+        TaskHandle {
+            id,
+            shared: self.shared.clone(),
+            decode,
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(output: &[u8]) -> Result<T, Error> {
+    rmp_serde::from_slice(output).map_err(|error| Error::Decode(error.to_string()))
+}
+
+/// The result of a task started on a [`Pool`]. Dropping it cancels the task.
+pub struct TaskHandle<R, B: Backend = DefaultBackend> {
+    id: u64,
+    shared: Rc<RefCell<Shared<B>>>,
+    decode: fn(Vec<u8>, Vec<Buffer>) -> Result<R, Error>,
+}
+
+impl<R, B: Backend> TaskHandle<R, B> {
+    /// Takes the task back from the pool, so the handle gives [`Error::Cancelled`]. A
+    /// task that is already running is stopped by replacing its worker. Natively, the
+    /// thread is left to finish it and its result is thrown away.
+    pub fn cancel(&self) {
+        self.shared.borrow_mut().cancel(self.id);
+    }
+}
+
+impl<R, B: Backend> Future for TaskHandle<R, B> {
+    type Output = Result<R, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut shared = self.shared.borrow_mut();
+        shared.drive(cx);
+        let pending = (shared.tasks.get_mut(&self.id)).expect("polled after completion");
+        let Some(result) = pending.result.take() else {
+            pending.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        };
+        shared.tasks.remove(&self.id);
+        shared.wake_waiting();
+        drop(shared);
+        Poll::Ready(result.and_then(|(output, buffers)| (self.decode)(output, buffers)))
+    }
+}
+
+impl<R, B: Backend> Drop for TaskHandle<R, B> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        if shared.tasks.contains_key(&self.id) {
+            shared.cancel(self.id);
+            shared.tasks.remove(&self.id);
+            shared.wake_waiting();
+        }
+    }
+}
+
+struct Shared<B: Backend> {
+    backend: B,
+    sender: UnboundedSender<Event>,
+    events: UnboundedReceiver<Event>,
+    workers: Vec<Slot<B::Worker>>,
+    queue: VecDeque<Job>,
+    tasks: HashMap<u64, Pending>,
+    next_job: u64,
+    next_worker: u64,
+}
+
+struct Slot<W> {
+    id: u64,
+    /// The error if the worker could not be started, which leaves the pool a worker
+    /// short.
+    worker: Result<W, Error>,
+    state: WorkerState,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum WorkerState {
+    Starting,
+    Idle,
+    Busy(u64),
+}
+
+#[derive(Default)]
+struct Pending {
+    result: Option<Result<Output, Error>>,
+    waker: Option<Waker>,
+}
+
+impl<B: Backend> Shared<B> {
+    fn spawn(&mut self) -> Slot<B::Worker> {
+        let id = self.next_worker;
+        self.next_worker += 1;
+        Slot {
+            id,
+            worker: self.backend.spawn(id, self.sender.clone()),
+            state: WorkerState::Starting,
+        }
+    }
+
+    /// Handles what the workers reported, which the handles being polled take turns to
+    /// do, and hands out the queued jobs.
+    fn drive(&mut self, cx: &mut Context) {
+        while let Poll::Ready(Some(event)) = self.events.poll_next_unpin(cx) {
+            self.handle(event);
+        }
+        self.dispatch();
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Ready(worker) => {
+                if let Some(slot) = self.slot(worker) {
+                    if slot.state == WorkerState::Starting {
+                        slot.state = WorkerState::Idle;
+                    }
+                }
+            }
+            Event::Done {
+                worker,
+                job,
+                result,
+            } => {
+                if let Some(slot) = self.slot(worker) {
+                    if slot.state == WorkerState::Busy(job) {
+                        slot.state = WorkerState::Idle;
+                        self.finish(job, result);
+                    }
+                }
+            }
+            Event::Crashed { worker, reason } => {
+                let Some(index) = self.workers.iter().position(|slot| slot.id == worker) else {
+                    return;
+                };
+                match self.workers[index].state {
+                    // A worker that never loaded, such as when the script is missing,
+                    // would fail the same way again. Its slot stays empty, so once no
+                    // worker is left the queued tasks fail instead of waiting.
+                    WorkerState::Starting => {
+                        log::warn!("worker {worker} failed to start: {reason}");
+                        self.workers[index].worker = Err(Error::Unavailable(reason));
+                    }
+                    WorkerState::Busy(job) => {
+                        self.finish(job, Err(Error::Crashed(reason)));
+                        self.workers[index] = self.spawn();
+                    }
+                    WorkerState::Idle => self.workers[index] = self.spawn(),
+                }
+            }
+        }
+    }
+
+    fn slot(&mut self, worker: u64) -> Option<&mut Slot<B::Worker>> {
+        self.workers.iter_mut().find(|slot| slot.id == worker)
+    }
+
+    fn dispatch(&mut self) {
+        while !self.queue.is_empty() {
+            let idle = self.workers.iter_mut().find_map(|slot| match &slot.worker {
+                Ok(worker) if slot.state == WorkerState::Idle => Some((worker, &mut slot.state)),
+                _ => None,
+            });
+            let Some((worker, state)) = idle else {
+                break;
+            };
+            let job = self.queue.pop_front().unwrap();
+            *state = WorkerState::Busy(job.id);
+            worker.run(job);
+        }
+
+        // Replacements that fail to start can leave the queue with nowhere to go.
+        if self.workers.iter().all(|slot| slot.worker.is_err()) {
+            if let Some(Err(error)) = self.workers.first().map(|slot| &slot.worker) {
+                let error = error.clone();
+                for job in std::mem::take(&mut self.queue) {
+                    self.finish(job.id, Err(error.clone()));
+                }
+            }
+        }
+    }
+
+    fn cancel(&mut self, job: u64) {
+        if let Some(index) = self.queue.iter().position(|queued| queued.id == job) {
+            self.queue.remove(index);
+        } else if let Some(index) =
+            (self.workers.iter()).position(|slot| slot.state == WorkerState::Busy(job))
+        {
+            // Dropping the worker stops it.
+            self.workers[index] = self.spawn();
+        }
+        self.finish(job, Err(Error::Cancelled));
+    }
+
+    fn finish(&mut self, job: u64, result: Result<Output, Error>) {
+        if let Some(pending) = self.tasks.get_mut(&job) {
+            if pending.result.is_none() {
+                pending.result = Some(result);
+                if let Some(waker) = pending.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Wakes the handles still waiting, so one of them takes over watching for events
+    /// from the one that finished.
+    fn wake_waiting(&mut self) {
+        for pending in self.tasks.values_mut() {
+            if let Some(waker) = pending.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Where workers for the pool and for [`threads`](crate::threads) are started from, if
+/// [`set_worker_script`] has been called.
+static WORKER_SCRIPT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the URL of the worker script, which a release renames to include a hash of its
+/// contents. The bootstrap that `cargo xtask site` generates calls it before anything
+/// starts workers; until then they use `/worker.js`.
+#[wasm_bindgen]
+pub fn set_worker_script(url: &str) {
+    *WORKER_SCRIPT.lock().unwrap() = Some(url.to_owned());
+}
+
+pub(crate) fn worker_script() -> String {
+    let script = WORKER_SCRIPT.lock().unwrap();
+    script.clone().unwrap_or_else(|| "/worker.js".to_owned())
+}
+
+/// Starts Web Workers that load this module, with a script like the `worker.js` that
+/// `cargo xtask site` generates, which by default is the one [`set_worker_script`] set.
+/// The script receives the compiled module as its first message, along with the memory
+/// in the threaded build, initialises the bindings with them and calls the function
+/// named by `start`, [`worker_pool_serve`].
+#[derive(Clone, Debug)]
+pub struct WebBackend {
+    script: String,
+}
+
+impl WebBackend {
+    pub fn new(script: impl Into<String>) -> WebBackend {
+        WebBackend {
+            script: script.into(),
+        }
+    }
+}
+
+impl Default for WebBackend {
+    fn default() -> WebBackend {
+        WebBackend::new(worker_script())
+    }
+}
+
+impl Backend for WebBackend {
+    type Worker = WebWorker;
+
+    fn spawn(&self, id: u64, events: UnboundedSender<Event>) -> Result<WebWorker, Error> {
+        let sender = events.clone();
+        let options = web_sys::WorkerOptions::new();
+        options.set_type(web_sys::WorkerType::Module);
+        let worker = web_sys::Worker::new_with_options(&self.script, &options)
+            .map_err(|error| Error::Unavailable(js_message(&error)))?;
+
+        let onmessage = Closure::<dyn FnMut(_)>::new({
+            let events = events.clone();
+            move |event: web_sys::MessageEvent| {
+                let _ = events.unbounded_send(reply_event(id, &event.data()));
+            }
+        });
+        // Also fired for exceptions the worker doesn't catch, such as traps.
+        let onerror = Closure::<dyn FnMut(_)>::new(move |event: web_sys::ErrorEvent| {
+            let _ = events.unbounded_send(Event::Crashed {
+                worker: id,
+                reason: event.message(),
+            });
+        });
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        worker.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+
+        // Compiling the module again in every worker would be wasted work. The threaded
+        // build's workers share its memory too, making them threads of the same instance.
+        let message = js_sys::Object::new();
+        set_property(&message, "module", &wasm_bindgen::module());
+        if crate::threads::is_threaded_build() {
+            set_property(&message, "memory", &wasm_bindgen::memory());
+            set_property(&message, "threads", &true.into());
+        }
+        set_property(&message, "start", &"worker_pool_serve".into());
+        worker
+            .post_message(&message)
+            .map_err(|error| Error::Unavailable(js_message(&error)))?;
+        Ok(WebWorker {
+            id,
+            worker,
+            events: sender,
+            _onmessage: onmessage,
+            _onerror: onerror,
+        })
+    }
+}
+
+pub struct WebWorker {
+    id: u64,
+    worker: web_sys::Worker,
+    events: UnboundedSender<Event>,
+    _onmessage: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _onerror: Closure<dyn FnMut(web_sys::ErrorEvent)>,
+}
+
+impl Worker for WebWorker {
+    fn run(&self, job: Job) {
+        let input = js_sys::Uint8Array::from(job.input.as_slice());
+        let buffers: js_sys::Array = (job.buffers.into_iter())
+            .map(Buffer::into_array_buffer)
+            .collect();
+        let message = js_sys::Object::new();
+        set_property(&message, "id", &(job.id as f64).into());
+        set_property(&message, "task", &job.task.into());
+        set_property(&message, "input", &input);
+        set_property(&message, "buffers", &buffers);
+        let transfer = js_sys::Array::of1(&input.buffer());
+        for buffer in buffers.iter() {
+            transfer.push(&buffer);
+        }
+        if let Err(error) = self.worker.post_message_with_transfer(&message, &transfer) {
+            // Reported like any other failure, so the job doesn't wait forever.
+            let _ = self.events.unbounded_send(Event::Crashed {
+                worker: self.id,
+                reason: js_message(&error),
+            });
+        }
+    }
+}
+
+impl Drop for WebWorker {
+    fn drop(&mut self) {
+        self.worker.set_onmessage(None);
+        self.worker.set_onerror(None);
+        self.worker.terminate();
+    }
+}
+
+/// The event for a message a worker posted: `"ready"`, a reply to a job, or the error
+/// `worker.js` posts when the module fails to load.
+fn reply_event(worker: u64, data: &JsValue) -> Event {
+    if data.as_string().as_deref() == Some("ready") {
+        return Event::Ready(worker);
+    }
+    let field = |name: &str| js_sys::Reflect::get(data, &name.into()).unwrap_or_default();
+    let Some(job) = field("id").as_f64() else {
+        return Event::Crashed {
+            worker,
+            reason: field("error")
+                .as_string()
+                .unwrap_or_else(|| "the worker sent an unexpected message".to_owned()),
+        };
+    };
+    let result = match field("error").as_string() {
+        None => Ok((
+            js_sys::Uint8Array::new(&field("output")).to_vec(),
+            buffers_from(&field("buffers")),
+        )),
+        Some(kind) => {
+            let message = field("message").as_string().unwrap_or_default();
+            Err(match kind.as_str() {
+                "not-registered" => Error::NotRegistered(message),
+                "encode" => Error::Encode(message),
+                "decode" => Error::Decode(message),
+                _ => Error::Failed(message),
+            })
+        }
+    };
+    Event::Done {
+        worker,
+        job: job as u64,
+        result,
+    }
+}
+
+fn buffers_from(array: &JsValue) -> Vec<Buffer> {
+    js_sys::Array::from(array)
+        .iter()
+        .map(|buffer| Buffer::from(buffer.unchecked_into::<js_sys::ArrayBuffer>()))
+        .collect()
+}
+
+thread_local! {
+    static SERVING: RefCell<Option<Listener>> = const { RefCell::new(None) };
+}
+
+/// Makes this worker run the jobs it is sent. Called by the worker script once the
+/// module has loaded.
+#[doc(hidden)]
+#[wasm_bindgen]
+pub fn worker_pool_serve() {
+    // Tasks can use Rayon too. Workers sharing the module's memory share its pool, which
+    // the page has to set up with `init_thread_pool` before starting any that do: made
+    // here, it would be this worker's thread alone, and the others would wait on it.
+    if !crate::threads::is_threaded_build() {
+        let _ = crate::threads::single_thread();
+    }
+    let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let listener = Listener::new(&scope, "message", {
+        let scope = scope.clone();
+        move |event| {
+            let data = event.unchecked_into::<web_sys::MessageEvent>().data();
+            let (reply, transfer) = answer(&data);
+            if let Err(error) = scope.post_message_with_transfer(&reply, &transfer) {
+                web_sys::console::error_2(&"failed to send the task's output:".into(), &error);
+            }
+        }
+    });
+    SERVING.with(|serving| *serving.borrow_mut() = Some(listener));
+    let _ = scope.post_message(&"ready".into());
+}
+
+/// Runs the job in `data` and gives the reply, along with what to transfer with it.
+fn answer(data: &JsValue) -> (js_sys::Object, js_sys::Array) {
+    let field = |name: &str| js_sys::Reflect::get(data, &name.into()).unwrap_or_default();
+    let reply = js_sys::Object::new();
+    let transfer = js_sys::Array::new();
+    set_property(&reply, "id", &field("id"));
+    let task = field("task").as_string().unwrap_or_default();
+    let input = js_sys::Uint8Array::new(&field("input")).to_vec();
+    match execute(&task, &input, buffers_from(&field("buffers"))) {
+        Ok((output, buffers)) => {
+            let output = js_sys::Uint8Array::from(output.as_slice());
+            transfer.push(&output.buffer());
+            let buffers: js_sys::Array = (buffers.into_iter())
+                .map(Buffer::into_array_buffer)
+                .collect();
+            for buffer in buffers.iter() {
+                transfer.push(&buffer);
+            }
+            set_property(&reply, "output", &output);
+            set_property(&reply, "buffers", &buffers);
+        }
+        Err(error) => {
+            let (kind, message) = match error {
+                Error::NotRegistered(name) => ("not-registered", name),
+                Error::Encode(message) => ("encode", message),
+                Error::Decode(message) => ("decode", message),
+                error => ("failed", error.to_string()),
+            };
+            set_property(&reply, "error", &kind.into());
+            set_property(&reply, "message", &message.into());
+        }
+    }
+    (reply, transfer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    use super::*;
+
+    /// Gives back its input, after waiting that many milliseconds.
+    struct Wait;
+
+    impl Task for Wait {
+        const NAME: &'static str = "tests::wait";
+        type Input = u64;
+        type Output = u64;
+
+        fn run(millis: u64, _: &mut Vec<Buffer>) -> Result<u64, String> {
+            std::thread::sleep(Duration::from_millis(millis));
+            Ok(millis)
+        }
+    }
+
+    /// Appends its input to the first buffer, and hands back a second one.
+    struct Append;
+
+    impl Task for Append {
+        const NAME: &'static str = "tests::append";
+        type Input = Vec<u8>;
+        type Output = usize;
+
+        fn run(input: Vec<u8>, buffers: &mut Vec<Buffer>) -> Result<usize, String> {
+            let mut bytes = buffers.first().ok_or("no buffer")?.to_vec();
+            bytes.extend(input);
+            *buffers = vec![Buffer::from(bytes), Buffer::from(vec![0])];
+            Ok(buffers.len())
+        }
+    }
+
+    struct Panic;
+
+    impl Task for Panic {
+        const NAME: &'static str = "tests::panic";
+        type Input = String;
+        type Output = ();
+
+        fn run(message: String, _: &mut Vec<Buffer>) -> Result<(), String> {
+            panic!("{message}");
+        }
+    }
+
+    struct Unregistered;
+
+    impl Task for Unregistered {
+        const NAME: &'static str = "tests::unregistered";
+        type Input = ();
+        type Output = ();
+
+        fn run(_: (), _: &mut Vec<Buffer>) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn pool(size: usize) -> Pool<NativeBackend> {
+        register::<Wait>();
+        register::<Append>();
+        register::<Panic>();
+        Pool::with_backend(NativeBackend, size).unwrap()
+    }
+
+    /// A pool of one worker that has loaded, so the next task starts straight away.
+    fn loaded_pool() -> Pool<NativeBackend> {
+        let pool = pool(1);
+        assert_eq!(block_on(pool.run::<Wait>(&0)), Ok(0));
+        pool
+    }
+
+    #[test]
+    fn runs_tasks_in_the_order_they_were_started() {
+        let pool = loaded_pool();
+        // The first waits longest, so only a queue kept in order finishes them in order.
+        let handles: Vec<_> = [30, 20, 10, 0]
+            .iter()
+            .map(|millis| pool.run::<Wait>(millis))
+            .collect();
+        assert_eq!(pool.queued(), 3);
+        let finished = Rc::new(RefCell::new(Vec::new()));
+        let waits = handles.into_iter().map(|handle| {
+            let finished = finished.clone();
+            async move {
+                let millis = handle.await.unwrap();
+                finished.borrow_mut().push(millis);
+            }
+        });
+        block_on(join_all(waits));
+        assert_eq!(*finished.borrow(), [30, 20, 10, 0]);
+        assert_eq!(pool.queued(), 0);
+    }
+
+    #[test]
+    fn cancels_queued_and_running_tasks() {
+        let pool = loaded_pool();
+        let running = pool.run::<Wait>(&200);
+        let queued = pool.run::<Wait>(&0);
+        let dropped = pool.run::<Wait>(&0);
+        queued.cancel();
+        drop(dropped);
+        assert_eq!(pool.queued(), 0);
+        assert_eq!(block_on(queued), Err(Error::Cancelled));
+
+        running.cancel();
+        assert_eq!(block_on(running), Err(Error::Cancelled));
+        // The worker was replaced, so the next task doesn't wait for the cancelled one.
+        assert_eq!(pool.size(), 1);
+        assert_eq!(block_on(pool.run::<Wait>(&1)), Ok(1));
+    }
+
+    #[test]
+    fn reports_failures() {
+        let pool = pool(1);
+        assert_eq!(
+            block_on(pool.run::<Panic>(&"boom".to_owned())),
+            Err(Error::Failed("panicked: boom".to_owned()))
+        );
+        // The panic didn't take the worker with it.
+        assert_eq!(block_on(pool.run::<Wait>(&0)), Ok(0));
+        assert_eq!(
+            block_on(pool.run::<Unregistered>(&())),
+            Err(Error::NotRegistered(Unregistered::NAME.to_owned()))
+        );
+        assert_eq!(
+            block_on(pool.run::<Append>(&vec![1])),
+            Err(Error::Failed("no buffer".to_owned()))
+        );
+        assert!(matches!(
+            Pool::with_backend(NativeBackend, 0),
+            Err(Error::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn sends_buffers_both_ways() {
+        let pool = pool(2);
+        let handle = pool.run_with::<Append>(&vec![3, 4], vec![Buffer::from(vec![1, 2])]);
+        let (count, buffers) = block_on(handle).unwrap();
+        assert_eq!(count, 2);
+        let buffers: Vec<_> = buffers.iter().map(Buffer::to_vec).collect();
+        assert_eq!(buffers, [vec![1, 2, 3, 4], vec![0]]);
+    }
+
+    /// Workers that crash on loading, or on the first job they are given if `loads`.
+    struct Crashing {
+        loads: bool,
+        spawned: Rc<Cell<u32>>,
+    }
+
+    struct CrashingWorker {
+        id: u64,
+        events: UnboundedSender<Event>,
+    }
+
+    impl Backend for Crashing {
+        type Worker = CrashingWorker;
+
+        fn spawn(&self, id: u64, events: UnboundedSender<Event>) -> Result<CrashingWorker, Error> {
+            self.spawned.set(self.spawned.get() + 1);
+            let event = if self.loads {
+                Event::Ready(id)
+            } else {
+                Event::Crashed {
+                    worker: id,
+                    reason: "the script failed to load".to_owned(),
+                }
+            };
+            events.unbounded_send(event).unwrap();
+            Ok(CrashingWorker { id, events })
+        }
+    }
+
+    impl Worker for CrashingWorker {
+        fn run(&self, _job: Job) {
+            let crashed = Event::Crashed {
+                worker: self.id,
+                reason: "out of memory".to_owned(),
+            };
+            self.events.unbounded_send(crashed).unwrap();
+        }
+    }
+
+    #[test]
+    fn fails_queued_tasks_when_no_worker_loads() {
+        let spawned = Rc::new(Cell::new(0));
+        let backend = Crashing {
+            loads: false,
+            spawned: spawned.clone(),
+        };
+        let pool = Pool::with_backend(backend, 2).unwrap();
+        let handles = [pool.run::<Wait>(&0), pool.run::<Wait>(&1)];
+        let failed = Err(Error::Unavailable("the script failed to load".to_owned()));
+        assert_eq!(
+            block_on(join_all(handles)),
+            [failed.clone(), failed.clone()]
+        );
+        // Later tasks fail straight away too, and nothing was started again.
+        assert_eq!(block_on(pool.run::<Wait>(&2)), failed);
+        assert_eq!(spawned.get(), 2);
+    }
+
+    #[test]
+    fn replaces_workers_that_crash_while_running() {
+        let spawned = Rc::new(Cell::new(0));
+        let backend = Crashing {
+            loads: true,
+            spawned: spawned.clone(),
+        };
+        let pool = Pool::with_backend(backend, 1).unwrap();
+        for _ in 0..3 {
+            assert_eq!(
+                block_on(pool.run::<Wait>(&0)),
+                Err(Error::Crashed("out of memory".to_owned()))
+            );
+        }
+        assert_eq!(spawned.get(), 4);
+    }
+}
@@ -0,0 +1,60 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use futures::channel::mpsc::UnboundedSender;
+
+use super::{execute, Backend, Error, Event, Job, Worker};
+
+/// Runs each worker on a thread of its own. Tasks registered on any thread can run on
+/// all of them, and a task that panics fails without stopping its thread. Like the
+/// other native backends, it is meant for tests and tools.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NativeBackend;
+
+impl Backend for NativeBackend {
+    type Worker = NativeWorker;
+
+    fn spawn(&self, id: u64, events: UnboundedSender<Event>) -> Result<NativeWorker, Error> {
+        let (jobs, incoming) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(format!("worker-pool-{id}"))
+            .spawn(move || {
+                let _ = events.unbounded_send(Event::Ready(id));
+                // Ends once the worker is dropped, after the job it is running.
+                while let Ok(job) = incoming.recv() {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        execute(job.task, &job.input, job.buffers)
+                    }))
+                    .unwrap_or_else(|payload| {
+                        let message = (payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "Box<dyn Any>".to_owned());
+                        Err(Error::Failed(format!("panicked: {message}")))
+                    });
+                    let done = Event::Done {
+                        worker: id,
+                        job: job.id,
+                        result,
+                    };
+                    if events.unbounded_send(done).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|error| Error::Unavailable(error.to_string()))?;
+        Ok(NativeWorker { jobs })
+    }
+}
+
+/// A worker started by [`NativeBackend`]. Dropping it lets its thread finish the job it
+/// is running, whose result is then ignored, and end.
+pub struct NativeWorker {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Worker for NativeWorker {
+    fn run(&self, job: Job) {
+        let _ = self.jobs.send(job);
+    }
+}
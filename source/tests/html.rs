@@ -0,0 +1,56 @@
+//! What `html!` expands to, checked against the builder calls it stands for.
+
+use hello_wasm::html;
+use hello_wasm::vdom::{h, VNode};
+
+#[test]
+fn elements_attributes_and_iterators() {
+    let items = ["one", "two"];
+    let node = html! {
+        <ul class="list" data-count={items.len()}>
+            { for items.iter().map(|item| html! { <li key={item}>{ *item }</li> }) }
+        </ul>
+    };
+    assert_eq!(
+        node,
+        h("ul")
+            .class("list")
+            .attr("data-count", "2")
+            .child(h("li").key("one").text("one"))
+            .child(h("li").key("two").text("two"))
+            .into()
+    );
+}
+
+#[test]
+fn void_elements_and_valueless_attributes() {
+    let node = html! {
+        <p>"a"<br>"b"<input type="checkbox" checked/></p>
+    };
+    assert_eq!(
+        node,
+        h("p")
+            .text("a")
+            .child(h("br"))
+            .text("b")
+            .child(h("input").attr("type", "checkbox").attr("checked", ""))
+            .into()
+    );
+}
+
+#[test]
+fn listeners_are_attached() {
+    let node = html! { <button onclick={|_| ()}>"Go"</button> };
+    let VNode::Element(button) = node else {
+        panic!("expected an element");
+    };
+    assert_eq!(button.listeners.keys().collect::<Vec<_>>(), ["click"]);
+}
+
+#[test]
+fn expressions_insert_nodes() {
+    let name = String::from("Ada");
+    let inner = h("em").text("hi");
+    let node = html! { <div>{ inner.clone() }" "{ name.clone() }</div> };
+    assert_eq!(node, h("div").child(inner).text(" ").text("Ada").into());
+}
@@ -0,0 +1,31 @@
+//! Rayon's global pool, which can only be set up once, so these run in a process of
+//! their own.
+
+use std::collections::HashSet;
+use std::sync::Barrier;
+use std::thread;
+
+use futures::executor::block_on;
+use hello_wasm::threads::{init_thread_pool, Mode};
+use rayon::prelude::*;
+
+#[test]
+fn init_thread_pool_starts_threads_natively() {
+    assert_eq!(block_on(init_thread_pool(3)).ok(), Some(Mode::Threads));
+    assert_eq!(rayon::current_num_threads(), 3);
+    // Not a thread of the pool itself.
+    assert_eq!(rayon::current_thread_index(), None);
+
+    // Every thread has to reach the barrier before any can leave it, so this only
+    // finishes if the work really is spread over three threads.
+    let barrier = Barrier::new(3);
+    let threads: HashSet<_> = rayon::broadcast(|_| {
+        barrier.wait();
+        thread::current().id()
+    })
+    .into_iter()
+    .collect();
+    assert_eq!(threads.len(), 3);
+    assert!(!threads.contains(&thread::current().id()));
+    assert_eq!((1..=100u32).into_par_iter().sum::<u32>(), 5050);
+}
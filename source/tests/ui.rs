@@ -0,0 +1,7 @@
+//! Markup `html!` rejects, with the errors it gives in `tests/ui/*.stderr`. Run with
+//! `TRYBUILD=overwrite` to update them after changing a message.
+
+#[test]
+fn html_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
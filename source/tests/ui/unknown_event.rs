@@ -0,0 +1,5 @@
+use hello_wasm::html;
+
+fn main() {
+    html! { <button onclik={|_| ()}/> };
+}
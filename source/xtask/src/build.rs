@@ -0,0 +1,222 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+use clap::{Args, ValueEnum};
+use wasm_bindgen_cli_support::Bindgen;
+
+use crate::{module_name, project_root, target_dir, workspace_root};
+
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// The toolchain for the threaded build.
+const NIGHTLY: &str = "nightly";
+
+/// Shared memory needs atomics, and bulk memory to initialise it once for every thread.
+const THREADS_RUSTFLAGS: &str = "-C target-feature=+atomics,+bulk-memory";
+
+/// The kind of JS module wasm-bindgen generates.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum BindgenTarget {
+    Web,
+    Bundler,
+    Nodejs,
+    NoModules,
+}
+
+impl BindgenTarget {
+    fn as_arg(self) -> &'static str {
+        match self {
+            BindgenTarget::Web => "web",
+            BindgenTarget::Bundler => "bundler",
+            BindgenTarget::Nodejs => "nodejs",
+            BindgenTarget::NoModules => "no-modules",
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct BuildArgs {
+    /// Build with the release profile.
+    #[arg(long)]
+    pub release: bool,
+
+    /// The JS module format to generate.
+    #[arg(long, value_enum, default_value = "web")]
+    pub target: BindgenTarget,
+
+    /// Where to write the `.wasm` and `.js` files. Defaults to the `site` directory.
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Also make the build with wasm atomics that threads need, named after the module
+    /// with `_threads` added. Needs the nightly toolchain with `rust-src`.
+    #[arg(long)]
+    pub threads: bool,
+}
+
+/// Files produced by a successful build.
+pub struct BuildOutput {
+    /// The module cargo produced, before wasm-bindgen.
+    pub cargo_wasm: PathBuf,
+    /// The module wasm-bindgen wrote next to the JS bindings.
+    pub bindgen_wasm: PathBuf,
+    /// The module of the build with wasm atomics, when there is one.
+    pub threads: Option<PathBuf>,
+}
+
+pub fn run(args: &BuildArgs) -> anyhow::Result<BuildOutput> {
+    let out_dir = args
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| project_root().join("site"));
+    let module = module_name()?;
+    let mut output = compile(
+        "hello-wasm",
+        &module,
+        args.release,
+        args.target,
+        false,
+        &out_dir,
+    )?;
+    if args.threads {
+        let threads = compile(
+            "hello-wasm",
+            &module,
+            args.release,
+            args.target,
+            true,
+            &out_dir,
+        )?;
+        output.threads = Some(threads.bindgen_wasm);
+    }
+    Ok(output)
+}
+
+/// Compiles the library of workspace package `package` to wasm, whose module is called
+/// `module`, and generates its bindings into `out_dir`. With `threads`, the build has
+/// wasm atomics and its files are named `{module}_threads`.
+pub fn compile(
+    package: &str,
+    module: &str,
+    release: bool,
+    target: BindgenTarget,
+    threads: bool,
+    out_dir: &Path,
+) -> anyhow::Result<BuildOutput> {
+    check_wasm_target()?;
+
+    let profile = if release { "release" } else { "debug" };
+    let (mut cargo, target_dir, out_name) = if threads {
+        check_threads_toolchain()?;
+        eprintln!("Compiling {module} with wasm atomics for {WASM_TARGET} ({profile})");
+        // The standard library has to be rebuilt with atomics too, which only nightly
+        // can do. Its own target directory keeps the usual build from being invalidated.
+        let target_dir = target_dir().join("threads");
+        let mut cargo = Command::new("rustup");
+        cargo
+            .args([
+                "run",
+                NIGHTLY,
+                "cargo",
+                "build",
+                "-Z",
+                "build-std=panic_abort,std",
+            ])
+            .env("RUSTFLAGS", THREADS_RUSTFLAGS)
+            .env("CARGO_TARGET_DIR", &target_dir);
+        (cargo, target_dir, format!("{module}_threads"))
+    } else {
+        eprintln!("Compiling {module} for {WASM_TARGET} ({profile})");
+        let mut cargo = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()));
+        cargo.arg("build");
+        (cargo, target_dir(), module.to_owned())
+    };
+    cargo.current_dir(workspace_root()).args([
+        "--package",
+        package,
+        "--lib",
+        "--target",
+        WASM_TARGET,
+    ]);
+    if release {
+        cargo.arg("--release");
+    }
+    let status = cargo.status().context("failed to run cargo")?;
+    if !status.success() {
+        bail!("cargo build failed with {status}");
+    }
+
+    let input = target_dir
+        .join(WASM_TARGET)
+        .join(profile)
+        .join(format!("{module}.wasm"));
+    if !input.exists() {
+        bail!("cargo finished but {} does not exist", input.display());
+    }
+
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+
+    eprintln!("Generating {} bindings", target.as_arg());
+    let mut bindgen = Bindgen::new();
+    bindgen
+        .input_path(&input)
+        .out_name(&out_name)
+        .typescript(true);
+    match target {
+        BindgenTarget::Web => bindgen.web(true)?,
+        BindgenTarget::Bundler => bindgen.bundler(true)?,
+        BindgenTarget::Nodejs => bindgen.nodejs(true)?,
+        BindgenTarget::NoModules => bindgen.no_modules(true)?,
+    };
+    bindgen
+        .generate(out_dir)
+        .context("wasm-bindgen failed to generate the bindings")?;
+
+    eprintln!("Wrote bindings to {}", out_dir.display());
+    Ok(BuildOutput {
+        bindgen_wasm: out_dir.join(format!("{out_name}_bg.wasm")),
+        cargo_wasm: input,
+        threads: None,
+    })
+}
+
+/// Fails early when rustup is available and reports that the wasm target is missing.
+/// Without rustup there is no reliable way to ask, so cargo gets to report it instead.
+fn check_wasm_target() -> anyhow::Result<()> {
+    let Ok(output) = Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .output()
+    else {
+        return Ok(());
+    };
+    let installed = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() && !installed.lines().any(|line| line.trim() == WASM_TARGET) {
+        bail!(
+            "the {WASM_TARGET} target is not installed\n\
+             install it with `rustup target add {WASM_TARGET}`"
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the nightly toolchain the threaded build uses has the standard library's
+/// source to rebuild.
+fn check_threads_toolchain() -> anyhow::Result<()> {
+    let output = Command::new("rustup")
+        .args(["component", "list", "--installed", "--toolchain", NIGHTLY])
+        .output()
+        .map_err(|_| {
+            anyhow::anyhow!("the threaded build needs rustup to run the nightly toolchain")
+        })?;
+    let installed = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || !installed.lines().any(|line| line.trim() == "rust-src") {
+        bail!(
+            "the threaded build needs the {NIGHTLY} toolchain with rust-src\n\
+             install it with `rustup toolchain install {NIGHTLY} --component rust-src --target {WASM_TARGET}`"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use std::thread;
+
+use anyhow::Context;
+use clap::Args;
+use hello_wasm::websocket::EchoServer;
+
+#[derive(Args, Debug)]
+pub struct EchoServerArgs {
+    /// The port to listen on.
+    #[arg(long, default_value_t = 9001)]
+    pub port: u16,
+}
+
+pub fn run(args: &EchoServerArgs) -> anyhow::Result<()> {
+    let server = EchoServer::bind(("127.0.0.1", args.port))
+        .with_context(|| format!("failed to bind port {}", args.port))?;
+    eprintln!("Echoing WebSocket messages at {}", server.url());
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,286 @@
+//! Renames build output to include a hash of its contents, so browsers can cache it
+//! forever and still pick up new builds, and rewrites the references to it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{ensure, Context};
+use sha2::{Digest, Sha256};
+
+/// Maps each original file name to its fingerprinted name. Written next to the files.
+pub const MANIFEST: &str = "asset-manifest.json";
+
+/// Hex digits of the SHA-256 kept in file names.
+const HASH_LEN: usize = 16;
+
+/// Files whose contents may refer to other files by name.
+const TEXT_EXTENSIONS: &[&str] = &["html", "js", "css", "json"];
+
+/// `name` with the hash of `contents` before its extension, so `app.js` becomes
+/// `app.0123456789abcdef.js`.
+pub fn fingerprinted_name(name: &str, contents: &[u8]) -> String {
+    let hash: String = Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .take(HASH_LEN / 2)
+        .collect();
+    match name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}.{hash}.{extension}"),
+        None => format!("{name}.{hash}"),
+    }
+}
+
+/// Renames `names` within `files`, which maps `/`-separated paths to contents. Names
+/// are processed in order, so a file that refers to another must come after it: its
+/// hash then covers the rewritten reference. Returns the manifest.
+pub fn fingerprint(
+    files: &mut BTreeMap<String, Vec<u8>>,
+    names: &[String],
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut manifest = BTreeMap::new();
+    for name in names {
+        let contents = files
+            .remove(name)
+            .with_context(|| format!("{name} does not exist"))?;
+        let renamed = fingerprinted_name(name, &contents);
+        ensure!(!files.contains_key(&renamed), "{renamed} already exists");
+        for (path, contents) in files.iter_mut() {
+            if is_text(path) {
+                if let Some(rewritten) = replace_references(contents, name, &renamed) {
+                    *contents = rewritten;
+                }
+            }
+        }
+        files.insert(renamed.clone(), contents);
+        manifest.insert(name.clone(), renamed);
+    }
+    Ok(manifest)
+}
+
+fn is_text(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, extension)| TEXT_EXTENSIONS.contains(&extension))
+}
+
+/// Replaces references to `name` in `contents`, or returns `None` if there are none. A
+/// reference is `name` not directly preceded or followed by more of a file name, so
+/// `index.js` matches in `"/index.js"` but not in `"my_index.js"`.
+fn replace_references(contents: &[u8], name: &str, renamed: &str) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut found = false;
+    while let Some(index) = rest.find(name) {
+        let before = rest[..index].chars().next_back();
+        let after = rest[index + name.len()..].chars().next();
+        let matches = !before.is_some_and(is_name_char)
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+        result.push_str(&rest[..index]);
+        result.push_str(if matches { renamed } else { name });
+        found |= matches;
+        rest = &rest[index + name.len()..];
+    }
+    result.push_str(rest);
+    found.then(|| result.into_bytes())
+}
+
+/// Fingerprints `names` in `dir`: writes the renamed files, rewrites references in the
+/// text files under `dir`, and removes the originals along with earlier fingerprinted
+/// copies. Returns the manifest, which is also written to [`MANIFEST`].
+pub fn run(dir: &Path, names: &[String]) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    for path in list_files(dir)? {
+        let key = path
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if names.contains(&key) || (is_text(&key) && !is_fingerprinted(&key) && key != MANIFEST) {
+            let contents =
+                fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            files.insert(key, contents);
+        }
+    }
+    let originals = files.clone();
+
+    let manifest = fingerprint(&mut files, names)?;
+
+    for name in names {
+        remove_stale(dir, name, &manifest[name])?;
+        let path = dir.join(name);
+        fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    for (key, contents) in &files {
+        if originals.get(key) != Some(contents) {
+            let path = dir.join(key);
+            fs::write(&path, contents)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+    }
+    let path = dir.join(MANIFEST);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(manifest)
+}
+
+/// Whether `name` looks like the output of [`fingerprinted_name`].
+fn is_fingerprinted(name: &str) -> bool {
+    let mut parts = name.rsplit('.');
+    let (_, hash) = (parts.next(), parts.next());
+    hash.is_some_and(|hash| hash.len() == HASH_LEN && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        && parts.next().is_some()
+}
+
+/// Removes copies of `name` fingerprinted by earlier builds, keeping `current`.
+fn remove_stale(dir: &Path, name: &str, current: &str) -> anyhow::Result<()> {
+    let (parent, file) = match name.rsplit_once('/') {
+        Some((parent, file)) => (dir.join(parent), file),
+        None => (dir.to_path_buf(), name),
+    };
+    let current = current.rsplit('/').next().unwrap_or(current);
+    let (stem, extension) = file.rsplit_once('.').unwrap_or((file, ""));
+    for entry in fs::read_dir(&parent)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let earlier = is_fingerprinted(&file_name)
+            && file_name.starts_with(&format!("{stem}."))
+            && file_name.ends_with(&format!(".{extension}"))
+            && file_name.len() == file.len() + HASH_LEN + 1;
+        if earlier && file_name != current {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn list_files(dir: &Path) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)
+            .with_context(|| format!("failed to read {}", current.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(entries: &[(&str, &str)]) -> BTreeMap<String, Vec<u8>> {
+        (entries.iter())
+            .map(|(name, contents)| (name.to_string(), contents.as_bytes().to_vec()))
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn names_depend_only_on_contents() {
+        // The first eight bytes of the SHA-256 of the empty string.
+        assert_eq!(fingerprinted_name("app.js", b""), "app.e3b0c44298fc1c14.js");
+        assert_eq!(
+            fingerprinted_name("dir/LICENSE", b""),
+            "dir/LICENSE.e3b0c44298fc1c14"
+        );
+        assert_eq!(
+            fingerprinted_name("a.css", b"body {}"),
+            fingerprinted_name("b.css", b"body {}").replacen('b', "a", 1)
+        );
+        assert_ne!(
+            fingerprinted_name("a.css", b"a"),
+            fingerprinted_name("a.css", b"b")
+        );
+        assert!(is_fingerprinted(&fingerprinted_name("a.css", b"a")));
+        assert!(!is_fingerprinted("a.css"));
+    }
+
+    #[test]
+    fn hashes_cover_rewritten_references() {
+        let mut files = files(&[
+            ("style.css", "body {}"),
+            ("index.js", "import \"./style.css\";"),
+            ("index.html", "<script src=\"/index.js\">"),
+        ]);
+        let manifest = fingerprint(&mut files, &names(&["style.css", "index.js"])).unwrap();
+
+        let style = fingerprinted_name("style.css", b"body {}");
+        let index_js = format!("import \"./{style}\";");
+        let index = fingerprinted_name("index.js", index_js.as_bytes());
+        assert_eq!(manifest["style.css"], style);
+        assert_eq!(manifest["index.js"], index);
+        assert_eq!(files[&index], index_js.as_bytes());
+        assert_eq!(
+            files["index.html"],
+            format!("<script src=\"/{index}\">").as_bytes()
+        );
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn fingerprints_the_same_way_every_time() {
+        let input = files(&[("a.js", "b.js"), ("b.js", "1")]);
+        let run = || {
+            let mut files = input.clone();
+            let manifest = fingerprint(&mut files, &names(&["b.js", "a.js"])).unwrap();
+            (files, manifest)
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn replaces_whole_names_only() {
+        let replace = |text: &str| {
+            replace_references(text.as_bytes(), "index.js", "index.0.js")
+                .map(|bytes| String::from_utf8(bytes).unwrap())
+        };
+        assert_eq!(replace("\"/index.js\"").as_deref(), Some("\"/index.0.js\""));
+        assert_eq!(replace("(index.js)").as_deref(), Some("(index.0.js)"));
+        assert_eq!(replace("index.js.map").as_deref(), Some("index.0.js.map"));
+        assert_eq!(replace("my_index.js index.jsx old.index.js"), None);
+    }
+
+    #[test]
+    fn rejects_missing_files() {
+        let mut files = files(&[("a.js", "")]);
+        assert!(fingerprint(&mut files, &names(&["b.js"])).is_err());
+    }
+
+    #[test]
+    fn replaces_earlier_builds_on_disk() {
+        let dir = std::env::temp_dir().join(format!("xtask-fingerprint-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let stale = fingerprinted_name("app.js", b"old");
+        fs::write(dir.join(&stale), "old").unwrap();
+        fs::write(dir.join("app.js"), "new").unwrap();
+        fs::write(dir.join("index.html"), "<script src=\"/app.js\">").unwrap();
+
+        let manifest = run(&dir, &names(&["app.js"])).unwrap();
+        let current = fingerprinted_name("app.js", b"new");
+        assert_eq!(manifest["app.js"], current);
+        let mut listed: Vec<_> = (fs::read_dir(&dir).unwrap())
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        listed.sort();
+        assert_eq!(listed, [current.as_str(), MANIFEST, "index.html"]);
+        assert_eq!(
+            fs::read_to_string(dir.join("index.html")).unwrap(),
+            format!("<script src=\"/{current}\">")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+//! Project automation, run with `cargo xtask <command>` from the `source` directory.
+
+mod build;
+mod echo_server;
+mod fingerprint;
+mod release;
+mod serve;
+mod service_worker;
+mod site;
+mod size_report;
+mod wasm;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+
+#[derive(Parser)]
+#[command(name = "xtask", about = "Build tasks for hello-wasm")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile the crate to wasm and generate the JS bindings.
+    Build(build::BuildArgs),
+    /// Run a WebSocket server that sends every message back, to try the client against.
+    EchoServer(echo_server::EchoServerArgs),
+    /// Make a size-optimised release build and check it against the size budget.
+    Release(release::ReleaseArgs),
+    /// Serve the site, rebuilding and reloading open pages when the source changes.
+    Serve(serve::ServeArgs),
+    /// Generate the HTML pages, JS bootstrap and assets described by `site.toml`.
+    Site(site::SiteArgs),
+    /// Attribute the bytes of the generated wasm to crates and functions.
+    SizeReport(size_report::SizeReportArgs),
+}
+
+fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Build(args) => build::run(&args).map(|_| ()),
+        Command::EchoServer(args) => echo_server::run(&args),
+        Command::Release(args) => release::run(&args),
+        Command::Serve(args) => serve::run(&args),
+        Command::Site(args) => site::run(&args),
+        Command::SizeReport(args) => size_report::run(&args),
+    }
+}
+
+/// The `source` directory, which holds the workspace manifest.
+pub fn workspace_root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap()
+}
+
+/// The repository root, which holds `source` and `site`.
+pub fn project_root() -> &'static Path {
+    workspace_root().parent().unwrap()
+}
+
+/// Where cargo places build output, honouring `CARGO_TARGET_DIR`.
+pub fn target_dir() -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| workspace_root().join("target"))
+}
+
+/// The name wasm-bindgen gives the crate's JS module and wasm file, which is the package
+/// name with dashes replaced by underscores.
+pub fn module_name() -> anyhow::Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Manifest {
+        package: Package,
+    }
+    #[derive(serde::Deserialize)]
+    struct Package {
+        name: String,
+    }
+
+    let path = workspace_root().join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&manifest).with_context(|| format!("failed to parse {}", path.display()))?;
+    Ok(manifest.package.name.replace('-', "_"))
+}
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+use clap::Args;
+
+use crate::build::{self, BindgenTarget, BuildArgs};
+use crate::{fingerprint, module_name, project_root, service_worker, site, target_dir, wasm};
+
+const DEFAULT_BUDGET: u64 = 256 * 1024;
+
+#[derive(Args, Debug)]
+pub struct ReleaseArgs {
+    /// The JS module format to generate.
+    #[arg(long, value_enum, default_value = "web")]
+    pub target: BindgenTarget,
+
+    /// Where to write the release. Defaults to `site` in the target directory, so the
+    /// committed `site` directory is left alone.
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Keep the plain file names instead of adding content hashes.
+    #[arg(long)]
+    pub no_fingerprint: bool,
+
+    /// Fail when the final `.wasm` is larger than this many bytes.
+    #[arg(long, env = "HELLO_WASM_SIZE_BUDGET", default_value_t = DEFAULT_BUDGET)]
+    pub budget: u64,
+}
+
+pub fn run(args: &ReleaseArgs) -> anyhow::Result<()> {
+    let config_path = project_root().join("site.toml");
+    let site_config = site::read_config(&config_path)?;
+    // The generated site and fingerprinting assume the bootstrap can import an ES module.
+    let site = matches!(args.target, BindgenTarget::Web);
+    let out_dir = args
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| target_dir().join("site"));
+    // Fingerprinting renames and deletes files, which must not happen to the sources.
+    if site && !args.no_fingerprint && is_source_site(&out_dir) {
+        bail!(
+            "refusing to fingerprint the release into {}, which holds the committed site; \
+             pick another --out-dir or pass --no-fingerprint",
+            out_dir.display()
+        );
+    }
+    let output = build::run(&BuildArgs {
+        release: true,
+        target: args.target,
+        out_dir: Some(out_dir),
+        threads: site && site_config.threads,
+    })?;
+
+    let mut sizes = vec![
+        ("cargo build", file_size(&output.cargo_wasm)?),
+        ("wasm-bindgen", file_size(&output.bindgen_wasm)?),
+    ];
+
+    // wasm-opt reads the enabled features from the `target_features` custom section,
+    // so it has to run before the custom sections are stripped.
+    if run_wasm_opt(&output.bindgen_wasm)? {
+        sizes.push(("wasm-opt -Oz", file_size(&output.bindgen_wasm)?));
+    }
+
+    let size = strip_custom_sections(&output.bindgen_wasm)?;
+    sizes.push(("strip custom sections", size));
+
+    print_size_table(&sizes);
+
+    if let Some(threads) = &output.threads {
+        run_wasm_opt(threads)?;
+        let size = strip_custom_sections(threads)?;
+        eprintln!("{} is {size} bytes", threads.display());
+    }
+
+    if site {
+        let out_dir = output.bindgen_wasm.parent().unwrap();
+        let site_files = site::write(&config_path, out_dir)?;
+
+        let module = module_name()?;
+        let mut files = Vec::new();
+        if site_config.service_worker.is_some() {
+            let worker = service_worker::build(out_dir)?;
+            run_wasm_opt(&worker.bindgen_wasm)?;
+            strip_custom_sections(&worker.bindgen_wasm)?;
+            files.extend(service_worker::module_files());
+        }
+        files.extend([format!("{module}_bg.wasm"), format!("{module}.js")]);
+        if output.threads.is_some() {
+            files.extend([
+                format!("{module}_threads_bg.wasm"),
+                format!("{module}_threads.js"),
+            ]);
+        }
+        files.extend(
+            site_files
+                .iter()
+                .map(|path| path.to_string_lossy().replace('\\', "/")),
+        );
+
+        let mut manifest = BTreeMap::new();
+        if !args.no_fingerprint {
+            // Assets first, since the bootstrap and pages refer to them. The bootstrap
+            // passes the worker script's new name on to the module.
+            let (bootstrap, names): (Vec<String>, Vec<String>) = files
+                .iter()
+                .filter(|name| {
+                    Path::new(name)
+                        .extension()
+                        .is_some_and(|extension| extension != "html")
+                })
+                .cloned()
+                .partition(|name| name == "index.js");
+            let names = [names, bootstrap].concat();
+            manifest = fingerprint::run(out_dir, &names)?;
+            for (name, renamed) in &manifest {
+                eprintln!("{name} -> {renamed}");
+            }
+        }
+        if let Some(routing) = &site_config.service_worker {
+            service_worker::write(out_dir, routing, &files, &manifest)?;
+        }
+    }
+
+    if size > args.budget {
+        bail!(
+            "{} is {size} bytes, {} over the budget of {} bytes",
+            output.bindgen_wasm.display(),
+            size - args.budget,
+            args.budget
+        );
+    }
+    eprintln!(
+        "{} bytes of the {} byte budget used ({:.1}%)",
+        size,
+        args.budget,
+        size as f64 / args.budget as f64 * 100.0
+    );
+    Ok(())
+}
+
+/// Whether `dir` is the `site` directory in the repository.
+fn is_source_site(dir: &Path) -> bool {
+    let source = project_root().join("site");
+    match (fs::canonicalize(dir), fs::canonicalize(&source)) {
+        (Ok(dir), Ok(source)) => dir == source,
+        _ => dir == source,
+    }
+}
+
+/// Optimises `path` in place with Binaryen's `wasm-opt`. Returns `false` and warns if
+/// `wasm-opt` is not installed.
+fn run_wasm_opt(path: &Path) -> anyhow::Result<bool> {
+    if Command::new("wasm-opt").arg("--version").output().is_err() {
+        eprintln!("warning: wasm-opt was not found on PATH, skipping the optimisation pass");
+        return Ok(false);
+    }
+
+    eprintln!("Optimising with wasm-opt");
+    let status = Command::new("wasm-opt")
+        .arg("-Oz")
+        .arg(path)
+        .arg("-o")
+        .arg(path)
+        .status()
+        .context("failed to run wasm-opt")?;
+    if !status.success() {
+        bail!("wasm-opt failed with {status}");
+    }
+    Ok(true)
+}
+
+/// Strips the custom sections from the module at `path` in place and returns its new
+/// size.
+fn strip_custom_sections(path: &Path) -> anyhow::Result<u64> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let (stripped, removed) = wasm::strip_custom_sections(&bytes)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    fs::write(path, &stripped).with_context(|| format!("failed to write {}", path.display()))?;
+    if !removed.is_empty() {
+        eprintln!("Stripped custom sections: {}", removed.join(", "));
+    }
+    Ok(stripped.len() as u64)
+}
+
+fn file_size(path: &Path) -> anyhow::Result<u64> {
+    Ok(fs::metadata(path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .len())
+}
+
+fn print_size_table(sizes: &[(&str, u64)]) {
+    let width = sizes.iter().map(|(step, _)| step.len()).max().unwrap_or(0);
+    println!("{:<width$}  {:>10}  {:>10}", "step", "bytes", "change");
+    let mut previous = None;
+    for &(step, size) in sizes {
+        let change = match previous {
+            Some(previous) => format!("{:+}", size as i64 - previous as i64),
+            None => String::new(),
+        };
+        println!("{step:<width$}  {size:>10}  {change:>10}");
+        previous = Some(size);
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::io::{Cursor, Read};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::Args;
+use hello_wasm_service_worker::CACHE_PREFIX;
+use notify::{RecursiveMode, Watcher};
+use tiny_http::{Header, Request, Response, Server};
+use tungstenite::{Message, WebSocket};
+
+use crate::build::{self, BindgenTarget, BuildArgs};
+use crate::{project_root, service_worker, site, target_dir, workspace_root};
+
+/// How long to wait for more file changes before rebuilding.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// The port to serve the site on. The live reload socket uses the next port up.
+    #[arg(long, default_value_t = 8000)]
+    pub port: u16,
+
+    /// Build with the release profile.
+    #[arg(long)]
+    pub release: bool,
+}
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+pub fn run(args: &ServeArgs) -> anyhow::Result<()> {
+    let site = project_root().join("site");
+    let build_args = BuildArgs {
+        release: args.release,
+        target: BindgenTarget::Web,
+        out_dir: Some(site.clone()),
+        threads: site::read_config(&project_root().join("site.toml"))?.threads,
+    };
+    // A broken build should not stop the server; the next save triggers another try.
+    if let Err(error) = build::run(&build_args) {
+        eprintln!("error: {error:#}");
+    }
+
+    let reload_port = args.port + 1;
+    let clients = Clients::default();
+    let listener = TcpListener::bind(("127.0.0.1", reload_port))
+        .with_context(|| format!("failed to bind the live reload socket on port {reload_port}"))?;
+    thread::spawn({
+        let clients = clients.clone();
+        move || accept_reload_clients(listener, clients)
+    });
+
+    let (changes, changed) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if event.paths.iter().any(|path| is_source_path(path)) {
+                let _ = changes.send(());
+            }
+        }
+    })
+    .context("failed to start the file watcher")?;
+    watcher
+        .watch(workspace_root(), RecursiveMode::Recursive)
+        .context("failed to watch the source directory")?;
+    thread::spawn(move || {
+        while changed.recv().is_ok() {
+            while changed.recv_timeout(DEBOUNCE).is_ok() {}
+            eprintln!("Change detected, rebuilding");
+            match build::run(&build_args) {
+                Ok(_) => broadcast_reload(&clients),
+                Err(error) => eprintln!("error: {error:#}"),
+            }
+        }
+    });
+
+    let server = Server::http(("127.0.0.1", args.port))
+        .map_err(|error| anyhow::anyhow!("failed to bind port {}: {error}", args.port))?;
+    eprintln!(
+        "Serving {} at http://localhost:{}/",
+        site.display(),
+        args.port
+    );
+    for request in server.incoming_requests() {
+        if let Err(error) = respond(request, &site, reload_port) {
+            eprintln!("error: {error:#}");
+        }
+    }
+    Ok(())
+}
+
+/// Whether a change to `path` should trigger a rebuild. Build output is ignored so a
+/// rebuild does not trigger itself.
+fn is_source_path(path: &Path) -> bool {
+    !path.starts_with(target_dir()) && !path.starts_with(workspace_root().join("target"))
+}
+
+fn respond(request: Request, site: &Path, reload_port: u16) -> anyhow::Result<()> {
+    if request.url() == format!("/{}", service_worker::SCRIPT) {
+        let response = Response::from_string(dev_service_worker())
+            .with_header(Header::from_bytes("Content-Type", "text/javascript").unwrap())
+            .with_header(Header::from_bytes("Cache-Control", "no-store").unwrap());
+        return Ok(request.respond(isolated(response))?);
+    }
+    let Some(mut path) = resolve(site, request.url()) else {
+        return Ok(request.respond(isolated(not_found()))?);
+    };
+    if path.is_dir() {
+        path.push("index.html");
+    } else if !path.exists() && path.extension().is_none() {
+        // Client-side routes such as `/users/1` have no file of their own; serve the
+        // app and let the router pick the view.
+        path = site.join("index.html");
+    }
+    let Ok(mut body) = fs::read(&path) else {
+        return Ok(request.respond(isolated(not_found()))?);
+    };
+
+    let mime = mime_type(&path);
+    if mime.starts_with("text/html") {
+        body = inject_reload_client(&body, reload_port);
+    }
+    let response = Response::from_data(body)
+        .with_header(Header::from_bytes("Content-Type", mime).unwrap())
+        .with_header(Header::from_bytes("Cache-Control", "no-store").unwrap());
+    Ok(request.respond(isolated(response))?)
+}
+
+fn not_found() -> Response<Cursor<Vec<u8>>> {
+    Response::from_string("Not Found").with_status_code(404)
+}
+
+/// Adds the headers that make pages cross-origin isolated, which they have to be to
+/// share memory between threads. Cross-origin resources then only load if they opt in
+/// with CORS or `Cross-Origin-Resource-Policy`, so production has to send the same.
+fn isolated<R: Read>(response: Response<R>) -> Response<R> {
+    response
+        .with_header(Header::from_bytes("Cross-Origin-Opener-Policy", "same-origin").unwrap())
+        .with_header(Header::from_bytes("Cross-Origin-Embedder-Policy", "require-corp").unwrap())
+}
+
+/// Stands in for the release's service worker, so pages always come from the server
+/// while developing. It takes over from a release's worker left on the same origin and
+/// deletes its caches, and having no fetch listener, passes every request through.
+fn dev_service_worker() -> String {
+    format!(
+        r#"self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (event) => {{
+    const stale = (names) => names.filter((name) => name.startsWith("{CACHE_PREFIX}"));
+    event.waitUntil(
+        caches
+            .keys()
+            .then((names) => Promise.all(stale(names).map((name) => caches.delete(name))))
+            .then(() => self.clients.claim()),
+    );
+}});
+"#
+    )
+}
+
+/// Maps a request URL to a file under `site`, refusing anything that would escape it.
+fn resolve(site: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let mut resolved = site.to_path_buf();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("ts" | "txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Adds a script that reloads the page when the live reload socket says so.
+fn inject_reload_client(html: &[u8], reload_port: u16) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    let script = format!(
+        "<script>\
+         new WebSocket(\"ws://\" + location.hostname + \":{reload_port}\")\
+         .onmessage = (event) => {{ if (event.data === \"reload\") location.reload(); }};\
+         </script>"
+    );
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{script}{}", &html[..index], &html[index..]).into_bytes(),
+        None => format!("{html}{script}").into_bytes(),
+    }
+}
+
+fn accept_reload_clients(listener: TcpListener, clients: Clients) {
+    for stream in listener.incoming().flatten() {
+        if let Ok(socket) = tungstenite::accept(stream) {
+            clients.lock().unwrap().push(socket);
+        }
+    }
+}
+
+fn broadcast_reload(clients: &Clients) {
+    let mut clients = clients.lock().unwrap();
+    // Pages that have closed fail to receive and are dropped.
+    clients.retain_mut(|socket| socket.send(Message::Text("reload".into())).is_ok());
+    eprintln!("Reloaded {} page(s)", clients.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve_in_site(url: &str) -> Option<PathBuf> {
+        resolve(Path::new("/srv/site"), url)
+    }
+
+    #[test]
+    fn resolves_paths_under_the_site() {
+        let site = Path::new("/srv/site");
+        assert_eq!(resolve_in_site("/"), Some(site.to_path_buf()));
+        assert_eq!(resolve_in_site("/index.js"), Some(site.join("index.js")));
+        assert_eq!(
+            resolve_in_site("/assets/./app.css?v=2#top"),
+            Some(site.join("assets/app.css"))
+        );
+        // Leading slashes are all stripped, so absolute paths stay inside.
+        assert_eq!(
+            resolve_in_site("//etc/passwd"),
+            Some(site.join("etc/passwd"))
+        );
+    }
+
+    #[test]
+    fn refuses_to_leave_the_site() {
+        assert_eq!(resolve_in_site("/../secret"), None);
+        assert_eq!(resolve_in_site("/assets/../../secret"), None);
+        assert_eq!(resolve_in_site("/assets/..?x=1"), None);
+        // URLs are not decoded, so an encoded `..` is only an odd file name.
+        let encoded = resolve_in_site("/%2e%2e/secret").unwrap();
+        assert!(encoded.starts_with("/srv/site"));
+        assert_eq!(encoded, Path::new("/srv/site/%2e%2e/secret"));
+    }
+
+    #[test]
+    fn picks_mime_types_by_extension() {
+        let mime = |name: &str| mime_type(Path::new(name));
+        assert_eq!(mime("index.html"), "text/html; charset=utf-8");
+        assert_eq!(mime("a/b.mjs"), "text/javascript; charset=utf-8");
+        assert_eq!(mime("hello_wasm_bg.wasm"), "application/wasm");
+        assert_eq!(mime("hello_wasm.d.ts"), "text/plain; charset=utf-8");
+        assert_eq!(mime("icon.svg"), "image/svg+xml");
+        assert_eq!(mime("archive.tar.gz"), "application/octet-stream");
+        assert_eq!(mime("README"), "application/octet-stream");
+    }
+
+    #[test]
+    fn injects_the_reload_client_before_the_body_ends() {
+        let html = inject_reload_client(b"<body><p></p></body></html>", 35729);
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<body><p></p><script>new WebSocket("));
+        assert!(html.contains("\":35729\")"));
+        assert!(html.ends_with("</script></body></html>"));
+
+        // Without a closing body tag the script goes at the end.
+        let fragment = String::from_utf8(inject_reload_client(b"<p>hi</p>", 1)).unwrap();
+        assert!(fragment.starts_with("<p>hi</p><script>"));
+        assert!(fragment.ends_with("</script>"));
+    }
+}
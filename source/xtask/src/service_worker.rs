@@ -0,0 +1,141 @@
+//! Builds the service worker in `service-worker` and writes the `sw.js` that runs it,
+//! configured to precache the files of the release it belongs to.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use hello_wasm_service_worker::{Config, Routing};
+use sha2::{Digest, Sha256};
+
+use crate::build::{self, BindgenTarget, BuildOutput};
+
+/// The script pages register, which has to stay at the same URL from one release to the
+/// next, so it is never fingerprinted.
+pub const SCRIPT: &str = "sw.js";
+
+const PACKAGE: &str = "hello-wasm-service-worker";
+
+/// The worker's wasm module and bindings are named after this.
+const MODULE: &str = "hello_wasm_service_worker";
+
+/// Hex digits of the SHA-256 kept in the version.
+const VERSION_LEN: usize = 16;
+
+/// Compiles the worker into `out_dir`. Workers can't import ES modules everywhere yet,
+/// so its bindings are a classic script.
+pub fn build(out_dir: &Path) -> anyhow::Result<BuildOutput> {
+    build::compile(
+        PACKAGE,
+        MODULE,
+        true,
+        BindgenTarget::NoModules,
+        false,
+        out_dir,
+    )
+}
+
+/// The files [`build`] writes.
+pub fn module_files() -> [String; 2] {
+    [format!("{MODULE}_bg.wasm"), format!("{MODULE}.js")]
+}
+
+/// Writes [`SCRIPT`] into `out_dir`, precaching `files`, the site's files by their
+/// names before fingerprinting. `manifest` maps those names to the ones they were
+/// renamed to, and is empty when nothing was fingerprinted.
+pub fn write(
+    out_dir: &Path,
+    routing: &Routing,
+    files: &[String],
+    manifest: &BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    let renamed = |name: &String| manifest.get(name).unwrap_or(name).clone();
+
+    // Any change to what is cached makes a new version, with a cache of its own.
+    let mut hasher = Sha256::new();
+    let mut precache = Vec::new();
+    for name in files.iter().map(renamed) {
+        let path = out_dir.join(&name);
+        let contents =
+            fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let url = url_path(&name);
+        hasher.update(url.as_bytes());
+        hasher.update([0]);
+        hasher.update(Sha256::digest(&contents));
+        precache.push(url);
+    }
+    let version: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .take(VERSION_LEN / 2)
+        .collect();
+
+    let config = Config {
+        version,
+        precache,
+        routing: routing.clone(),
+    };
+    let [wasm, bindings] = module_files().map(|name| renamed(&name));
+    let path = out_dir.join(SCRIPT);
+    fs::write(&path, script(&config, &bindings, &wasm)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!(
+        "Wrote {} precaching {} files as version {}",
+        path.display(),
+        config.precache.len(),
+        config.version
+    );
+    Ok(())
+}
+
+/// The URL path a file in the site is served at, which for a page is the page's path.
+fn url_path(name: &str) -> String {
+    if name == "index.html" {
+        "/".to_owned()
+    } else if let Some(page) = name.strip_suffix("/index.html") {
+        format!("/{page}")
+    } else {
+        format!("/{name}")
+    }
+}
+
+/// Loads the worker's module and passes events on to it. Listeners have to be added
+/// before the script finishes, so they wait for the module rather than the other way
+/// round.
+fn script(config: &Config, bindings: &str, wasm: &str) -> anyhow::Result<String> {
+    let config = serde_json::to_string(&serde_json::to_string(config)?)?;
+    Ok(format!(
+        r#"// Generated by `cargo xtask release`. Edit site.toml instead.
+importScripts("/{bindings}");
+
+// The module is precached too, since the worker may have to start offline.
+const wasm = "/{wasm}";
+const ready = wasm_bindgen({{ module_or_path: caches.match(wasm).then((cached) => cached || fetch(wasm)) }})
+    .then(() => wasm_bindgen.configure({config}));
+
+self.addEventListener("install", (event) => {{
+    event.waitUntil(ready.then(() => wasm_bindgen.install()));
+}});
+
+self.addEventListener("activate", (event) => {{
+    event.waitUntil(ready.then(() => wasm_bindgen.activate()));
+}});
+
+self.addEventListener("fetch", (event) => {{
+    const url = new URL(event.request.url);
+    if (event.request.method === "GET" && url.origin === self.location.origin) {{
+        event.respondWith(ready.then(() => wasm_bindgen.handle_fetch(event)));
+    }}
+}});
+
+// Lets a page switch to a new version without waiting for the old one's pages to close.
+self.addEventListener("message", (event) => {{
+    if (event.data === "skip-waiting") {{
+        self.skipWaiting();
+    }}
+}});
+"#
+    ))
+}
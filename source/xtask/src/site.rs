@@ -0,0 +1,538 @@
+//! Generates the static part of the site from `site.toml`: an HTML page per entry, with
+//! the app rendered ahead of time, the JS bootstrap that starts the wasm module, the
+//! script the worker pool starts workers with, and whatever is in the assets directory.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use clap::Args;
+use hello_wasm_service_worker::Routing;
+use serde::Deserialize;
+
+use crate::service_worker::SCRIPT;
+use crate::{module_name, project_root};
+
+/// The script `hello_wasm::worker_pool` starts workers with. The bootstrap hands its
+/// URL to the module, so a release can fingerprint it like the other scripts.
+pub const WORKER_SCRIPT: &str = "worker.js";
+
+#[derive(Args, Debug)]
+pub struct SiteArgs {
+    /// The site configuration. Defaults to `site.toml` in the repository root.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Where to write the site. Defaults to the `site` directory.
+    #[arg(long)]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SiteConfig {
+    /// The site's name, available to title templates as `{name}`.
+    pub name: String,
+    /// The template for page titles, with `{page}` replaced by the page's title and
+    /// `{name}` by the site's name.
+    #[serde(default = "default_title")]
+    pub title: String,
+    #[serde(default = "default_lang")]
+    pub lang: String,
+    /// The `id` of the element the app is rendered into.
+    #[serde(default = "default_root")]
+    pub root: String,
+    /// The level passed to `init_logger`. Logging stays off if unset.
+    pub log_level: Option<LogLevel>,
+    /// A directory whose files are copied into the site as they are, relative to the
+    /// configuration file.
+    pub assets: Option<PathBuf>,
+    /// Stylesheets linked from every page.
+    #[serde(default)]
+    pub styles: Vec<String>,
+    #[serde(default = "default_pages", rename = "page")]
+    pub pages: Vec<PageConfig>,
+    /// Makes the site work offline with a service worker, which `cargo xtask release`
+    /// builds. Pages register it when this is set.
+    pub service_worker: Option<Routing>,
+    /// Starts the thread pool before the app, on threads where the page is cross-origin
+    /// isolated and the build with wasm atomics loads, which `cargo xtask release` and
+    /// `cargo xtask serve` then make too.
+    #[serde(default)]
+    pub threads: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PageConfig {
+    /// The URL path the page is served at. `/about` is written to `about/index.html`.
+    pub path: String,
+    pub title: String,
+    /// Whether to render the app into the page, so it shows before the wasm loads.
+    #[serde(default = "default_prerender")]
+    pub prerender: bool,
+}
+
+/// The levels of `hello_wasm::Level`, spelled the same in `site.toml`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn name(self) -> &'static str {
+        match self {
+            LogLevel::Trace => "Trace",
+            LogLevel::Debug => "Debug",
+            LogLevel::Info => "Info",
+            LogLevel::Warn => "Warn",
+            LogLevel::Error => "Error",
+        }
+    }
+}
+
+fn default_title() -> String {
+    "{page} | {name}".to_owned()
+}
+
+fn default_lang() -> String {
+    "en".to_owned()
+}
+
+fn default_root() -> String {
+    "app".to_owned()
+}
+
+fn default_pages() -> Vec<PageConfig> {
+    vec![PageConfig {
+        path: "/".to_owned(),
+        title: "Home".to_owned(),
+        prerender: true,
+    }]
+}
+
+fn default_prerender() -> bool {
+    true
+}
+
+pub fn run(args: &SiteArgs) -> anyhow::Result<()> {
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| project_root().join("site.toml"));
+    let out_dir = args
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| project_root().join("site"));
+    write(&config_path, &out_dir)?;
+    Ok(())
+}
+
+/// Generates the site described by the configuration at `config_path` into `out_dir`.
+/// Returns the paths of the files written, relative to `out_dir`.
+pub fn write(config_path: &Path, out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let config = read_config(config_path)?;
+    let assets = match &config.assets {
+        Some(dir) => read_assets(&config_path.parent().unwrap_or(Path::new(".")).join(dir))?,
+        None => BTreeMap::new(),
+    };
+
+    let files = generate(&config, &module_name()?, &assets, hello_wasm::render_app)?;
+    for (path, contents) in &files {
+        let path = out_dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    eprintln!("Wrote {} files to {}", files.len(), out_dir.display());
+    Ok(files.into_keys().collect())
+}
+
+pub fn read_config(path: &Path) -> anyhow::Result<SiteConfig> {
+    let config =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    toml::from_str(&config).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Every file under `dir`, keyed by its path relative to `dir`.
+fn read_assets(dir: &Path) -> anyhow::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut assets = BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = fs::read_dir(&current)
+            .with_context(|| format!("failed to read {}", current.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let contents = fs::read(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                assets.insert(path.strip_prefix(dir)?.to_path_buf(), contents);
+            }
+        }
+    }
+    Ok(assets)
+}
+
+/// Produces the site's files, keyed by their path in the output directory. The result
+/// depends only on the arguments, so it can be compared against a known-good copy.
+/// `render` renders the app at a URL path, for pages that are prerendered.
+pub fn generate(
+    config: &SiteConfig,
+    module: &str,
+    assets: &BTreeMap<PathBuf, Vec<u8>>,
+    render: impl Fn(&str) -> String,
+) -> anyhow::Result<BTreeMap<PathBuf, Vec<u8>>> {
+    ensure!(!config.pages.is_empty(), "the site has no pages");
+    ensure!(
+        !config.root.is_empty()
+            && config
+                .root
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "root {:?} must be a plain element id",
+        config.root
+    );
+    let mut files = BTreeMap::new();
+    files.insert(
+        PathBuf::from("index.js"),
+        bootstrap(config, module).into_bytes(),
+    );
+    files.insert(PathBuf::from(WORKER_SCRIPT), worker(module).into_bytes());
+    for page in &config.pages {
+        let path = page_file(&page.path)?;
+        let html = page_html(config, page, &render);
+        ensure!(
+            files.insert(path.clone(), html.into_bytes()).is_none(),
+            "more than one page is written to {}",
+            path.display()
+        );
+    }
+    for (path, contents) in assets {
+        ensure!(
+            !files.contains_key(path),
+            "asset {} would overwrite a generated file",
+            path.display()
+        );
+        files.insert(path.clone(), contents.clone());
+    }
+    Ok(files)
+}
+
+/// The file a page at URL path `path` is written to.
+fn page_file(path: &str) -> anyhow::Result<PathBuf> {
+    let Some(relative) = path.strip_prefix('/') else {
+        bail!("page path {path:?} does not start with `/`");
+    };
+    let mut file = PathBuf::new();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => file.push(part),
+            _ => bail!("page path {path:?} must not contain `.` or `..`"),
+        }
+    }
+    file.push("index.html");
+    Ok(file)
+}
+
+fn page_html(config: &SiteConfig, page: &PageConfig, render: impl Fn(&str) -> String) -> String {
+    let title = fill_title(&config.title, &page.title, &config.name);
+    let styles: String = config
+        .styles
+        .iter()
+        .map(|href| format!("    <link rel=\"stylesheet\" href=\"{}\">\n", escape(href)))
+        .collect();
+    let app = if page.prerender {
+        render(&page.path)
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<!DOCTYPE html>
+<!-- Generated by `cargo xtask site`. Edit site.toml instead. -->
+<html lang="{lang}">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title}</title>
+{styles}</head>
+<body>
+    <div id="{root}">{app}</div>
+    <script type="module" src="/index.js"></script>
+</body>
+</html>
+"#,
+        lang = escape(&config.lang),
+        title = escape(&title),
+        root = escape(&config.root),
+    )
+}
+
+/// Replaces `{page}` and `{name}` in a title template. Done in one pass, so that a
+/// page title containing `{name}` is kept as it is.
+fn fill_title(template: &str, page: &str, name: &str) -> String {
+    let mut title = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        title.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{page}") {
+            title.push_str(page);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{name}") {
+            title.push_str(name);
+            rest = after;
+        } else {
+            title.push('{');
+            rest = &rest[1..];
+        }
+    }
+    title.push_str(rest);
+    title
+}
+
+/// The script that loads the wasm module and starts the app, hydrating prerendered
+/// markup where there is some, and registers the service worker if there is one.
+fn bootstrap(config: &SiteConfig, module: &str) -> String {
+    let (imports, logger) = match config.log_level {
+        Some(level) => (
+            "init_logger, hydrate, mount_app, set_worker_script, Level",
+            format!("    init_logger(Level.{});\n\n", level.name()),
+        ),
+        None => ("hydrate, mount_app, set_worker_script", String::new()),
+    };
+    let root = &config.root;
+    let service_worker = if config.service_worker.is_some() {
+        format!(
+            r#"
+if ("serviceWorker" in navigator) {{
+    navigator.serviceWorker.register("/{SCRIPT}").catch((error) => {{
+        console.warn("failed to register the service worker:", error);
+    }});
+}}
+"#
+        )
+    } else {
+        String::new()
+    };
+    // Only cross-origin isolated pages can share memory between threads. Anywhere else,
+    // or if the threaded build is missing, the usual build runs on one thread.
+    let (load, threads) = if config.threads {
+        (
+            format!(
+                r#"const {{ default: init, init_thread_pool, {imports} }} = await (self.crossOriginIsolated
+    ? import("./{module}_threads.js").catch(() => import("./{module}.js"))
+    : import("./{module}.js"));
+"#
+            ),
+            "    await init_thread_pool(navigator.hardwareConcurrency);
+",
+        )
+    } else {
+        (
+            format!("import init, {{ {imports} }} from \"./{module}.js\";\n"),
+            "",
+        )
+    };
+    format!(
+        r##"// Generated by `cargo xtask site`. Edit site.toml instead.
+{load}
+async function run() {{
+    await init();
+    set_worker_script("/{WORKER_SCRIPT}");
+{threads}{logger}    // Pages rendered ahead of time already have the app's markup.
+    if (document.getElementById("{root}").hasChildNodes()) {{
+        hydrate("#{root}");
+    }} else {{
+        mount_app("#{root}");
+    }}
+}}
+run();
+{service_worker}"##
+    )
+}
+
+/// The script for the worker pool's workers and the thread pool's threads. Each loads
+/// the module it is sent, sharing the memory it is sent in the threaded build, and then
+/// calls the export named by `start`.
+fn worker(module: &str) -> String {
+    format!(
+        r#"// Generated by `cargo xtask site`. Edit site.toml instead.
+self.onmessage = async (event) => {{
+    self.onmessage = null;
+    const {{ module, memory, threads, start }} = event.data;
+    try {{
+        const bindings = await import(threads ? "./{module}_threads.js" : "./{module}.js");
+        await bindings.default({{ module_or_path: module, memory }});
+        bindings[start]();
+    }} catch (error) {{
+        self.postMessage({{ error: String(error) }});
+    }}
+}};
+"#
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> SiteConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn generate_files(config: &SiteConfig) -> BTreeMap<PathBuf, String> {
+        let files = generate(config, "app", &BTreeMap::new(), |path| {
+            format!("<p>{path}</p>")
+        });
+        (files.unwrap().into_iter())
+            .map(|(path, contents)| (path, String::from_utf8(contents).unwrap()))
+            .collect()
+    }
+
+    /// The committed `site` directory is the golden copy: after changing what is
+    /// generated, run `cargo xtask site` and review the difference.
+    #[test]
+    fn matches_the_committed_site() {
+        let root = project_root();
+        let config = read_config(&root.join("site.toml")).unwrap();
+        let assets = read_assets(&root.join(config.assets.as_ref().unwrap())).unwrap();
+        let files = generate(
+            &config,
+            &module_name().unwrap(),
+            &assets,
+            hello_wasm::render_app,
+        );
+        for (path, contents) in files.unwrap() {
+            let committed = fs::read(root.join("site").join(&path)).unwrap();
+            assert!(
+                committed == contents,
+                "site/{} is out of date; run `cargo xtask site`",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn writes_pages_with_titles_and_styles() {
+        let config = config(
+            r#"
+            name = "Demo"
+            title = "{name}: {page}"
+            styles = ["/a.css"]
+            [[page]]
+            path = "/docs/intro"
+            title = "Intro & more"
+            "#,
+        );
+        let files = generate_files(&config);
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            ["docs/intro/index.html", "index.js", "worker.js"]
+        );
+        assert_eq!(
+            files[Path::new("docs/intro/index.html")],
+            r#"<!DOCTYPE html>
+<!-- Generated by `cargo xtask site`. Edit site.toml instead. -->
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Demo: Intro &amp; more</title>
+    <link rel="stylesheet" href="/a.css">
+</head>
+<body>
+    <div id="app"><p>/docs/intro</p></div>
+    <script type="module" src="/index.js"></script>
+</body>
+</html>
+"#
+        );
+    }
+
+    #[test]
+    fn writes_the_bootstrap() {
+        let files = generate_files(&config("name = \"Demo\"\nlog_level = \"Warn\""));
+        assert_eq!(
+            files[Path::new("index.js")],
+            r##"// Generated by `cargo xtask site`. Edit site.toml instead.
+import init, { init_logger, hydrate, mount_app, set_worker_script, Level } from "./app.js";
+
+async function run() {
+    await init();
+    set_worker_script("/worker.js");
+    init_logger(Level.Warn);
+
+    // Pages rendered ahead of time already have the app's markup.
+    if (document.getElementById("app").hasChildNodes()) {
+        hydrate("#app");
+    } else {
+        mount_app("#app");
+    }
+}
+run();
+"##
+        );
+    }
+
+    #[test]
+    fn writes_the_threaded_bootstrap() {
+        let files = generate_files(&config("name = \"Demo\"\nthreads = true"));
+        let bootstrap = &files[Path::new("index.js")];
+        assert!(bootstrap.contains(
+            r#"const { default: init, init_thread_pool, hydrate, mount_app, set_worker_script } = await (self.crossOriginIsolated
+    ? import("./app_threads.js").catch(() => import("./app.js"))
+    : import("./app.js"));
+"#
+        ));
+        assert!(bootstrap.contains("    await init_thread_pool(navigator.hardwareConcurrency);\n"));
+        assert!(files[Path::new("worker.js")]
+            .contains(r#"await import(threads ? "./app_threads.js" : "./app.js")"#));
+    }
+
+    #[test]
+    fn substitutes_titles_in_one_pass() {
+        assert_eq!(
+            fill_title("{page} | {name}", "{name}", "Site"),
+            "{name} | Site"
+        );
+        assert_eq!(fill_title("{name}{page}{", "a", "b"), "ba{");
+        assert_eq!(fill_title("{other} {page}", "a", "b"), "{other} a");
+    }
+
+    #[test]
+    fn rejects_unknown_log_levels() {
+        assert!(toml::from_str::<SiteConfig>("name = \"Demo\"\nlog_level = \"Verbose\"").is_err());
+        assert!(toml::from_str::<SiteConfig>("name = \"Demo\"\nlog_level = \"debug\"").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_pages_and_roots() {
+        let bad = [
+            "name = \"Demo\"\npage = []",
+            "name = \"Demo\"\nroot = \"a b\"",
+            "name = \"Demo\"\n[[page]]\npath = \"about\"\ntitle = \"About\"",
+            "name = \"Demo\"\n[[page]]\npath = \"/../x\"\ntitle = \"X\"",
+        ];
+        for toml in bad {
+            let files = generate(&config(toml), "app", &BTreeMap::new(), |_| String::new());
+            assert!(files.is_err(), "{toml}");
+        }
+    }
+}
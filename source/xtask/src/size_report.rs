@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+use serde::Serialize;
+
+use crate::project_root;
+use crate::wasm::{self, Module};
+
+#[derive(Args, Debug)]
+pub struct SizeReportArgs {
+    /// The module to report on. Defaults to `site/hello_wasm_bg.wasm`. Functions can
+    /// only be attributed when the module still has its `name` section, so use a
+    /// build that has not been through `cargo xtask release`.
+    pub wasm: Option<PathBuf>,
+
+    /// Compare against another build of the module instead of listing sizes.
+    #[arg(long, value_name = "OLD_WASM")]
+    pub diff: Option<PathBuf>,
+
+    /// Print JSON instead of tables.
+    #[arg(long)]
+    pub json: bool,
+
+    /// How many rows to print per table. JSON output always has every row.
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    file: String,
+    total_bytes: u64,
+    sections: Vec<Entry>,
+    crates: Vec<Entry>,
+    functions: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct DiffEntry {
+    name: String,
+    old_bytes: u64,
+    new_bytes: u64,
+    delta: i64,
+}
+
+#[derive(Serialize)]
+struct DiffReport {
+    old_file: String,
+    new_file: String,
+    old_total_bytes: u64,
+    new_total_bytes: u64,
+    sections: Vec<DiffEntry>,
+    crates: Vec<DiffEntry>,
+    functions: Vec<DiffEntry>,
+}
+
+pub fn run(args: &SizeReportArgs) -> anyhow::Result<()> {
+    let path = args
+        .wasm
+        .clone()
+        .unwrap_or_else(|| project_root().join("site").join("hello_wasm_bg.wasm"));
+    let report = analyze(&path)?;
+
+    match &args.diff {
+        None if args.json => println!("{}", serde_json::to_string_pretty(&report)?),
+        None => print_report(&report, args.top),
+        Some(old_path) => {
+            let old = analyze(old_path)?;
+            let diff = DiffReport {
+                old_file: old.file,
+                new_file: report.file,
+                old_total_bytes: old.total_bytes,
+                new_total_bytes: report.total_bytes,
+                sections: diff_entries(&old.sections, &report.sections),
+                crates: diff_entries(&old.crates, &report.crates),
+                functions: diff_entries(&old.functions, &report.functions),
+            };
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else {
+                print_diff(&diff, args.top);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn analyze(path: &Path) -> anyhow::Result<Report> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let context = || format!("failed to parse {}", path.display());
+
+    let mut sections = HashMap::new();
+    for section in wasm::sections(&bytes).with_context(context)? {
+        let name = match section.name {
+            Some(name) => format!("custom \"{name}\""),
+            None => section_name(section.id).to_owned(),
+        };
+        *sections.entry(name).or_default() += section.raw.len() as u64;
+    }
+
+    let module = Module::parse(&bytes).with_context(context)?;
+    let mut crates = HashMap::new();
+    let mut functions = HashMap::new();
+    for body in &module.functions {
+        let (krate, function) = match module.function_names.get(&body.index) {
+            Some(symbol) => attribute(symbol),
+            None => ("[unnamed]".to_owned(), format!("function[{}]", body.index)),
+        };
+        *crates.entry(krate).or_default() += body.size as u64;
+        *functions.entry(function).or_default() += body.size as u64;
+    }
+    for segment in &module.data {
+        let name = module
+            .data_names
+            .get(&segment.index)
+            .cloned()
+            .unwrap_or_else(|| format!("data[{}]", segment.index));
+        *crates.entry("[data]".to_owned()).or_default() += segment.size as u64;
+        *functions.entry(format!("[data] {name}")).or_default() += segment.size as u64;
+    }
+
+    Ok(Report {
+        file: path.display().to_string(),
+        total_bytes: bytes.len() as u64,
+        sections: sorted(sections),
+        crates: sorted(crates),
+        functions: sorted(functions),
+    })
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "data count",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+/// Returns the crate and demangled name for a symbol from the name section.
+fn attribute(symbol: &str) -> (String, String) {
+    if symbol.starts_with("__wbg") || symbol.starts_with("__wbindgen") {
+        return ("[wasm-bindgen]".to_owned(), symbol.to_owned());
+    }
+    match rustc_demangle::try_demangle(symbol) {
+        Ok(demangled) => {
+            let name = format!("{demangled:#}");
+            (crate_of(&name).unwrap_or("[other]").to_owned(), name)
+        }
+        Err(_) => ("[other]".to_owned(), symbol.to_owned()),
+    }
+}
+
+/// Picks the crate out of a demangled path. Trait impls such as
+/// `<alloc::string::String as core::fmt::Write>::write_str` belong to the type's crate,
+/// unless the type is a primitive or generic, in which case they belong to the trait's.
+fn crate_of(path: &str) -> Option<&str> {
+    let mut path = path.trim_start_matches(['<', '&', '*', '(', '[']);
+    for prefix in ["mut ", "const ", "dyn "] {
+        path = path.strip_prefix(prefix).unwrap_or(path);
+    }
+
+    let head = path.split("::").next()?;
+    if !head.is_empty()
+        && head.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && path.contains("::")
+    {
+        return Some(head);
+    }
+    let (_, rest) = path.split_once(" as ")?;
+    crate_of(rest)
+}
+
+fn sorted(sizes: HashMap<String, u64>) -> Vec<Entry> {
+    let mut entries: Vec<_> = sizes
+        .into_iter()
+        .map(|(name, bytes)| Entry { name, bytes })
+        .collect();
+    entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+fn diff_entries(old: &[Entry], new: &[Entry]) -> Vec<DiffEntry> {
+    let mut sizes: HashMap<&str, (u64, u64)> = HashMap::new();
+    for entry in old {
+        sizes.entry(&entry.name).or_default().0 = entry.bytes;
+    }
+    for entry in new {
+        sizes.entry(&entry.name).or_default().1 = entry.bytes;
+    }
+
+    let mut entries: Vec<_> = sizes
+        .into_iter()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(name, (old_bytes, new_bytes))| DiffEntry {
+            name: name.to_owned(),
+            old_bytes,
+            new_bytes,
+            delta: new_bytes as i64 - old_bytes as i64,
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.delta
+            .unsigned_abs()
+            .cmp(&a.delta.unsigned_abs())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    entries
+}
+
+fn print_report(report: &Report, top: usize) {
+    println!("{}: {} bytes", report.file, report.total_bytes);
+    for (title, entries) in [
+        ("section", &report.sections),
+        ("crate", &report.crates),
+        ("function", &report.functions),
+    ] {
+        println!();
+        println!("{:>10}  {:>6}  {title}", "bytes", "%");
+        for entry in entries.iter().take(top) {
+            let percent = entry.bytes as f64 / report.total_bytes as f64 * 100.0;
+            println!("{:>10}  {percent:>5.1}%  {}", entry.bytes, entry.name);
+        }
+        if entries.len() > top {
+            println!(
+                "{:>10}  {:>6}  ... and {} more",
+                "",
+                "",
+                entries.len() - top
+            );
+        }
+    }
+}
+
+fn print_diff(diff: &DiffReport, top: usize) {
+    println!(
+        "{} -> {}: {} -> {} bytes ({:+})",
+        diff.old_file,
+        diff.new_file,
+        diff.old_total_bytes,
+        diff.new_total_bytes,
+        diff.new_total_bytes as i64 - diff.old_total_bytes as i64
+    );
+    for (title, entries) in [
+        ("section", &diff.sections),
+        ("crate", &diff.crates),
+        ("function", &diff.functions),
+    ] {
+        println!();
+        println!("{:>10}  {:>10}  {:>10}  {title}", "old", "new", "delta");
+        if entries.is_empty() {
+            println!("{:>10}  {:>10}  {:>10}  (no changes)", "", "", "");
+        }
+        for entry in entries.iter().take(top) {
+            println!(
+                "{:>10}  {:>10}  {:>+10}  {}",
+                entry.old_bytes, entry.new_bytes, entry.delta, entry.name
+            );
+        }
+        if entries.len() > top {
+            println!(
+                "{:>10}  {:>10}  {:>10}  ... and {} more",
+                "",
+                "",
+                "",
+                entries.len() - top
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(entries: &[(&str, u64)]) -> Vec<Entry> {
+        entries
+            .iter()
+            .map(|&(name, bytes)| Entry {
+                name: name.to_owned(),
+                bytes,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn attributes_mangled_symbols() {
+        assert_eq!(
+            attribute("_ZN4core3fmt5write17h0123456789abcdefE"),
+            ("core".to_owned(), "core::fmt::write".to_owned())
+        );
+        assert_eq!(
+            attribute("_RNvCs15kBYyAo9fc_7mycrate7example"),
+            ("mycrate".to_owned(), "mycrate::example".to_owned())
+        );
+        assert_eq!(
+            attribute("__wbindgen_malloc"),
+            ("[wasm-bindgen]".to_owned(), "__wbindgen_malloc".to_owned())
+        );
+        assert_eq!(
+            attribute("memcpy"),
+            ("[other]".to_owned(), "memcpy".to_owned())
+        );
+    }
+
+    #[test]
+    fn finds_the_crate_of_demangled_paths() {
+        assert_eq!(crate_of("hello_wasm::vdom::diff"), Some("hello_wasm"));
+        assert_eq!(
+            crate_of("<alloc::string::String as core::fmt::Write>::write_str"),
+            Some("alloc")
+        );
+        assert_eq!(
+            crate_of("<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop"),
+            Some("alloc")
+        );
+        assert_eq!(
+            crate_of(
+                "<&mut serde_json::ser::Serializer<W> as serde::ser::Serializer>::serialize_str"
+            ),
+            Some("serde_json")
+        );
+        // Primitives and generics belong to the trait.
+        assert_eq!(crate_of("<u32 as core::fmt::Display>::fmt"), Some("core"));
+        assert_eq!(
+            crate_of("<T as alloc::string::ToString>::to_string"),
+            Some("alloc")
+        );
+        assert_eq!(crate_of("<[T] as core::fmt::Debug>::fmt"), Some("core"));
+        assert_eq!(crate_of("main"), None);
+    }
+
+    #[test]
+    fn diffs_added_removed_and_changed_entries() {
+        let old = entries(&[("same", 10), ("grew", 100), ("shrank", 50), ("removed", 7)]);
+        let new = entries(&[("same", 10), ("grew", 120), ("shrank", 20), ("added", 5)]);
+        let diff: Vec<_> = diff_entries(&old, &new)
+            .into_iter()
+            .map(|entry| (entry.name, entry.old_bytes, entry.new_bytes, entry.delta))
+            .collect();
+        // Largest change first, unchanged entries left out.
+        assert_eq!(
+            diff,
+            [
+                ("shrank".to_owned(), 50, 20, -30),
+                ("grew".to_owned(), 100, 120, 20),
+                ("removed".to_owned(), 7, 0, -7),
+                ("added".to_owned(), 0, 5, 5),
+            ]
+        );
+        assert!(diff_entries(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,360 @@
+//! Just enough of the wasm binary format to walk a module's sections and find out
+//! what takes up space in it.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+
+const MAGIC: &[u8] = b"\0asm";
+const HEADER_LEN: usize = 8;
+
+pub const CUSTOM_SECTION: u8 = 0;
+pub const IMPORT_SECTION: u8 = 2;
+pub const CODE_SECTION: u8 = 10;
+pub const DATA_SECTION: u8 = 11;
+
+const NAME_SUBSECTION_FUNCTIONS: u8 = 1;
+const NAME_SUBSECTION_DATA: u8 = 9;
+
+/// One top-level section of a module.
+pub struct Section<'a> {
+    pub id: u8,
+    /// The name of a custom section; `None` for every other section.
+    pub name: Option<&'a str>,
+    /// The section contents, after the name for custom sections.
+    pub payload: &'a [u8],
+    /// The whole section including its id and size, for copying it unchanged.
+    pub raw: &'a [u8],
+}
+
+pub fn sections(bytes: &[u8]) -> anyhow::Result<Vec<Section<'_>>> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        bail!("not a wasm module");
+    }
+
+    let mut sections = Vec::new();
+    let mut pos = HEADER_LEN;
+    while pos < bytes.len() {
+        let start = pos;
+        let id = bytes[pos];
+        pos += 1;
+        let size = read_u32(bytes, &mut pos)? as usize;
+        let end = pos
+            .checked_add(size)
+            .filter(|&end| end <= bytes.len())
+            .context("section runs past the end of the module")?;
+
+        let mut payload = &bytes[pos..end];
+        let mut name = None;
+        if id == CUSTOM_SECTION {
+            let mut name_pos = 0;
+            name = Some(read_name(payload, &mut name_pos).context("custom section name")?);
+            payload = &payload[name_pos..];
+        }
+
+        sections.push(Section {
+            id,
+            name,
+            payload,
+            raw: &bytes[start..end],
+        });
+        pos = end;
+    }
+    Ok(sections)
+}
+
+/// Returns a copy of the module without any custom sections (names, producers,
+/// debug info and so on), along with the names of the sections removed.
+pub fn strip_custom_sections(bytes: &[u8]) -> anyhow::Result<(Vec<u8>, Vec<String>)> {
+    let mut output = bytes[..HEADER_LEN].to_vec();
+    let mut removed = Vec::new();
+    for section in sections(bytes)? {
+        if section.id == CUSTOM_SECTION {
+            removed.push(section.name.unwrap_or_default().to_owned());
+        } else {
+            output.extend_from_slice(section.raw);
+        }
+    }
+    Ok((output, removed))
+}
+
+/// A function body in the code section.
+pub struct FunctionBody {
+    /// The function index, counting imported functions first.
+    pub index: u32,
+    /// Bytes taken in the code section, including the body's size prefix.
+    pub size: usize,
+}
+
+/// A segment in the data section.
+pub struct DataSegment {
+    pub index: u32,
+    /// Bytes taken in the data section, including the segment header.
+    pub size: usize,
+}
+
+/// The parts of a module that code size can be attributed to.
+#[derive(Default)]
+pub struct Module {
+    pub functions: Vec<FunctionBody>,
+    pub data: Vec<DataSegment>,
+    pub function_names: HashMap<u32, String>,
+    pub data_names: HashMap<u32, String>,
+}
+
+impl Module {
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Module> {
+        let mut module = Module::default();
+        let mut imported_functions = 0;
+        for section in sections(bytes)? {
+            match (section.id, section.name) {
+                (IMPORT_SECTION, _) => {
+                    imported_functions =
+                        count_imported_functions(section.payload).context("import section")?;
+                }
+                (CODE_SECTION, _) => {
+                    module.functions = function_bodies(section.payload, imported_functions)
+                        .context("code section")?;
+                }
+                (DATA_SECTION, _) => {
+                    module.data = data_segments(section.payload).context("data section")?;
+                }
+                (CUSTOM_SECTION, Some("name")) => {
+                    module.read_names(section.payload).context("name section")?;
+                }
+                _ => {}
+            }
+        }
+        Ok(module)
+    }
+
+    fn read_names(&mut self, payload: &[u8]) -> anyhow::Result<()> {
+        let mut pos = 0;
+        while pos < payload.len() {
+            let id = payload[pos];
+            pos += 1;
+            let size = read_u32(payload, &mut pos)? as usize;
+            let contents = payload
+                .get(pos..pos + size)
+                .context("name subsection runs past the section")?;
+            pos += size;
+
+            let names = match id {
+                NAME_SUBSECTION_FUNCTIONS => &mut self.function_names,
+                NAME_SUBSECTION_DATA => &mut self.data_names,
+                _ => continue,
+            };
+            let mut name_pos = 0;
+            for _ in 0..read_u32(contents, &mut name_pos)? {
+                let index = read_u32(contents, &mut name_pos)?;
+                names.insert(index, read_name(contents, &mut name_pos)?.to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn count_imported_functions(payload: &[u8]) -> anyhow::Result<u32> {
+    let mut pos = 0;
+    let mut functions = 0;
+    for _ in 0..read_u32(payload, &mut pos)? {
+        read_name(payload, &mut pos)?;
+        read_name(payload, &mut pos)?;
+        let kind = read_byte(payload, &mut pos)?;
+        match kind {
+            // Function: type index.
+            0 => {
+                read_u32(payload, &mut pos)?;
+                functions += 1;
+            }
+            // Table: element type, limits.
+            1 => {
+                read_byte(payload, &mut pos)?;
+                skip_limits(payload, &mut pos)?;
+            }
+            // Memory: limits.
+            2 => skip_limits(payload, &mut pos)?,
+            // Global: value type, mutability.
+            3 => pos += 2,
+            // Tag: attribute, type index.
+            4 => {
+                read_byte(payload, &mut pos)?;
+                read_u32(payload, &mut pos)?;
+            }
+            _ => bail!("unknown import kind {kind}"),
+        }
+    }
+    Ok(functions)
+}
+
+fn skip_limits(bytes: &[u8], pos: &mut usize) -> anyhow::Result<()> {
+    let flags = read_byte(bytes, pos)?;
+    read_u64(bytes, pos)?;
+    if flags & 1 != 0 {
+        read_u64(bytes, pos)?;
+    }
+    Ok(())
+}
+
+fn function_bodies(payload: &[u8], first_index: u32) -> anyhow::Result<Vec<FunctionBody>> {
+    let mut pos = 0;
+    let count = read_u32(payload, &mut pos)?;
+    let end = first_index
+        .checked_add(count)
+        .context("too many functions")?;
+    // Every body takes at least a byte, so a count larger than that is caught below
+    // rather than allocated for.
+    let mut bodies = Vec::with_capacity((count as usize).min(payload.len() - pos));
+    for index in first_index..end {
+        let start = pos;
+        let size = read_u32(payload, &mut pos)? as usize;
+        pos = pos
+            .checked_add(size)
+            .filter(|&end| end <= payload.len())
+            .context("function body runs past the section")?;
+        bodies.push(FunctionBody {
+            index,
+            size: pos - start,
+        });
+    }
+    Ok(bodies)
+}
+
+fn data_segments(payload: &[u8]) -> anyhow::Result<Vec<DataSegment>> {
+    let mut pos = 0;
+    let count = read_u32(payload, &mut pos)?;
+    let mut segments = Vec::with_capacity((count as usize).min(payload.len() - pos));
+    for index in 0..count {
+        let start = pos;
+        match read_u32(payload, &mut pos)? {
+            0 => skip_const_expr(payload, &mut pos)?,
+            1 => {}
+            2 => {
+                read_u32(payload, &mut pos)?;
+                skip_const_expr(payload, &mut pos)?;
+            }
+            flags => bail!("unknown data segment flags {flags}"),
+        }
+        let len = read_u32(payload, &mut pos)? as usize;
+        pos = pos
+            .checked_add(len)
+            .filter(|&end| end <= payload.len())
+            .context("data segment runs past the section")?;
+        segments.push(DataSegment {
+            index,
+            size: pos - start,
+        });
+    }
+    Ok(segments)
+}
+
+/// Skips a constant expression such as a data segment offset, up to its `end` opcode.
+fn skip_const_expr(bytes: &[u8], pos: &mut usize) -> anyhow::Result<()> {
+    loop {
+        match read_byte(bytes, pos)? {
+            0x0b => return Ok(()),
+            // i32.const, i64.const
+            0x41 | 0x42 => {
+                read_u64(bytes, pos)?;
+            }
+            // global.get
+            0x23 => {
+                read_u32(bytes, pos)?;
+            }
+            // Extended constant arithmetic has no immediates.
+            0x6a | 0x6b | 0x6c | 0x7c | 0x7d | 0x7e => {}
+            opcode => bail!("unsupported opcode {opcode:#04x} in constant expression"),
+        }
+    }
+}
+
+fn read_byte(bytes: &[u8], pos: &mut usize) -> anyhow::Result<u8> {
+    let byte = *bytes.get(*pos).context("unexpected end of input")?;
+    *pos += 1;
+    Ok(byte)
+}
+
+/// Reads a length-prefixed UTF-8 string at `pos`, advancing past it.
+pub fn read_name<'a>(bytes: &'a [u8], pos: &mut usize) -> anyhow::Result<&'a str> {
+    let len = read_u32(bytes, pos)? as usize;
+    let name = bytes
+        .get(*pos..*pos + len)
+        .context("name runs past the end of input")?;
+    *pos += len;
+    std::str::from_utf8(name).context("name is not UTF-8")
+}
+
+/// Reads an unsigned LEB128 integer at `pos`, advancing past it.
+pub fn read_u32(bytes: &[u8], pos: &mut usize) -> anyhow::Result<u32> {
+    u32::try_from(read_u64(bytes, pos)?).context("LEB128 integer does not fit in 32 bits")
+}
+
+/// Reads an unsigned (or, for skipping, signed) LEB128 integer of up to 64 bits.
+fn read_u64(bytes: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+    let mut result = 0u64;
+    for shift in (0..70).step_by(7) {
+        let byte = read_byte(bytes, pos)?;
+        result |= u64::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    bail!("LEB128 integer is too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with the given sections, each an id and payload.
+    fn module(sections: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        for (id, payload) in sections {
+            bytes.push(*id);
+            bytes.push(payload.len() as u8);
+            bytes.extend_from_slice(payload);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_function_bodies_after_imports() {
+        // One imported function `env.f`, then two bodies of 2 and 3 bytes.
+        let imports: &[u8] = &[1, 3, b'e', b'n', b'v', 1, b'f', 0, 0];
+        let code: &[u8] = &[2, 1, 0x0b, 2, 0x01, 0x0b];
+        let module =
+            Module::parse(&module(&[(IMPORT_SECTION, imports), (CODE_SECTION, code)])).unwrap();
+        let bodies: Vec<_> = (module.functions.iter())
+            .map(|body| (body.index, body.size))
+            .collect();
+        assert_eq!(bodies, [(1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn strips_custom_sections() {
+        let bytes = module(&[(CUSTOM_SECTION, &[4, b'n', b'a', b'm', b'e', 0]), (1, &[0])]);
+        let (stripped, removed) = strip_custom_sections(&bytes).unwrap();
+        assert_eq!(removed, ["name"]);
+        assert_eq!(stripped, module(&[(1, &[0])]));
+    }
+
+    #[test]
+    fn rejects_counts_larger_than_the_payload() {
+        // A body count of u32::MAX with no bodies behind it.
+        let code: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert!(Module::parse(&module(&[(CODE_SECTION, code)])).is_err());
+        assert!(Module::parse(&module(&[(DATA_SECTION, code)])).is_err());
+    }
+
+    #[test]
+    fn rejects_function_indices_that_overflow() {
+        assert!(function_bodies(&[2, 1, 0x0b, 1, 0x0b], u32::MAX).is_err());
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_end() {
+        let code: &[u8] = &[1, 0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert!(Module::parse(&module(&[(CODE_SECTION, code)])).is_err());
+        assert!(sections(&module(&[(1, &[0])])[..9]).is_err());
+    }
+}